@@ -16,6 +16,33 @@ pub enum BlendDstFactor {
     OneMinusSrcAlpha,
 }
 
+#[derive(Copy, Clone)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunc {
+    fn test(self, value: u32, reference: u32) -> bool {
+        match self {
+            CompareFunc::Never => false,
+            CompareFunc::Less => value < reference,
+            CompareFunc::Equal => value == reference,
+            CompareFunc::LessEqual => value <= reference,
+            CompareFunc::Greater => value > reference,
+            CompareFunc::NotEqual => value != reference,
+            CompareFunc::GreaterEqual => value >= reference,
+            CompareFunc::Always => true,
+        }
+    }
+}
+
 pub const TILE_DIM: usize = 16;
 pub const TILE_PIXELS: usize = TILE_DIM * TILE_DIM;
 
@@ -26,6 +53,8 @@ pub const W_FRACT_BITS: u32 = 8;
 pub const W_INVERSE_FRACT_BITS: u32 = 30;
 pub const Z_FRACT_BITS: u32 = 30; // Must be greater than 16
 pub const ST_FRACT_BITS: u32 = 24;
+pub const COLOR_FRACT_BITS: u32 = 24; // Must be greater than 8
+pub const STIPPLE_FRACT_BITS: u32 = 8;
 
 pub struct ColorThrust {
     pub color_buffer: [u32; TILE_PIXELS],
@@ -36,6 +65,12 @@ pub struct ColorThrust {
     pub texture_width_shift: u32,
     pub texture_height_shift: u32,
 
+    // Pixel rectangles (glDrawPixels/glBitmap) bypass coverage, texturing and color interpolation; fragment coverage
+    // and colors are supplied per tile pixel instead
+    pub pixel_rectangle_enable: bool,
+    pub pixel_coverage: [bool; TILE_PIXELS],
+    pub pixel_colors: [u32; TILE_PIXELS],
+
     pub w0_min: i32,
     pub w0_dx: i32,
     pub w0_dy: i32,
@@ -57,14 +92,50 @@ pub struct ColorThrust {
     pub t_min: i32,
     pub t_dx: i32,
     pub t_dy: i32,
+    pub r_min: i32,
+    pub r_dx: i32,
+    pub r_dy: i32,
+    pub g_min: i32,
+    pub g_dx: i32,
+    pub g_dy: i32,
+    pub b_min: i32,
+    pub b_dx: i32,
+    pub b_dy: i32,
+    pub a_min: i32,
+    pub a_dx: i32,
+    pub a_dy: i32,
+    pub secondary_r_min: i32,
+    pub secondary_r_dx: i32,
+    pub secondary_r_dy: i32,
+    pub secondary_g_min: i32,
+    pub secondary_g_dx: i32,
+    pub secondary_g_dy: i32,
+    pub secondary_b_min: i32,
+    pub secondary_b_dx: i32,
+    pub secondary_b_dy: i32,
+    pub stipple_min: i32,
+    pub stipple_dx: i32,
+    pub stipple_dy: i32,
+    // Polygon offset, in depth buffer units
+    pub depth_offset: i32,
+
+    pub alpha_test_enable: bool,
+    pub alpha_test_func: CompareFunc,
+    pub alpha_test_ref: u32,
 
     pub depth_test_enable: bool,
     pub depth_mask_enable: bool,
+    // Packed ARGB write mask; set bits are written
+    pub color_mask: u32,
+
+    pub color_sum_enable: bool,
+
+    pub line_stipple_enable: bool,
+    pub line_stipple_pattern: u16,
+    pub line_stipple_factor: u32,
 
     pub blend_src_factor: BlendSrcFactor,
     pub blend_dst_factor: BlendDstFactor,
-
-    pub color: Vec4,
 }
 
 impl ColorThrust {
@@ -77,6 +148,10 @@ impl ColorThrust {
             texture_width_shift: 0,
             texture_height_shift: 0,
 
+            pixel_rectangle_enable: false,
+            pixel_coverage: [false; TILE_PIXELS],
+            pixel_colors: [0; TILE_PIXELS],
+
             w0_min: 0,
             w0_dx: 0,
             w0_dy: 0,
@@ -98,14 +173,48 @@ impl ColorThrust {
             t_min: 0,
             t_dx: 0,
             t_dy: 0,
+            r_min: 0,
+            r_dx: 0,
+            r_dy: 0,
+            g_min: 0,
+            g_dx: 0,
+            g_dy: 0,
+            b_min: 0,
+            b_dx: 0,
+            b_dy: 0,
+            a_min: 0,
+            a_dx: 0,
+            a_dy: 0,
+            secondary_r_min: 0,
+            secondary_r_dx: 0,
+            secondary_r_dy: 0,
+            secondary_g_min: 0,
+            secondary_g_dx: 0,
+            secondary_g_dy: 0,
+            secondary_b_min: 0,
+            secondary_b_dx: 0,
+            secondary_b_dy: 0,
+            stipple_min: 0,
+            stipple_dx: 0,
+            stipple_dy: 0,
+            depth_offset: 0,
+
+            alpha_test_enable: false,
+            alpha_test_func: CompareFunc::Always,
+            alpha_test_ref: 0,
 
             depth_test_enable: false,
             depth_mask_enable: true,
+            color_mask: 0xffffffff,
+
+            color_sum_enable: false,
+
+            line_stipple_enable: false,
+            line_stipple_pattern: 0xffff,
+            line_stipple_factor: 1,
 
             blend_src_factor: BlendSrcFactor::One,
             blend_dst_factor: BlendDstFactor::Zero,
-
-            color: Vec4::zero(),
         }
     }
 
@@ -117,6 +226,14 @@ impl ColorThrust {
         let mut z_row = self.z_min;
         let mut s_row = self.s_min;
         let mut t_row = self.t_min;
+        let mut r_row = self.r_min;
+        let mut g_row = self.g_min;
+        let mut b_row = self.b_min;
+        let mut a_row = self.a_min;
+        let mut secondary_r_row = self.secondary_r_min;
+        let mut secondary_g_row = self.secondary_g_min;
+        let mut secondary_b_row = self.secondary_b_min;
+        let mut stipple_row = self.stipple_min;
 
         // TODO: Clip to viewport bounds within tile
         for y in 0..TILE_DIM {
@@ -127,100 +244,138 @@ impl ColorThrust {
             let mut z = z_row;
             let mut s = s_row;
             let mut t = t_row;
+            let mut r = r_row;
+            let mut g = g_row;
+            let mut b = b_row;
+            let mut a = a_row;
+            let mut secondary_r = secondary_r_row;
+            let mut secondary_g = secondary_g_row;
+            let mut secondary_b = secondary_b_row;
+            let mut stipple = stipple_row;
 
             for x in 0..TILE_DIM {
-                if (w0 | w1 | w2) >= 0 {
-                    let z = (z >> (Z_FRACT_BITS - 16)) as u16;
-                    let buffer_index = y as usize * TILE_DIM + x as usize;
+                let buffer_index = y as usize * TILE_DIM + x as usize;
+                let is_covered = if self.pixel_rectangle_enable {
+                    self.pixel_coverage[buffer_index]
+                } else {
+                    (w0 | w1 | w2) >= 0 && self.line_stipple_test(stipple)
+                };
+                if is_covered {
+                    // Polygon offset can push depth outside of [0, 1], so clamp it to the depth buffer's range
+                    let z = ((z >> (Z_FRACT_BITS - 16)) + self.depth_offset).max(0).min(0xffff) as u16;
                     let depth_test_result = !self.depth_test_enable || z < self.depth_buffer[buffer_index];
-                    const RESTORED_W_FRACT_BITS: u32 = 8; // Must be less than W_INVERSE_FRACT_BITS and ST_FRACT_BITS
-
-                    fn inverse_approx(x: u32) -> u32 {
-                        let shl = x.leading_zeros() & 31;
-                        let normalized_x = x << shl;
-                        // TODO: Why is 3 the magic number here? Is that dependent on the other constants? Can we determine shr a better way?
-                        let shr = (64 - 2 * (W_INVERSE_FRACT_BITS - RESTORED_W_FRACT_BITS - 3) - shl) & 31;
-
-                        let mut e = !normalized_x; // 2's complement approximation
-                        let mut q = e;
-                        for _ in 0..4 { // TODO: Is this the correct number of steps?
-                            q += ((((q as u64) * (e as u64)) >> 32) as u32);
-                            e = (((e as u64) * (e as u64)) >> 32) as u32;
+                    let mut alpha_test_result = true;
+                    // With every channel masked only depth can change, so texturing and blending are skipped entirely,
+                    // unless the alpha test needs the fragment's alpha
+                    if self.color_mask != 0 || self.alpha_test_enable {
+                        let src_color = if self.pixel_rectangle_enable {
+                            let color = self.pixel_colors[buffer_index];
+                            Vec4::new(((color >> 16) & 0xff) as f32, ((color >> 8) & 0xff) as f32, ((color >> 0) & 0xff) as f32, ((color >> 24) & 0xff) as f32)
+                        } else {
+                            const RESTORED_W_FRACT_BITS: u32 = 8; // Must be less than W_INVERSE_FRACT_BITS and ST_FRACT_BITS
+
+                            fn inverse_approx(x: u32) -> u32 {
+                                let shl = x.leading_zeros() & 31;
+                                let normalized_x = x << shl;
+                                // TODO: Why is 3 the magic number here? Is that dependent on the other constants? Can we determine shr a better way?
+                                let shr = (64 - 2 * (W_INVERSE_FRACT_BITS - RESTORED_W_FRACT_BITS - 3) - shl) & 31;
+
+                                let mut e = !normalized_x; // 2's complement approximation
+                                let mut q = e;
+                                for _ in 0..4 { // TODO: Is this the correct number of steps?
+                                    q += ((((q as u64) * (e as u64)) >> 32) as u32);
+                                    e = (((e as u64) * (e as u64)) >> 32) as u32;
+                                }
+
+                                return (q >> shr) | (1 << (32 - shr));
+                            }
+                            let w_approx = inverse_approx(w_inverse as _) as i32;
+
+                            /*if x == 0 && y == 0 {
+                                /*let one = 1 << W_INVERSE_FRACT_BITS;
+                                let w = (one << W_INVERSE_FRACT_BITS) / (w_inverse as i64);
+                                let w = (w >> (W_INVERSE_FRACT_BITS - RESTORED_W_FRACT_BITS)) as i32;*/
+                                println!("***** w_inverse: 0x{:08x}, w: 0x{:08x}, w_approx: 0x{:08x}, error: {}", w_inverse, w, w_approx, (w_approx as i32) - (w as i32));
+                            }*/
+
+                            let w = w_approx;
+
+                            let s = ((s >> RESTORED_W_FRACT_BITS) * w) as u32;
+                            let t = ((t >> RESTORED_W_FRACT_BITS) * w) as u32;
+                            let s_floor = s >> ST_FRACT_BITS;
+                            let t_floor = t >> ST_FRACT_BITS;
+                            const ST_FILTER_BITS: u32 = 4; // Must be less than ST_FRACT_BITS
+                            let s_fract = (s >> (ST_FRACT_BITS - ST_FILTER_BITS)) & ((1 << ST_FILTER_BITS) - 1);
+                            let t_fract = (t >> (ST_FRACT_BITS - ST_FILTER_BITS)) & ((1 << ST_FILTER_BITS) - 1);
+                            let one_minus_s_fract = (1 << ST_FILTER_BITS) - s_fract;
+                            let one_minus_t_fract = (1 << ST_FILTER_BITS) - t_fract;
+                            let texel_color0 = self.fetch_texel(s_floor + 0, t_floor + 0);
+                            let texel_color1 = self.fetch_texel(s_floor + 1, t_floor + 0);
+                            let texel_color2 = self.fetch_texel(s_floor + 0, t_floor + 1);
+                            let texel_color3 = self.fetch_texel(s_floor + 1, t_floor + 1);
+                            let a_red = (texel_color0.0 * one_minus_s_fract + texel_color1.0 * s_fract) >> ST_FILTER_BITS;
+                            let a_green = (texel_color0.1 * one_minus_s_fract + texel_color1.1 * s_fract) >> ST_FILTER_BITS;
+                            let a_blue = (texel_color0.2 * one_minus_s_fract + texel_color1.2 * s_fract) >> ST_FILTER_BITS;
+                            let a_alpha = (texel_color0.3 * one_minus_s_fract + texel_color1.3 * s_fract) >> ST_FILTER_BITS;
+                            let b_red = (texel_color2.0 * one_minus_s_fract + texel_color3.0 * s_fract) >> ST_FILTER_BITS;
+                            let b_green = (texel_color2.1 * one_minus_s_fract + texel_color3.1 * s_fract) >> ST_FILTER_BITS;
+                            let b_blue = (texel_color2.2 * one_minus_s_fract + texel_color3.2 * s_fract) >> ST_FILTER_BITS;
+                            let b_alpha = (texel_color2.3 * one_minus_s_fract + texel_color3.3 * s_fract) >> ST_FILTER_BITS;
+                            let texel_red = (a_red * one_minus_t_fract + b_red * t_fract) >> ST_FILTER_BITS;
+                            let texel_green = (a_green * one_minus_t_fract + b_green * t_fract) >> ST_FILTER_BITS;
+                            let texel_blue = (a_blue * one_minus_t_fract + b_blue * t_fract) >> ST_FILTER_BITS;
+                            let texel_alpha = (a_alpha * one_minus_t_fract + b_alpha * t_fract) >> ST_FILTER_BITS;
+                            let restore_color = |c: i32| (((c >> RESTORED_W_FRACT_BITS) * w) >> (COLOR_FRACT_BITS - 8)).max(0).min(255);
+                            let vertex_color = Vec4::new(restore_color(r) as f32, restore_color(g) as f32, restore_color(b) as f32, restore_color(a) as f32);
+                            let textured_color = vertex_color * Vec4::new(texel_red as f32, texel_green as f32, texel_blue as f32, texel_alpha as f32) / 256.0;
+                            // Color sum happens after texturing and before fog (which isn't implemented yet)
+                            if self.color_sum_enable {
+                                let secondary_color = Vec4::new(restore_color(secondary_r) as f32, restore_color(secondary_g) as f32, restore_color(secondary_b) as f32, 0.0);
+                                (textured_color + secondary_color).min(Vec4::splat(255.0))
+                            } else {
+                                textured_color
+                            }
+                        };
+
+                        // Fragments failing the alpha test are discarded before the depth and color writes
+                        if self.alpha_test_enable {
+                            alpha_test_result = self.alpha_test_func.test(src_color.w().floor() as u32, self.alpha_test_ref);
                         }
 
-                        return (q >> shr) | (1 << (32 - shr));
-                    }
-                    let w_approx = inverse_approx(w_inverse as _) as i32;
-
-                    /*if x == 0 && y == 0 {
-                        /*let one = 1 << W_INVERSE_FRACT_BITS;
-                        let w = (one << W_INVERSE_FRACT_BITS) / (w_inverse as i64);
-                        let w = (w >> (W_INVERSE_FRACT_BITS - RESTORED_W_FRACT_BITS)) as i32;*/
-                        println!("***** w_inverse: 0x{:08x}, w: 0x{:08x}, w_approx: 0x{:08x}, error: {}", w_inverse, w, w_approx, (w_approx as i32) - (w as i32));
-                    }*/
-
-                    let w = w_approx;
-
-                    let s = ((s >> RESTORED_W_FRACT_BITS) * w) as u32;
-                    let t = ((t >> RESTORED_W_FRACT_BITS) * w) as u32;
-                    let s_floor = s >> ST_FRACT_BITS;
-                    let t_floor = t >> ST_FRACT_BITS;
-                    const ST_FILTER_BITS: u32 = 4; // Must be less than ST_FRACT_BITS
-                    let s_fract = (s >> (ST_FRACT_BITS - ST_FILTER_BITS)) & ((1 << ST_FILTER_BITS) - 1);
-                    let t_fract = (t >> (ST_FRACT_BITS - ST_FILTER_BITS)) & ((1 << ST_FILTER_BITS) - 1);
-                    let one_minus_s_fract = (1 << ST_FILTER_BITS) - s_fract;
-                    let one_minus_t_fract = (1 << ST_FILTER_BITS) - t_fract;
-                    let texel_color0 = self.fetch_texel(s_floor + 0, t_floor + 0);
-                    let texel_color1 = self.fetch_texel(s_floor + 1, t_floor + 0);
-                    let texel_color2 = self.fetch_texel(s_floor + 0, t_floor + 1);
-                    let texel_color3 = self.fetch_texel(s_floor + 1, t_floor + 1);
-                    let a_red = (texel_color0.0 * one_minus_s_fract + texel_color1.0 * s_fract) >> ST_FILTER_BITS;
-                    let a_green = (texel_color0.1 * one_minus_s_fract + texel_color1.1 * s_fract) >> ST_FILTER_BITS;
-                    let a_blue = (texel_color0.2 * one_minus_s_fract + texel_color1.2 * s_fract) >> ST_FILTER_BITS;
-                    let a_alpha = (texel_color0.3 * one_minus_s_fract + texel_color1.3 * s_fract) >> ST_FILTER_BITS;
-                    let b_red = (texel_color2.0 * one_minus_s_fract + texel_color3.0 * s_fract) >> ST_FILTER_BITS;
-                    let b_green = (texel_color2.1 * one_minus_s_fract + texel_color3.1 * s_fract) >> ST_FILTER_BITS;
-                    let b_blue = (texel_color2.2 * one_minus_s_fract + texel_color3.2 * s_fract) >> ST_FILTER_BITS;
-                    let b_alpha = (texel_color2.3 * one_minus_s_fract + texel_color3.3 * s_fract) >> ST_FILTER_BITS;
-                    let texel_red = (a_red * one_minus_t_fract + b_red * t_fract) >> ST_FILTER_BITS;
-                    let texel_green = (a_green * one_minus_t_fract + b_green * t_fract) >> ST_FILTER_BITS;
-                    let texel_blue = (a_blue * one_minus_t_fract + b_blue * t_fract) >> ST_FILTER_BITS;
-                    let texel_alpha = (a_alpha * one_minus_t_fract + b_alpha * t_fract) >> ST_FILTER_BITS;
-                    // TODO: Use properly interpolated vertex color
-                    let src_color = self.color * 255.0 * Vec4::new(texel_red as f32, texel_green as f32, texel_blue as f32, texel_alpha as f32) / 256.0;
-
-                    let src_scale_factors = match self.blend_src_factor {
-                        BlendSrcFactor::Zero => Vec4::zero(),
-                        BlendSrcFactor::One => Vec4::splat(255.0),
-                        BlendSrcFactor::SrcColor => src_color,
-                        BlendSrcFactor::SrcAlpha => Vec4::splat(src_color.w()),
-                    };
-
-                    let dst_color = self.color_buffer[buffer_index];
-                    let dst_red = (dst_color >> 16) & 0xff;
-                    let dst_green = (dst_color >> 8) & 0xff;
-                    let dst_blue = (dst_color >> 0) & 0xff;
-                    let dst_alpha = (dst_color >> 24) & 0xff;
-                    let dst_color = Vec4::new(dst_red as f32, dst_green as f32, dst_blue as f32, dst_alpha as f32);
-                    let dst_scale_factors = match self.blend_dst_factor {
-                        BlendDstFactor::Zero => Vec4::zero(),
-                        BlendDstFactor::One => Vec4::splat(255.0),
-                        BlendDstFactor::SrcAlpha => Vec4::splat(src_color.w()),
-                        BlendDstFactor::OneMinusSrcAlpha => Vec4::splat(255.0 - src_color.w()),
-                    };
-
-                    let color = (src_color * src_scale_factors + dst_color * dst_scale_factors) / 256.0;
-
-                    let color = color.min(Vec4::splat(255.0));
-                    let color_red = color.x().floor() as u32;
-                    let color_green = color.y().floor() as u32;
-                    let color_blue = color.z().floor() as u32;
-                    let color_alpha = color.w().floor() as u32;
-                    if depth_test_result {
-                        self.color_buffer[buffer_index] = (color_alpha << 24) | (color_red << 16) | (color_green << 8) | (color_blue << 0);
+                        let src_scale_factors = match self.blend_src_factor {
+                            BlendSrcFactor::Zero => Vec4::zero(),
+                            BlendSrcFactor::One => Vec4::splat(255.0),
+                            BlendSrcFactor::SrcColor => src_color,
+                            BlendSrcFactor::SrcAlpha => Vec4::splat(src_color.w()),
+                        };
+
+                        let dst_color = self.color_buffer[buffer_index];
+                        let dst_red = (dst_color >> 16) & 0xff;
+                        let dst_green = (dst_color >> 8) & 0xff;
+                        let dst_blue = (dst_color >> 0) & 0xff;
+                        let dst_alpha = (dst_color >> 24) & 0xff;
+                        let dst_color = Vec4::new(dst_red as f32, dst_green as f32, dst_blue as f32, dst_alpha as f32);
+                        let dst_scale_factors = match self.blend_dst_factor {
+                            BlendDstFactor::Zero => Vec4::zero(),
+                            BlendDstFactor::One => Vec4::splat(255.0),
+                            BlendDstFactor::SrcAlpha => Vec4::splat(src_color.w()),
+                            BlendDstFactor::OneMinusSrcAlpha => Vec4::splat(255.0 - src_color.w()),
+                        };
+
+                        let color = (src_color * src_scale_factors + dst_color * dst_scale_factors) / 256.0;
+
+                        let color = color.min(Vec4::splat(255.0));
+                        let color_red = color.x().floor() as u32;
+                        let color_green = color.y().floor() as u32;
+                        let color_blue = color.z().floor() as u32;
+                        let color_alpha = color.w().floor() as u32;
+                        if alpha_test_result && depth_test_result {
+                            let color = (color_alpha << 24) | (color_red << 16) | (color_green << 8) | (color_blue << 0);
+                            self.color_buffer[buffer_index] = (color & self.color_mask) | (self.color_buffer[buffer_index] & !self.color_mask);
+                        }
                     }
 
-                    self.depth_buffer[buffer_index] = if depth_test_result && self.depth_mask_enable {
+                    self.depth_buffer[buffer_index] = if alpha_test_result && depth_test_result && self.depth_mask_enable {
                         z
                     } else {
                         self.depth_buffer[buffer_index]
@@ -234,6 +389,14 @@ impl ColorThrust {
                 z += self.z_dx;
                 s += self.s_dx;
                 t += self.t_dx;
+                r += self.r_dx;
+                g += self.g_dx;
+                b += self.b_dx;
+                a += self.a_dx;
+                secondary_r += self.secondary_r_dx;
+                secondary_g += self.secondary_g_dx;
+                secondary_b += self.secondary_b_dx;
+                stipple += self.stipple_dx;
             }
 
             w0_row += self.w0_dy;
@@ -243,7 +406,24 @@ impl ColorThrust {
             z_row += self.z_dy;
             s_row += self.s_dy;
             t_row += self.t_dy;
+            r_row += self.r_dy;
+            g_row += self.g_dy;
+            b_row += self.b_dy;
+            a_row += self.a_dy;
+            secondary_r_row += self.secondary_r_dy;
+            secondary_g_row += self.secondary_g_dy;
+            secondary_b_row += self.secondary_b_dy;
+            stipple_row += self.stipple_dy;
+        }
+    }
+
+    fn line_stipple_test(&self, stipple: i32) -> bool {
+        if !self.line_stipple_enable {
+            return true;
         }
+        let counter = (stipple.max(0) >> STIPPLE_FRACT_BITS) as u32;
+        let bit = (counter / self.line_stipple_factor) & 15;
+        ((self.line_stipple_pattern >> bit) & 1) != 0
     }
 
     fn fetch_texel(&self, s: u32, t: u32) -> (u32, u32, u32, u32) {
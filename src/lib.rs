@@ -6,6 +6,7 @@ mod matrix;
 mod vec2;
 mod vec3;
 mod vec4;
+mod vertex_cache;
 
 use minifb::{Scale, Window, WindowOptions};
 
@@ -14,9 +15,14 @@ use matrix::*;
 use vec2::*;
 use vec3::*;
 use vec4::*;
+use vertex_cache::*;
 
 use std::cell::RefCell;
-use std::ffi::{CStr, c_void};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::ffi::{CStr, CString, c_void};
+use std::mem;
 use std::ptr;
 use std::rc::Rc;
 use std::slice;
@@ -25,6 +31,9 @@ use std::slice;
 const WIDTH: usize = 320;
 const HEIGHT: usize = 240;
 
+// Framebuffer pixels per app pixel, matching the viewport auto-scaling (see Command::Viewport)
+const FRAMEBUFFER_SCALE: f32 = 0.5;
+
 type LPVOID = *mut c_void;
 
 type BOOL = i32;
@@ -62,18 +71,27 @@ extern "stdcall" {
 }
 
 type GLboolean = i32;
+type GLbyte = i8;
 type GLubyte = u8;
 type GLshort = i16;
+type GLushort = u16;
 type GLenum = u32;
 type GLint = i32;
 type GLuint = u32;
 type GLsizei = u32;
 type GLfloat = f32;
+type GLclampf = f32;
 type GLbitfield = u32;
 type GLdouble = f64;
 type GLvoid = c_void;
 
 const GL_NO_ERROR: GLenum = 0;
+const GL_INVALID_ENUM: GLenum = 0x0500;
+const GL_INVALID_VALUE: GLenum = 0x0501;
+const GL_INVALID_OPERATION: GLenum = 0x0502;
+const GL_STACK_OVERFLOW: GLenum = 0x0503;
+const GL_STACK_UNDERFLOW: GLenum = 0x0504;
+const GL_OUT_OF_MEMORY: GLenum = 0x0505;
 
 const GL_ZERO: GLenum = 0;
 const GL_ONE: GLenum = 1;
@@ -81,19 +99,108 @@ const GL_ONE: GLenum = 1;
 const GL_FALSE: GLboolean = 0;
 const GL_TRUE: GLboolean = 1;
 
+const GL_POINTS: GLenum = 0x0000;
+const GL_LINES: GLenum = 0x0001;
+const GL_LINE_LOOP: GLenum = 0x0002;
+const GL_LINE_STRIP: GLenum = 0x0003;
 const GL_TRIANGLES: GLenum = 0x0004;
+const GL_TRIANGLE_STRIP: GLenum = 0x0005;
+const GL_TRIANGLE_FAN: GLenum = 0x0006;
 const GL_QUADS: GLenum = 0x0007;
-
+const GL_QUAD_STRIP: GLenum = 0x0008;
+const GL_POLYGON: GLenum = 0x0009;
+
+const GL_CURRENT_BIT: GLbitfield = 0x00000001;
+const GL_POINT_BIT: GLbitfield = 0x00000002;
+const GL_LINE_BIT: GLbitfield = 0x00000004;
+const GL_POLYGON_BIT: GLbitfield = 0x00000008;
+const GL_PIXEL_MODE_BIT: GLbitfield = 0x00000020;
+const GL_LIGHTING_BIT: GLbitfield = 0x00000040;
+const GL_FOG_BIT: GLbitfield = 0x00000080;
 const GL_DEPTH_BUFFER_BIT: GLbitfield = 0x00000100;
+const GL_VIEWPORT_BIT: GLbitfield = 0x00000800;
+const GL_TRANSFORM_BIT: GLbitfield = 0x00001000;
+const GL_ENABLE_BIT: GLbitfield = 0x00002000;
 const GL_COLOR_BUFFER_BIT: GLbitfield = 0x00004000;
+const GL_EVAL_BIT: GLbitfield = 0x00010000;
+const GL_LIST_BIT: GLbitfield = 0x00020000;
+const GL_TEXTURE_BIT: GLbitfield = 0x00040000;
+
+const GL_CLIENT_PIXEL_STORE_BIT: GLbitfield = 0x00000001;
+const GL_CLIENT_VERTEX_ARRAY_BIT: GLbitfield = 0x00000002;
+
+const GL_NEVER: GLenum = 0x0200;
+const GL_LESS: GLenum = 0x0201;
+const GL_EQUAL: GLenum = 0x0202;
+const GL_LEQUAL: GLenum = 0x0203;
+const GL_GREATER: GLenum = 0x0204;
+const GL_NOTEQUAL: GLenum = 0x0205;
+const GL_GEQUAL: GLenum = 0x0206;
+const GL_ALWAYS: GLenum = 0x0207;
 
 const GL_SRC_COLOR: GLenum = 0x0300;
 const GL_SRC_ALPHA: GLenum = 0x0302;
 const GL_ONE_MINUS_SRC_ALPHA: GLenum = 0x0303;
 
+const GL_FRONT: GLenum = 0x0404;
+const GL_BACK: GLenum = 0x0405;
+const GL_FRONT_AND_BACK: GLenum = 0x0408;
+
+const GL_CW: GLenum = 0x0900;
+const GL_CCW: GLenum = 0x0901;
+
+const GL_CURRENT_COLOR: GLenum = 0x0b00;
+const GL_CURRENT_NORMAL: GLenum = 0x0b02;
+const GL_CURRENT_TEXTURE_COORDS: GLenum = 0x0b03;
+const GL_CURRENT_RASTER_COLOR: GLenum = 0x0b04;
+const GL_CURRENT_RASTER_POSITION: GLenum = 0x0b07;
+const GL_CURRENT_RASTER_POSITION_VALID: GLenum = 0x0b08;
+const GL_POINT_SIZE: GLenum = 0x0b11;
+const GL_LINE_WIDTH: GLenum = 0x0b21;
+const GL_LINE_STIPPLE: GLenum = 0x0b24;
+const GL_LINE_STIPPLE_PATTERN: GLenum = 0x0b25;
+const GL_LINE_STIPPLE_REPEAT: GLenum = 0x0b26;
+const GL_LIST_MODE: GLenum = 0x0b30;
+const GL_LIST_BASE: GLenum = 0x0b32;
+const GL_LIST_INDEX: GLenum = 0x0b33;
+const GL_POLYGON_MODE: GLenum = 0x0b40;
+const GL_EDGE_FLAG: GLenum = 0x0b43;
+const GL_FRONT_FACE: GLenum = 0x0b46;
+const GL_LIGHTING: GLenum = 0x0b50;
+const GL_LIGHT_MODEL_LOCAL_VIEWER: GLenum = 0x0b51;
+const GL_LIGHT_MODEL_TWO_SIDE: GLenum = 0x0b52;
+const GL_LIGHT_MODEL_AMBIENT: GLenum = 0x0b53;
+const GL_SHADE_MODEL: GLenum = 0x0b54;
+
+const GL_DEPTH_RANGE: GLenum = 0x0b70;
 const GL_DEPTH_TEST: GLenum = 0x0b71;
+const GL_DEPTH_WRITEMASK: GLenum = 0x0b72;
+const GL_DEPTH_CLEAR_VALUE: GLenum = 0x0b73;
+const GL_DEPTH_FUNC: GLenum = 0x0b74;
+const GL_MATRIX_MODE: GLenum = 0x0ba0;
+const GL_VIEWPORT: GLenum = 0x0ba2;
+const GL_MODELVIEW_STACK_DEPTH: GLenum = 0x0ba3;
+const GL_PROJECTION_STACK_DEPTH: GLenum = 0x0ba4;
+const GL_TEXTURE_STACK_DEPTH: GLenum = 0x0ba5;
+const GL_MODELVIEW_MATRIX: GLenum = 0x0ba6;
+const GL_PROJECTION_MATRIX: GLenum = 0x0ba7;
+const GL_TEXTURE_MATRIX: GLenum = 0x0ba8;
+const GL_ATTRIB_STACK_DEPTH: GLenum = 0x0bb0;
+const GL_CLIENT_ATTRIB_STACK_DEPTH: GLenum = 0x0bb1;
+
+const GL_ALPHA_TEST: GLenum = 0x0bc0;
+const GL_ALPHA_TEST_FUNC: GLenum = 0x0bc1;
+const GL_ALPHA_TEST_REF: GLenum = 0x0bc2;
+
+const GL_BLEND_DST: GLenum = 0x0be0;
+const GL_BLEND_SRC: GLenum = 0x0be1;
 const GL_BLEND: GLenum = 0x0be2;
 
+const GL_COLOR_CLEAR_VALUE: GLenum = 0x0c22;
+const GL_COLOR_WRITEMASK: GLenum = 0x0c23;
+const GL_RGBA_MODE: GLenum = 0x0c31;
+const GL_DOUBLEBUFFER: GLenum = 0x0c32;
+
 const GL_UNPACK_SWAP_BYTES: GLenum = 0x0cf0;
 const GL_UNPACK_LSB_FIRST: GLenum = 0x0cf1;
 const GL_UNPACK_ROW_LENGTH: GLenum = 0x0cf2;
@@ -106,24 +213,95 @@ const GL_PACK_ROW_LENGTH: GLenum = 0x0d02;
 const GL_PACK_SKIP_ROWS: GLenum = 0x0d03;
 const GL_PACK_SKIP_PIXELS: GLenum = 0x0d04;
 const GL_PACK_ALIGNMENT: GLenum = 0x0d05;
+const GL_ZOOM_X: GLenum = 0x0d16;
+const GL_ZOOM_Y: GLenum = 0x0d17;
 
+const GL_MAX_EVAL_ORDER: GLenum = 0x0d30;
+const GL_MAX_LIGHTS: GLenum = 0x0d31;
 const GL_MAX_TEXTURE_SIZE: GLenum = 0xd33;
+const GL_MAX_ATTRIB_STACK_DEPTH: GLenum = 0x0d35;
+const GL_MAX_MODELVIEW_STACK_DEPTH: GLenum = 0x0d36;
+const GL_MAX_PROJECTION_STACK_DEPTH: GLenum = 0x0d38;
+const GL_MAX_TEXTURE_STACK_DEPTH: GLenum = 0x0d39;
+const GL_MAX_VIEWPORT_DIMS: GLenum = 0x0d3a;
+const GL_MAX_CLIENT_ATTRIB_STACK_DEPTH: GLenum = 0x0d3b;
+const GL_RED_BITS: GLenum = 0x0d52;
+const GL_GREEN_BITS: GLenum = 0x0d53;
+const GL_BLUE_BITS: GLenum = 0x0d54;
+const GL_ALPHA_BITS: GLenum = 0x0d55;
+const GL_DEPTH_BITS: GLenum = 0x0d56;
+const GL_STENCIL_BITS: GLenum = 0x0d57;
+
+const GL_AUTO_NORMAL: GLenum = 0x0d80;
+const GL_MAP1_COLOR_4: GLenum = 0x0d90;
+const GL_MAP2_COLOR_4: GLenum = 0x0db0;
+const GL_MAP1_GRID_DOMAIN: GLenum = 0x0dd0;
+const GL_MAP1_GRID_SEGMENTS: GLenum = 0x0dd1;
+const GL_MAP2_GRID_DOMAIN: GLenum = 0x0dd2;
+const GL_MAP2_GRID_SEGMENTS: GLenum = 0x0dd3;
 
 const GL_TEXTURE_2D: GLenum = 0x0de1;
 
+const GL_POINT: GLenum = 0x1b00;
+const GL_LINE: GLenum = 0x1b01;
+const GL_FILL: GLenum = 0x1b02;
+
+const GL_FLAT: GLenum = 0x1d00;
+const GL_SMOOTH: GLenum = 0x1d01;
+
+const GL_REPLACE: GLenum = 0x1e01;
+
+const GL_VENDOR: GLenum = 0x1f00;
+const GL_RENDERER: GLenum = 0x1f01;
+const GL_VERSION: GLenum = 0x1f02;
+const GL_EXTENSIONS: GLenum = 0x1f03;
+
+const GL_AMBIENT: GLenum = 0x1200;
+const GL_DIFFUSE: GLenum = 0x1201;
+const GL_SPECULAR: GLenum = 0x1202;
+const GL_POSITION: GLenum = 0x1203;
+const GL_SPOT_DIRECTION: GLenum = 0x1204;
+const GL_SPOT_EXPONENT: GLenum = 0x1205;
+const GL_SPOT_CUTOFF: GLenum = 0x1206;
+const GL_CONSTANT_ATTENUATION: GLenum = 0x1207;
+const GL_LINEAR_ATTENUATION: GLenum = 0x1208;
+const GL_QUADRATIC_ATTENUATION: GLenum = 0x1209;
+
 const GL_COMPILE: GLenum = 0x1300;
 const GL_COMPILE_AND_EXECUTE: GLenum = 0x1301;
 
+const GL_BYTE: GLenum = 0x1400;
 const GL_UNSIGNED_BYTE: GLenum = 0x1401;
+const GL_SHORT: GLenum = 0x1402;
 const GL_UNSIGNED_SHORT: GLenum = 0x1403;
+const GL_INT: GLenum = 0x1404;
+const GL_UNSIGNED_INT: GLenum = 0x1405;
 const GL_FLOAT: GLenum = 0x1406;
+const GL_2_BYTES: GLenum = 0x1407;
+const GL_3_BYTES: GLenum = 0x1408;
+const GL_4_BYTES: GLenum = 0x1409;
+const GL_DOUBLE: GLenum = 0x140a;
+
+const GL_EMISSION: GLenum = 0x1600;
+const GL_SHININESS: GLenum = 0x1601;
+const GL_AMBIENT_AND_DIFFUSE: GLenum = 0x1602;
 
 const GL_MODELVIEW: GLenum = 0x1700;
 const GL_PROJECTION: GLenum = 0x1701;
+const GL_TEXTURE: GLenum = 0x1702;
 
+const GL_DEPTH_COMPONENT: GLenum = 0x1902;
 const GL_RGB: GLenum = 0x1907;
 const GL_RGBA: GLenum = 0x1908;
 
+const GL_MODULATE: GLenum = 0x2100;
+const GL_DECAL: GLenum = 0x2101;
+
+const GL_TEXTURE_ENV_MODE: GLenum = 0x2200;
+const GL_TEXTURE_ENV_COLOR: GLenum = 0x2201;
+
+const GL_TEXTURE_ENV: GLenum = 0x2300;
+
 const GL_NEAREST: GLint = 0x2600;
 const GL_LINEAR: GLint = 0x2601;
 const GL_LINEAR_MIPMAP_NEAREST: GLint = 0x2701;
@@ -135,27 +313,301 @@ const GL_TEXTURE_WRAP_S: GLenum = 0x2802;
 const GL_TEXTURE_WRAP_T: GLenum = 0x2803;
 
 const GL_CLAMP: GLint = 0x2900;
+const GL_REPEAT: GLint = 0x2901;
+
+const GL_POLYGON_OFFSET_UNITS: GLenum = 0x2a00;
+const GL_POLYGON_OFFSET_POINT: GLenum = 0x2a01;
+const GL_POLYGON_OFFSET_LINE: GLenum = 0x2a02;
+
+const GL_V2F: GLenum = 0x2a20;
+const GL_V3F: GLenum = 0x2a21;
+const GL_C4UB_V2F: GLenum = 0x2a22;
+const GL_C4UB_V3F: GLenum = 0x2a23;
+const GL_C3F_V3F: GLenum = 0x2a24;
+const GL_N3F_V3F: GLenum = 0x2a25;
+const GL_C4F_N3F_V3F: GLenum = 0x2a26;
+const GL_T2F_V3F: GLenum = 0x2a27;
+const GL_T4F_V4F: GLenum = 0x2a28;
+const GL_T2F_C4UB_V3F: GLenum = 0x2a29;
+const GL_T2F_C3F_V3F: GLenum = 0x2a2a;
+const GL_T2F_N3F_V3F: GLenum = 0x2a2b;
+const GL_T2F_C4F_N3F_V3F: GLenum = 0x2a2c;
+const GL_T4F_C4F_N3F_V4F: GLenum = 0x2a2d;
+
+const GL_LIGHT0: GLenum = 0x4000;
+
+const GL_POLYGON_OFFSET_FILL: GLenum = 0x8037;
+const GL_POLYGON_OFFSET_FACTOR: GLenum = 0x8038;
+
+const GL_TEXTURE_PRIORITY: GLenum = 0x8066;
+const GL_TEXTURE_RESIDENT: GLenum = 0x8067;
+const GL_TEXTURE_BINDING_2D: GLenum = 0x8069;
+
+const GL_BGRA_EXT: GLenum = 0x80e1;
 
 const GL_VERTEX_ARRAY: GLenum = 0x8074;
 const GL_NORMAL_ARRAY: GLenum = 0x8075;
-
+const GL_COLOR_ARRAY: GLenum = 0x8076;
+const GL_TEXTURE_COORD_ARRAY: GLenum = 0x8078;
+const GL_EDGE_FLAG_ARRAY: GLenum = 0x8079;
+const GL_VERTEX_ARRAY_SIZE: GLenum = 0x807a;
+const GL_VERTEX_ARRAY_TYPE: GLenum = 0x807b;
+const GL_VERTEX_ARRAY_STRIDE: GLenum = 0x807c;
+const GL_NORMAL_ARRAY_TYPE: GLenum = 0x807e;
+const GL_NORMAL_ARRAY_STRIDE: GLenum = 0x807f;
+const GL_COLOR_ARRAY_SIZE: GLenum = 0x8081;
+const GL_COLOR_ARRAY_TYPE: GLenum = 0x8082;
+const GL_COLOR_ARRAY_STRIDE: GLenum = 0x8083;
+const GL_TEXTURE_COORD_ARRAY_SIZE: GLenum = 0x8088;
+const GL_TEXTURE_COORD_ARRAY_TYPE: GLenum = 0x8089;
+const GL_TEXTURE_COORD_ARRAY_STRIDE: GLenum = 0x808a;
+const GL_EDGE_FLAG_ARRAY_STRIDE: GLenum = 0x808c;
+
+const GL_ARRAY_ELEMENT_LOCK_FIRST_EXT: GLenum = 0x81a8;
+const GL_ARRAY_ELEMENT_LOCK_COUNT_EXT: GLenum = 0x81a9;
+
+const GL_LIGHT_MODEL_COLOR_CONTROL: GLenum = 0x81f8;
+const GL_SINGLE_COLOR: GLenum = 0x81f9;
+const GL_SEPARATE_SPECULAR_COLOR: GLenum = 0x81fa;
+
+const GL_COLOR_SUM_EXT: GLenum = 0x8458;
+const GL_CURRENT_SECONDARY_COLOR_EXT: GLenum = 0x8459;
+const GL_SECONDARY_COLOR_ARRAY_SIZE_EXT: GLenum = 0x845a;
+const GL_SECONDARY_COLOR_ARRAY_TYPE_EXT: GLenum = 0x845b;
+const GL_SECONDARY_COLOR_ARRAY_STRIDE_EXT: GLenum = 0x845c;
+const GL_SECONDARY_COLOR_ARRAY_EXT: GLenum = 0x845e;
+
+const GL_TEXTURE0_ARB: GLenum = 0x84c0;
+const GL_ACTIVE_TEXTURE_ARB: GLenum = 0x84e0;
+const GL_CLIENT_ACTIVE_TEXTURE_ARB: GLenum = 0x84e1;
+const GL_MAX_TEXTURE_UNITS_ARB: GLenum = 0x84e2;
+const GL_TRANSPOSE_MODELVIEW_MATRIX_ARB: GLenum = 0x84e3;
+const GL_TRANSPOSE_PROJECTION_MATRIX_ARB: GLenum = 0x84e4;
+const GL_TRANSPOSE_TEXTURE_MATRIX_ARB: GLenum = 0x84e5;
+
+const GL_POINT_SPRITE_ARB: GLenum = 0x8861;
+const GL_COORD_REPLACE_ARB: GLenum = 0x8862;
+
+const MAX_TEXTURE_UNITS: usize = 1; // The rasterizer only has a single texture stage
+const MAX_LIGHTS: usize = 8;
+const MAX_EVAL_ORDER: usize = 30;
+
+// Extensions whose entry points and state are implemented, advertised through glGetString(GL_EXTENSIONS). Extension
+// entry points are handed out by wglGetProcAddress, so additions here usually need additions there too.
+// GL_ARB_multitexture requires at least two units, so its entry points are handed out without being advertised.
+const EXTENSIONS: &[&str] = &[
+    "GL_ARB_point_sprite",
+    "GL_ARB_transpose_matrix",
+    "GL_EXT_compiled_vertex_array",
+    "GL_EXT_draw_range_elements",
+    "GL_EXT_secondary_color",
+];
+
+// Overridable at run time with the GLOAT_VERTEX_CACHE_SIZE and GLOAT_VERTEX_CACHE_POLICY (fifo/lru) env vars
+const DEFAULT_VERTEX_CACHE_SIZE: usize = 16;
+
+// Minimum stack depths mandated by the spec (these include the current matrix)
+const MAX_MODELVIEW_STACK_DEPTH: usize = 32;
+const MAX_PROJECTION_STACK_DEPTH: usize = 2;
+const MAX_TEXTURE_STACK_DEPTH: usize = 2;
+const MAX_ATTRIB_STACK_DEPTH: usize = 16;
+const MAX_CLIENT_ATTRIB_STACK_DEPTH: usize = 16;
+
+// Commands are recorded as-is while the list is open and compiled into ops by glEndList
 struct DisplayList {
     commands: Vec<Command>,
+    ops: Vec<DisplayListOp>,
 }
 
 impl DisplayList {
     fn new() -> DisplayList {
         DisplayList {
             commands: Vec::new(),
+            ops: Vec::new(),
+        }
+    }
+
+    fn compile(&mut self) {
+        let commands = mem::replace(&mut self.commands, Vec::new());
+
+        // Find the glBegin/glEnd runs we can fully resolve; anything else is left to the interpretive path so it behaves
+        // (and fails) exactly as it would've without the batch
+        let mut runs = Vec::new();
+        for (i, command) in commands.iter().enumerate() {
+            if let Command::Begin { mode } = *command {
+                let run_len = commands[i + 1..].iter().take_while(|command| VertexBatch::is_foldable(command)).count();
+                let end = i + 1 + run_len;
+                if let (Some(primitive_mode), Some(&Command::End)) = (primitive_mode(mode), commands.get(end)) {
+                    runs.push((i, end, primitive_mode));
+                }
+            }
+        }
+
+        let mut ops = Vec::new();
+        let mut runs = runs.into_iter().peekable();
+        let mut batch: Option<VertexBatch> = None;
+        for (i, command) in commands.into_iter().enumerate() {
+            match runs.peek() {
+                Some(&(begin, end, primitive_mode)) if i >= begin => {
+                    if i == begin {
+                        batch = Some(VertexBatch::new(primitive_mode));
+                    } else if i < end {
+                        batch.as_mut().unwrap().fold(&command);
+                    } else {
+                        ops.push(DisplayListOp::Batch(batch.take().unwrap()));
+                        runs.next();
+                    }
+                }
+                _ => ops.push(DisplayListOp::Command(command))
+            }
+        }
+        self.ops = ops;
+    }
+}
+
+enum DisplayListOp {
+    Command(Command),
+    Batch(VertexBatch),
+}
+
+// A glBegin/glEnd run packed into object-space vertices. Attributes that weren't set within the run before a given
+// vertex are inherited from the current state at replay time; since an attribute stays set once it's been set, those
+// vertices always form a prefix of the batch, so we just count them.
+struct VertexBatch {
+    primitive_mode: PrimitiveMode,
+    verts: Vec<Vertex>,
+
+    inherited_normals: usize,
+    inherited_colors: usize,
+    inherited_secondary_colors: usize,
+    inherited_tex_coords: usize,
+    inherited_edge_flags: usize,
+
+    // Final attribute values, which become current after the batch like they would after the original commands
+    normal: Option<Vec3>,
+    color: Option<Vec4>,
+    secondary_color: Option<Vec3>,
+    tex_coord: Option<Vec2>,
+    edge_flag: Option<bool>,
+}
+
+impl VertexBatch {
+    fn new(primitive_mode: PrimitiveMode) -> VertexBatch {
+        VertexBatch {
+            primitive_mode,
+            verts: Vec::new(),
+
+            inherited_normals: 0,
+            inherited_colors: 0,
+            inherited_secondary_colors: 0,
+            inherited_tex_coords: 0,
+            inherited_edge_flags: 0,
+
+            normal: None,
+            color: None,
+            secondary_color: None,
+            tex_coord: None,
+            edge_flag: None,
+        }
+    }
+
+    fn is_foldable(command: &Command) -> bool {
+        match *command {
+            Command::Color3f { .. } |
+            Command::Color4f { .. } |
+            Command::EdgeFlag { .. } |
+            Command::Normal3f { .. } |
+            Command::SecondaryColor3fEXT { .. } |
+            Command::TexCoord2f { .. } |
+            Command::Vertex4f { .. } => true,
+            // Other targets are left to Context::execute to flag
+            Command::MultiTexCoord2fARB { target, .. } => target == GL_TEXTURE0_ARB,
+            _ => false
+        }
+    }
+
+    // Mirrors what Context::execute does for these commands
+    fn fold(&mut self, command: &Command) {
+        match *command {
+            Command::Color3f { red, green, blue } => {
+                self.color = Some(Vec4::new(red, green, blue, 1.0).max(Vec4::splat(0.0)).min(Vec4::splat(1.0)));
+            }
+            Command::Color4f { red, green, blue, alpha } => {
+                self.color = Some(Vec4::new(red, green, blue, alpha));
+            }
+            Command::EdgeFlag { flag } => {
+                self.edge_flag = Some(flag != GL_FALSE);
+            }
+            Command::MultiTexCoord2fARB { s, t, .. } | Command::TexCoord2f { s, t } => {
+                self.tex_coord = Some(Vec2::new(s, t));
+            }
+            Command::Normal3f { nx, ny, nz } => {
+                self.normal = Some(Vec3::new(nx, ny, nz));
+            }
+            Command::SecondaryColor3fEXT { red, green, blue } => {
+                self.secondary_color = Some(Vec3::new(red, green, blue).max(Vec3::zero()).min(Vec3::splat(1.0)));
+            }
+            Command::Vertex4f { x, y, z, w } => {
+                if self.normal.is_none() {
+                    self.inherited_normals += 1;
+                }
+                if self.color.is_none() {
+                    self.inherited_colors += 1;
+                }
+                if self.secondary_color.is_none() {
+                    self.inherited_secondary_colors += 1;
+                }
+                if self.tex_coord.is_none() {
+                    self.inherited_tex_coords += 1;
+                }
+                if self.edge_flag.is_none() {
+                    self.inherited_edge_flags += 1;
+                }
+                self.verts.push(Vertex {
+                    position: Vec4::new(x, y, z, w),
+                    normal: self.normal.unwrap_or(Vec3::splat(0.0)),
+                    color: self.color.unwrap_or(Vec4::splat(0.0)),
+                    secondary_color: self.secondary_color.unwrap_or(Vec3::splat(0.0)),
+                    tex_coord: self.tex_coord.unwrap_or(Vec2::splat(0.0)),
+                    edge_flag: self.edge_flag.unwrap_or(true),
+                });
+            }
+            _ => unreachable!()
         }
     }
+
+    fn sets_attributes(&self) -> bool {
+        self.normal.is_some() || self.color.is_some() || self.secondary_color.is_some() || self.tex_coord.is_some() || self.edge_flag.is_some()
+    }
 }
 
+#[derive(Clone, Copy)]
 enum MatrixMode {
     ModelView,
     Projection,
+    Texture,
 }
 
+#[derive(Clone, Copy)]
+enum FrontFace {
+    Cw,
+    Ccw,
+}
+
+#[derive(Clone, Copy)]
+enum PolygonMode {
+    Point,
+    Line,
+    Fill,
+}
+
+#[derive(Clone, Copy)]
+enum ShadeModel {
+    Flat,
+    Smooth,
+}
+
+#[derive(Clone, Copy)]
 enum TextureFilter {
     Nearest,
     Linear,
@@ -168,6 +620,13 @@ struct Texture {
     min_filter: TextureFilter,
     wrap_s: WrapParameter,
     wrap_t: WrapParameter,
+    priority: GLfloat,
+    internal_format: GLint,
+    // Dimensions as specified by the app. Images larger than MAX_TEXTURE_DIM are stored downscaled by
+    // 2^downscale_shift, in which case width/height are the stored dimensions.
+    level_width: usize,
+    level_height: usize,
+    downscale_shift: u32,
     width: usize,
     height: usize,
     data: Vec<u32>,
@@ -180,22 +639,441 @@ impl Texture {
             min_filter: TextureFilter::Linear,
             wrap_s: WrapParameter::Repeat,
             wrap_t: WrapParameter::Repeat,
+            priority: 1.0,
+            internal_format: GL_RGBA as _,
+            level_width: 0,
+            level_height: 0,
+            downscale_shift: 0,
             width: 0,
             height: 0,
             data: Vec::new(),
         }
     }
+
+    fn parameters(&self) -> TextureParameters {
+        TextureParameters {
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            wrap_s: self.wrap_s,
+            wrap_t: self.wrap_t,
+            priority: self.priority,
+        }
+    }
+
+    fn set_parameters(&mut self, parameters: TextureParameters) {
+        self.mag_filter = parameters.mag_filter;
+        self.min_filter = parameters.min_filter;
+        self.wrap_s = parameters.wrap_s;
+        self.wrap_t = parameters.wrap_t;
+        self.priority = parameters.priority;
+    }
+}
+
+// The texture object state saved with GL_TEXTURE_BIT
+#[derive(Clone, Copy)]
+struct TextureParameters {
+    mag_filter: TextureFilter,
+    min_filter: TextureFilter,
+    wrap_s: WrapParameter,
+    wrap_t: WrapParameter,
+    priority: GLfloat,
+}
+
+fn type_size(type_: GLenum) -> usize {
+    match type_ {
+        GL_BYTE | GL_UNSIGNED_BYTE => 1,
+        GL_SHORT | GL_UNSIGNED_SHORT => 2,
+        GL_INT | GL_UNSIGNED_INT | GL_FLOAT => 4,
+        GL_DOUBLE => 8,
+        _ => panic!("Invalid type: 0x{:08x}", type_)
+    }
+}
+
+// A component type of vertex attributes, whether passed to immediate-mode calls or read from client arrays
+trait Component: Copy {
+    fn to_float(self) -> GLfloat;
+
+    // Maps integers to [-1, 1] (signed) or [0, 1] (unsigned) as specified for normals and colors; floats pass through
+    fn to_normalized(self) -> GLfloat;
+}
+
+macro_rules! integer_components {
+    ($($type:ty: $max:expr, $signed:expr;)+) => {
+        $(
+            impl Component for $type {
+                fn to_float(self) -> GLfloat {
+                    self as GLfloat
+                }
+
+                fn to_normalized(self) -> GLfloat {
+                    let c = self as f64;
+                    (if $signed { (2.0 * c + 1.0) / $max } else { c / $max }) as GLfloat
+                }
+            }
+        )+
+    };
+}
+
+integer_components! {
+    GLbyte: 255.0, true;
+    GLubyte: 255.0, false;
+    GLshort: 65535.0, true;
+    GLushort: 65535.0, false;
+    GLint: 4294967295.0, true;
+    GLuint: 4294967295.0, false;
+}
+
+impl Component for GLfloat {
+    fn to_float(self) -> GLfloat {
+        self
+    }
+
+    fn to_normalized(self) -> GLfloat {
+        self
+    }
+}
+
+impl Component for GLdouble {
+    fn to_float(self) -> GLfloat {
+        self as GLfloat
+    }
+
+    fn to_normalized(self) -> GLfloat {
+        self as GLfloat
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClientArray {
+    enabled: bool,
+    size: GLint,
+    type_: GLenum,
+    stride: GLsizei,
+    pointer: *const GLvoid,
+}
+
+impl ClientArray {
+    fn new(size: GLint, type_: GLenum) -> ClientArray {
+        ClientArray {
+            enabled: false,
+            size,
+            type_,
+            stride: 0,
+            pointer: ptr::null(),
+        }
+    }
+
+    fn set_pointer(&mut self, size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        self.size = size;
+        self.type_ = type_;
+        self.stride = stride;
+        self.pointer = pointer;
+    }
+
+    fn element(&self, index: GLint) -> *const u8 {
+        let stride = if self.stride != 0 {
+            self.stride as usize
+        } else {
+            self.size as usize * type_size(self.type_)
+        };
+        unsafe { (self.pointer as *const u8).add(index as usize * stride) }
+    }
+
+    // Fetches an element's components, filling in missing components from (0, 0, 0, 1). Integer components are
+    // optionally normalized as specified for normals and colors.
+    unsafe fn fetch(&self, index: GLint, normalize: bool) -> [GLfloat; 4] {
+        unsafe fn read<T: Component>(element: *const u8, i: usize, normalize: bool) -> GLfloat {
+            let c = ptr::read_unaligned((element as *const T).add(i));
+            if normalize { c.to_normalized() } else { c.to_float() }
+        }
+
+        let element = self.element(index);
+        let mut ret = [0.0, 0.0, 0.0, 1.0];
+        for i in 0..self.size as usize {
+            ret[i] = match self.type_ {
+                GL_BYTE => read::<GLbyte>(element, i, normalize),
+                GL_UNSIGNED_BYTE => read::<GLubyte>(element, i, normalize),
+                GL_SHORT => read::<GLshort>(element, i, normalize),
+                GL_UNSIGNED_SHORT => read::<GLushort>(element, i, normalize),
+                GL_INT => read::<GLint>(element, i, normalize),
+                GL_UNSIGNED_INT => read::<GLuint>(element, i, normalize),
+                GL_FLOAT => read::<GLfloat>(element, i, normalize),
+                GL_DOUBLE => read::<GLdouble>(element, i, normalize),
+                _ => unreachable!()
+            };
+        }
+        ret
+    }
 }
 
+#[derive(Clone, Copy)]
 enum WrapParameter {
     Clamp,
     Repeat,
 }
 
+// Maps a window position in the app's resolution to an index into the back and depth buffers, which are auto-scaled
+// by half (see Command::Viewport) and stored top row first
+fn framebuffer_index(x: GLint, y: GLint) -> Option<usize> {
+    let x = x.div_euclid(2);
+    let y = y.div_euclid(2);
+    if x < 0 || x >= WIDTH as GLint || y < 0 || y >= HEIGHT as GLint {
+        return None;
+    }
+    Some((HEIGHT - 1 - y as usize) * WIDTH + x as usize)
+}
+
+// Returns the byte offset of the first pixel and the byte stride between rows of a client image, following the pixel
+// store addressing rules
+fn pixel_layout(width: usize, num_components: usize, element_size: usize, row_length: GLint, skip_pixels: GLint, skip_rows: GLint, alignment: GLint) -> (usize, usize) {
+    let row_length = if row_length > 0 { row_length as usize } else { width };
+    let alignment = alignment as usize;
+    let row_elements = if element_size >= alignment {
+        num_components * row_length
+    } else {
+        alignment / element_size * ((element_size * num_components * row_length + alignment - 1) / alignment)
+    };
+    ((skip_pixels as usize * num_components + skip_rows as usize * row_elements) * element_size, row_elements * element_size)
+}
+
+fn internal_format_has_alpha(internalformat: GLint) -> bool {
+    match internalformat as GLenum {
+        3 | GL_RGB => false,
+        _ => true
+    }
+}
+
+// Number of values taken by a glLight parameter, or None if pname isn't one
+fn light_param_count(pname: GLenum) -> Option<usize> {
+    match pname {
+        GL_AMBIENT | GL_DIFFUSE | GL_SPECULAR | GL_POSITION => Some(4),
+        GL_SPOT_DIRECTION => Some(3),
+        GL_SPOT_EXPONENT | GL_SPOT_CUTOFF | GL_CONSTANT_ATTENUATION | GL_LINEAR_ATTENUATION | GL_QUADRATIC_ATTENUATION => Some(1),
+        _ => None
+    }
+}
+
+// Number of values taken by a glLightModel parameter, or None if pname isn't one
+fn light_model_param_count(pname: GLenum) -> Option<usize> {
+    match pname {
+        GL_LIGHT_MODEL_AMBIENT => Some(4),
+        GL_LIGHT_MODEL_LOCAL_VIEWER | GL_LIGHT_MODEL_TWO_SIDE | GL_LIGHT_MODEL_COLOR_CONTROL => Some(1),
+        _ => None
+    }
+}
+
+// Number of values taken by a glMaterial parameter, or None if pname isn't one
+fn material_param_count(pname: GLenum) -> Option<usize> {
+    match pname {
+        GL_AMBIENT | GL_DIFFUSE | GL_SPECULAR | GL_EMISSION | GL_AMBIENT_AND_DIFFUSE => Some(4),
+        GL_SHININESS => Some(1),
+        _ => None
+    }
+}
+
+// Evaluator maps of each dimension, in the order of their GL_MAP1_*/GL_MAP2_* enums (color 4, index, normal, texture
+// coord 1-4, vertex 3-4) starting at GL_MAP1_COLOR_4/GL_MAP2_COLOR_4. Index maps are accepted but never evaluated, as
+// there's no color index mode.
+const NUM_EVAL_MAPS: usize = 9;
+const EVAL_MAP_COLOR_4: usize = 0;
+const EVAL_MAP_INDEX: usize = 1;
+const EVAL_MAP_NORMAL: usize = 2;
+const EVAL_MAP_TEXTURE_COORD_1: usize = 3;
+const EVAL_MAP_TEXTURE_COORD_4: usize = 6;
+const EVAL_MAP_VERTEX_3: usize = 7;
+const EVAL_MAP_VERTEX_4: usize = 8;
+const EVAL_MAP_COMPONENTS: [usize; NUM_EVAL_MAPS] = [4, 1, 3, 1, 2, 3, 4, 3, 4];
+
+// Index of an evaluator map target relative to the first target of its dimension, or None if target isn't one
+fn eval_map_index(target: GLenum, first: GLenum) -> Option<usize> {
+    if target >= first && target < first + NUM_EVAL_MAPS as GLenum {
+        Some((target - first) as usize)
+    } else {
+        None
+    }
+}
+
+// Bernstein basis polynomials of degree order - 1 at t, along with their derivatives
+fn bernstein(order: usize, t: GLfloat) -> (Vec<GLfloat>, Vec<GLfloat>) {
+    let mut basis = vec![1.0];
+    let mut derivatives = vec![0.0];
+    for degree in 1..order {
+        let mut next_basis = vec![0.0; degree + 1];
+        let mut next_derivatives = vec![0.0; degree + 1];
+        for (i, &b) in basis.iter().enumerate() {
+            next_basis[i] += (1.0 - t) * b;
+            next_basis[i + 1] += t * b;
+            next_derivatives[i] -= degree as GLfloat * b;
+            next_derivatives[i + 1] += degree as GLfloat * b;
+        }
+        basis = next_basis;
+        derivatives = next_derivatives;
+    }
+    (basis, derivatives)
+}
+
+// Reads evaluator control points from client memory, filling in missing components from (0, 0, 0, 1)
+unsafe fn read_control_points<T: Component>(points: *const T, components: usize, ustride: usize, uorder: usize, vstride: usize, vorder: usize) -> Vec<Vec4> {
+    let mut ret = Vec::with_capacity(uorder * vorder);
+    for i in 0..uorder {
+        for j in 0..vorder {
+            let point = slice::from_raw_parts(points.add(i * ustride + j * vstride), components);
+            let c = attribute_components(point.iter().map(|c| c.to_float()));
+            ret.push(Vec4::new(c[0], c[1], c[2], c[3]));
+        }
+    }
+    ret
+}
+
+// Control points of an evaluator map. One-dimensional maps are stored as two-dimensional ones with a v order of 1.
+#[derive(Clone)]
+struct EvalMap {
+    u1: GLfloat,
+    u2: GLfloat,
+    uorder: usize,
+    v1: GLfloat,
+    v2: GLfloat,
+    vorder: usize,
+    // Indexed by i * vorder + j
+    points: Vec<Vec4>,
+}
+
+impl EvalMap {
+    // The initial maps, which have order 1 and evaluate to the attributes' initial values everywhere
+    fn initial_maps() -> Vec<EvalMap> {
+        (0..NUM_EVAL_MAPS).map(|index| EvalMap {
+            u1: 0.0,
+            u2: 1.0,
+            uorder: 1,
+            v1: 0.0,
+            v2: 1.0,
+            vorder: 1,
+            points: vec![match index {
+                EVAL_MAP_COLOR_4 => Vec4::splat(1.0),
+                EVAL_MAP_INDEX => Vec4::new(1.0, 0.0, 0.0, 1.0),
+                EVAL_MAP_NORMAL => Vec4::new(0.0, 0.0, 1.0, 1.0),
+                _ => Vec4::new(0.0, 0.0, 0.0, 1.0),
+            }],
+        }).collect()
+    }
+
+    // Evaluates the map at (u, v), returning the value along with its partial derivatives with respect to u and v
+    fn evaluate(&self, u: GLfloat, v: GLfloat) -> (Vec4, Vec4, Vec4) {
+        let (u_basis, u_derivatives) = bernstein(self.uorder, (u - self.u1) / (self.u2 - self.u1));
+        let (v_basis, v_derivatives) = bernstein(self.vorder, (v - self.v1) / (self.v2 - self.v1));
+        let mut value = Vec4::zero();
+        let mut du = Vec4::zero();
+        let mut dv = Vec4::zero();
+        for i in 0..self.uorder {
+            for j in 0..self.vorder {
+                let point = self.points[i * self.vorder + j];
+                value += point * (u_basis[i] * v_basis[j]);
+                du += point * (u_derivatives[i] * v_basis[j]);
+                dv += point * (u_basis[i] * v_derivatives[j]);
+            }
+        }
+        (value, du / (self.u2 - self.u1), dv / (self.v2 - self.v1))
+    }
+}
+
+// The GL_AUTO_NORMAL normal of a map2 vertex, given its homogeneous position and partial derivatives
+fn auto_normal(position: Vec4, du: Vec4, dv: Vec4) -> Vec3 {
+    // The partial derivatives of position / w are (p_u * w - p * w_u) / w^2, but the 1 / w^2 factors don't change the
+    // direction of their cross product
+    let p = Vec3::new(position.x(), position.y(), position.z());
+    let pu = Vec3::new(du.x(), du.y(), du.z()) * position.w() - p * du.w();
+    let pv = Vec3::new(dv.x(), dv.y(), dv.z()) * position.w() - p * dv.w();
+    let normal = pu.cross(pv);
+    // Degenerate points (like the poles of a Bezier sphere) have no well-defined normal
+    if normal.len() > 0.0 { normal.normalize() } else { normal }
+}
+
+fn primitive_mode(mode: GLenum) -> Option<PrimitiveMode> {
+    match mode {
+        GL_POINTS => Some(PrimitiveMode::Points),
+        GL_LINES => Some(PrimitiveMode::Lines),
+        GL_LINE_LOOP => Some(PrimitiveMode::LineLoop),
+        GL_LINE_STRIP => Some(PrimitiveMode::LineStrip),
+        GL_TRIANGLES => Some(PrimitiveMode::Triangles),
+        GL_TRIANGLE_STRIP => Some(PrimitiveMode::TriangleStrip),
+        GL_TRIANGLE_FAN => Some(PrimitiveMode::TriangleFan),
+        GL_QUADS => Some(PrimitiveMode::Quads),
+        GL_QUAD_STRIP => Some(PrimitiveMode::QuadStrip),
+        GL_POLYGON => Some(PrimitiveMode::Polygon),
+        _ => None
+    }
+}
+
 #[derive(Clone, Copy)]
 enum PrimitiveMode {
+    Points,
+    Lines,
+    LineLoop,
+    LineStrip,
     Triangles,
+    TriangleStrip,
+    TriangleFan,
     Quads,
+    QuadStrip,
+    Polygon,
+}
+
+#[derive(Clone, Copy)]
+enum RasterPrimitive {
+    Point,
+    // The stipple counter at a given window position p is (p - stipple_origin) . stipple_direction
+    Line { stipple_origin: Vec2, stipple_direction: Vec2 },
+    Triangle,
+}
+
+// Applies the diamond-exit rule to a window-space segment from a to b: a fragment is produced if the segment intersects
+// the diamond |x - xf| + |y - yf| < 1/2 around its center, unless b lies inside that diamond. Only the fragment nearest
+// the line at each column center (along the unit major axis) can qualify, and the produced columns are contiguous, so
+// the first and last of them are returned (if any).
+fn diamond_exit_columns(a: Vec2, b: Vec2, major: Vec2, minor_axis: Vec2) -> Option<(i32, i32)> {
+    // Like the spec, both endpoints are perturbed by -(e, e^2) so segments passing exactly through diamond corners are
+    // resolved consistently. This happens in f64 so the perturbation survives next to window coordinates.
+    const EPSILON: f64 = 1.0 / 65536.0;
+    let to_axes = |p: Vec2| {
+        let (x, y) = (p.x() as f64 - EPSILON, p.y() as f64 - EPSILON * EPSILON);
+        (x * major.x() as f64 + y * major.y() as f64, x * minor_axis.x() as f64 + y * minor_axis.y() as f64)
+    };
+    let (a_u, a_v) = to_axes(a);
+    let (b_u, b_v) = to_axes(b);
+
+    let intersects_diamond = |center_u: f64, center_v: f64| {
+        // Clip the segment's parameter range against the diamond's four edges
+        let (mut s_min, mut s_max) = (0.0f64, 1.0f64);
+        for &(normal_u, normal_v) in [(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)].iter() {
+            let distance = normal_u * (a_u - center_u) + normal_v * (a_v - center_v) - 0.5;
+            let rate = normal_u * (b_u - a_u) + normal_v * (b_v - a_v);
+            if rate == 0.0 {
+                if distance >= 0.0 {
+                    return false;
+                }
+            } else if rate > 0.0 {
+                s_max = s_max.min(-distance / rate);
+            } else {
+                s_min = s_min.max(-distance / rate);
+            }
+        }
+        s_min < s_max
+    };
+
+    let slope = (b_v - a_v) / (b_u - a_u);
+    let mut columns = None;
+    for column in (a_u.floor() as i32 - 1)..=(b_u.floor() as i32 + 1) {
+        let center_u = column as f64 + 0.5;
+        let center_v = (a_v + slope * (center_u - a_u)).floor() + 0.5;
+        let b_in_diamond = (b_u - center_u).abs() + (b_v - center_v).abs() < 0.5;
+        if intersects_diamond(center_u, center_v) && !b_in_diamond {
+            columns = match columns {
+                Some((first, _)) => Some((first, column)),
+                _ => Some((column, column)),
+            };
+        }
+    }
+    columns
 }
 
 #[derive(Clone, Copy)]
@@ -203,169 +1081,857 @@ struct Vertex {
     position: Vec4,
     normal: Vec3,
     color: Vec4,
+    secondary_color: Vec3,
     tex_coord: Vec2,
+    edge_flag: bool,
 }
 
 enum Command {
     ActiveTextureARB { texture: GLenum },
+    AlphaFunc { func: GLenum, ref_: GLclampf },
     Begin { mode: GLenum },
     BindTexture { target: GLenum, texture: GLuint },
+    Bitmap { width: GLsizei, height: GLsizei, xorig: GLfloat, yorig: GLfloat, xmove: GLfloat, ymove: GLfloat, bitmap: Vec<bool> },
     BlendFunc { sfactor: GLenum, dfactor: GLenum },
     CallList { list: GLuint },
+    CallLists { lists: Vec<GLuint> },
     Clear { mask: GLbitfield },
     ClearColor { red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat },
     Color3f { red: GLfloat, green: GLfloat, blue: GLfloat },
     Color4f { red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat },
+    ColorMask { red: GLboolean, green: GLboolean, blue: GLboolean, alpha: GLboolean },
     CullFace { mode: GLenum },
     DepthMask { flag: GLboolean },
     Disable { cap: GLenum },
+    DrawPixels { width: GLsizei, height: GLsizei, pixels: Vec<u32> },
+    EdgeFlag { flag: GLboolean },
     Enable { cap: GLenum },
     End,
+    EvalCoord1f { u: GLfloat },
+    EvalCoord2f { u: GLfloat, v: GLfloat },
+    EvalMesh1 { mode: GLenum, i1: GLint, i2: GLint },
+    EvalMesh2 { mode: GLenum, i1: GLint, i2: GLint, j1: GLint, j2: GLint },
+    EvalPoint1 { i: GLint },
+    EvalPoint2 { i: GLint, j: GLint },
     Lightf { light: GLenum, pname: GLenum, param: GLfloat },
+    Lightfv { light: GLenum, pname: GLenum, params: [GLfloat; 4] },
+    LightModelfv { pname: GLenum, params: [GLfloat; 4] },
+    LineStipple { factor: GLint, pattern: GLushort },
+    LineWidth { width: GLfloat },
+    ListBase { base: GLuint },
+    FrontFace { mode: GLenum },
+    Frustum { left: GLdouble, right: GLdouble, bottom: GLdouble, top: GLdouble, zNear: GLdouble, zFar: GLdouble },
     LoadIdentity,
+    LoadMatrixd { m: [GLdouble; 16] },
+    LoadMatrixf { m: [GLfloat; 16] },
+    LoadTransposeMatrixdARB { m: [GLdouble; 16] },
+    LoadTransposeMatrixfARB { m: [GLfloat; 16] },
+    Map1f { target: GLenum, u1: GLfloat, u2: GLfloat, order: GLint, points: Vec<Vec4> },
+    Map2f { target: GLenum, u1: GLfloat, u2: GLfloat, uorder: GLint, v1: GLfloat, v2: GLfloat, vorder: GLint, points: Vec<Vec4> },
+    MapGrid1f { un: GLint, u1: GLfloat, u2: GLfloat },
+    MapGrid2f { un: GLint, u1: GLfloat, u2: GLfloat, vn: GLint, v1: GLfloat, v2: GLfloat },
+    Materialf { face: GLenum, pname: GLenum, param: GLfloat },
+    Materialfv { face: GLenum, pname: GLenum, params: [GLfloat; 4] },
     MatrixMode { mode: GLenum },
     MultiTexCoord2fARB { target: GLenum, s: GLfloat, t: GLfloat },
     MultMatrixd { m: [GLdouble; 16] },
     MultMatrixf { m: [GLfloat; 16] },
+    MultTransposeMatrixdARB { m: [GLdouble; 16] },
+    MultTransposeMatrixfARB { m: [GLfloat; 16] },
     Normal3f { nx: GLfloat, ny: GLfloat, nz: GLfloat },
-    Normal3fv { v: [GLfloat; 3] },
     Ortho { left: GLdouble, right: GLdouble, bottom: GLdouble, top: GLdouble, zNear: GLdouble, zFar: GLdouble },
+    PixelZoom { xfactor: GLfloat, yfactor: GLfloat },
+    PointSize { size: GLfloat },
     PolygonMode { face: GLenum, mode: GLenum },
+    PolygonOffset { factor: GLfloat, units: GLfloat },
+    PopAttrib,
     PopMatrix,
+    PushAttrib { mask: GLbitfield },
     PushMatrix,
+    RasterPos4f { x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat },
+    Rotated { angle: GLdouble, x: GLdouble, y: GLdouble, z: GLdouble },
     Rotatef { angle: GLfloat, x: GLfloat, y: GLfloat, z: GLfloat },
+    Scaled { x: GLdouble, y: GLdouble, z: GLdouble },
+    Scalef { x: GLfloat, y: GLfloat, z: GLfloat },
+    SecondaryColor3fEXT { red: GLfloat, green: GLfloat, blue: GLfloat },
     ShadeModel { mode: GLenum },
     TexCoord2f { s: GLfloat, t: GLfloat },
+    TexEnvfv { target: GLenum, pname: GLenum, params: [GLfloat; 4] },
+    TexEnvi { target: GLenum, pname: GLenum, param: GLint },
     TexGenf { coord: GLenum, pname: GLenum, param: GLfloat },
     TexGeni { coord: GLenum, pname: GLenum, param: GLint },
     TexParameteri { target: GLenum, pname: GLenum, param: GLint },
     Translated { x: GLdouble, y: GLdouble, z: GLdouble },
     Translatef { x: GLfloat, y: GLfloat, z: GLfloat },
-    Vertex3f { x: GLfloat, y: GLfloat, z: GLfloat },
+    Vertex4f { x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat },
     Viewport { x: GLint, y: GLint, width: GLsizei, height: GLsizei },
 }
 
-struct PatchedFunction {
-    original_addr: *mut u8,
-    restore_data: [u8; 5],
+// Current vertex attributes and raster position (GL_CURRENT_BIT)
+#[derive(Clone, Copy)]
+struct CurrentState {
+    normal: Vec3,
+    color: Vec4,
+    secondary_color: Vec3,
+    tex_coord: Vec2,
+    edge_flag: bool,
+
+    // Raster position in window coordinates
+    raster_position: Vec3,
+    raster_position_valid: bool,
+    raster_color: Vec4,
+}
+
+impl CurrentState {
+    fn new() -> CurrentState {
+        CurrentState {
+            normal: Vec3::zero(),
+            color: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            secondary_color: Vec3::zero(),
+            tex_coord: Vec2::new(0.0, 0.0),
+            edge_flag: true,
+
+            raster_position: Vec3::zero(),
+            raster_position_valid: true,
+            raster_color: Vec4::splat(1.0),
+        }
+    }
 }
 
-impl PatchedFunction {
-    fn new(original_addr: *mut u8, patch_addr: *const u8) -> PatchedFunction {
-        unsafe {
-            let mut old_protection = 0;
-            if VirtualProtect(original_addr as _, 5, PAGE_READWRITE, &mut old_protection as *mut _) == FALSE {
-                panic!("Couldn't make memory region readable/writable");
-            }
+// GL_POINT_BIT
+#[derive(Clone, Copy)]
+struct PointState {
+    point_size: GLfloat,
+    point_sprite_enable: bool,
+    point_sprite_coord_replace: bool,
+}
+
+impl PointState {
+    fn new() -> PointState {
+        PointState {
+            point_size: 1.0,
+            point_sprite_enable: false,
+            point_sprite_coord_replace: false,
+        }
+    }
+}
 
-            let patch_region = slice::from_raw_parts_mut(original_addr, 5);
+// GL_LINE_BIT
+#[derive(Clone, Copy)]
+struct LineState {
+    line_width: GLfloat,
+    line_stipple_enable: bool,
+    line_stipple_factor: GLint,
+    line_stipple_pattern: GLushort,
+}
+
+impl LineState {
+    fn new() -> LineState {
+        LineState {
+            line_width: 1.0,
+            line_stipple_enable: false,
+            line_stipple_factor: 1,
+            line_stipple_pattern: 0xffff,
+        }
+    }
+}
 
-            let mut restore_data = [0; 5];
-            restore_data.copy_from_slice(patch_region);
+// GL_POLYGON_BIT
+#[derive(Clone, Copy)]
+struct PolygonState {
+    front_face: FrontFace,
+    polygon_mode_front: PolygonMode,
+    polygon_mode_back: PolygonMode,
+    polygon_offset_factor: GLfloat,
+    polygon_offset_units: GLfloat,
+    polygon_offset_point_enable: bool,
+    polygon_offset_line_enable: bool,
+    polygon_offset_fill_enable: bool,
+}
+
+impl PolygonState {
+    fn new() -> PolygonState {
+        PolygonState {
+            front_face: FrontFace::Ccw,
+            polygon_mode_front: PolygonMode::Fill,
+            polygon_mode_back: PolygonMode::Fill,
+            polygon_offset_factor: 0.0,
+            polygon_offset_units: 0.0,
+            polygon_offset_point_enable: false,
+            polygon_offset_line_enable: false,
+            polygon_offset_fill_enable: false,
+        }
+    }
+}
 
-            let rel_addr = (patch_addr as usize).wrapping_sub((original_addr as usize).wrapping_add(5));
-            patch_region[0] = 0xe9; // JMP rel
-            patch_region[1..].copy_from_slice(&rel_addr.to_le_bytes());
+// GL_PIXEL_MODE_BIT
+#[derive(Clone, Copy)]
+struct PixelModeState {
+    pixel_zoom_x: GLfloat,
+    pixel_zoom_y: GLfloat,
+}
 
-            if VirtualProtect(original_addr as _, 5, old_protection, &mut old_protection as *mut _) == FALSE {
-                panic!("Couldn't restore memory region protection");
-            }
+impl PixelModeState {
+    fn new() -> PixelModeState {
+        PixelModeState {
+            pixel_zoom_x: 1.0,
+            pixel_zoom_y: 1.0,
+        }
+    }
+}
 
-            PatchedFunction {
-                original_addr,
-                restore_data,
-            }
+#[derive(Clone, Copy)]
+struct Light {
+    enabled: bool,
+    ambient: Vec4,
+    diffuse: Vec4,
+    specular: Vec4,
+    // Position and spot direction are stored in eye coordinates, transformed by the modelview matrix when specified
+    position: Vec4,
+    spot_direction: Vec3,
+    spot_exponent: GLfloat,
+    spot_cutoff: GLfloat,
+    constant_attenuation: GLfloat,
+    linear_attenuation: GLfloat,
+    quadratic_attenuation: GLfloat,
+}
+
+impl Light {
+    fn new(index: usize) -> Light {
+        // Light 0 is the only one that's white by default
+        let color = if index == 0 { Vec4::splat(1.0) } else { Vec4::new(0.0, 0.0, 0.0, 1.0) };
+        Light {
+            enabled: false,
+            ambient: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            diffuse: color,
+            specular: color,
+            position: Vec4::new(0.0, 0.0, 1.0, 0.0),
+            spot_direction: Vec3::new(0.0, 0.0, -1.0),
+            spot_exponent: 0.0,
+            spot_cutoff: 180.0,
+            constant_attenuation: 1.0,
+            linear_attenuation: 0.0,
+            quadratic_attenuation: 0.0,
         }
     }
 }
 
-impl Drop for PatchedFunction {
-    fn drop(&mut self) {
-        unsafe {
-            let mut old_protection = 0;
-            if VirtualProtect(self.original_addr as _, 5, PAGE_READWRITE, &mut old_protection as *mut _) == FALSE {
-                panic!("Couldn't make memory region readable/writable");
-            }
+#[derive(Clone, Copy)]
+struct Material {
+    ambient: Vec4,
+    diffuse: Vec4,
+    specular: Vec4,
+    emission: Vec4,
+    shininess: GLfloat,
+}
+
+impl Material {
+    fn new() -> Material {
+        Material {
+            ambient: Vec4::new(0.2, 0.2, 0.2, 1.0),
+            diffuse: Vec4::new(0.8, 0.8, 0.8, 1.0),
+            specular: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            emission: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            shininess: 0.0,
+        }
+    }
+}
 
-            let patch_region = slice::from_raw_parts_mut(self.original_addr, 5);
-            patch_region.copy_from_slice(&self.restore_data);
+// GL_LIGHTING_BIT
+// TODO: Only the specular term is evaluated (see LightingState::specular); the primary color isn't lit yet
+#[derive(Clone, Copy)]
+struct LightingState {
+    shade_model: ShadeModel,
+    lighting_enable: bool,
+    lights: [Light; MAX_LIGHTS],
+    front_material: Material,
+    back_material: Material,
+    light_model_ambient: Vec4,
+    light_model_local_viewer: bool,
+    light_model_two_side: bool,
+    light_model_color_control: GLenum,
+}
+
+impl LightingState {
+    fn new() -> LightingState {
+        let mut lights = [Light::new(0); MAX_LIGHTS];
+        for (index, light) in lights.iter_mut().enumerate() {
+            *light = Light::new(index);
+        }
+        LightingState {
+            shade_model: ShadeModel::Smooth,
+            lighting_enable: false,
+            lights,
+            front_material: Material::new(),
+            back_material: Material::new(),
+            light_model_ambient: Vec4::new(0.2, 0.2, 0.2, 1.0),
+            light_model_local_viewer: false,
+            light_model_two_side: false,
+            light_model_color_control: GL_SINGLE_COLOR,
+        }
+    }
 
-            if VirtualProtect(self.original_addr as _, 5, old_protection, &mut old_protection as *mut _) == FALSE {
-                panic!("Couldn't restore memory region protection");
+    // Sums the specular term of the lighting equation over the enabled lights, for a vertex at the given eye position
+    // with a unit eye-space normal
+    // TODO: Back faces of two-sided lighting should use the back material
+    fn specular(&self, eye: Vec4, normal: Vec3) -> Vec3 {
+        let position = Vec3::new(eye.x(), eye.y(), eye.z()) / eye.w();
+        let material = &self.front_material;
+        let mut ret = Vec3::zero();
+        for light in self.lights.iter().filter(|light| light.enabled) {
+            let light_position = Vec3::new(light.position.x(), light.position.y(), light.position.z());
+            let (to_light, attenuation) = if light.position.w() != 0.0 {
+                let to_light = light_position / light.position.w() - position;
+                let distance = to_light.len();
+                if distance == 0.0 {
+                    continue;
+                }
+                (to_light / distance, 1.0 / (light.constant_attenuation + light.linear_attenuation * distance + light.quadratic_attenuation * distance * distance))
+            } else {
+                (light_position.normalize(), 1.0)
+            };
+            // Lights facing away from the surface have no highlight
+            if normal.dot(to_light) <= 0.0 {
+                continue;
             }
+            let spot = if light.spot_cutoff != 180.0 {
+                let cos_angle = -to_light.dot(light.spot_direction.normalize());
+                if cos_angle < light.spot_cutoff.to_radians().cos() {
+                    continue;
+                }
+                cos_angle.powf(light.spot_exponent)
+            } else {
+                1.0
+            };
+            let to_eye = if self.light_model_local_viewer { (Vec3::zero() - position).normalize() } else { Vec3::new(0.0, 0.0, 1.0) };
+            let half = (to_light + to_eye).normalize();
+            let highlight = normal.dot(half).max(0.0).powf(material.shininess);
+            let light_specular = Vec3::new(light.specular.x(), light.specular.y(), light.specular.z());
+            let material_specular = Vec3::new(material.specular.x(), material.specular.y(), material.specular.z());
+            ret += light_specular * material_specular * (attenuation * spot * highlight);
         }
+        ret.max(Vec3::zero()).min(Vec3::splat(1.0))
     }
 }
 
-struct Context {
-    window: Window,
-    back_buffer: Vec<u32>,
-    depth_buffer: Vec<u16>,
+// GL_FOG_BIT
+// TODO: Fog itself isn't implemented; only the color sum enable, which shares the group, is tracked
+#[derive(Clone, Copy)]
+struct FogState {
+    color_sum_enable: bool,
+}
 
-    _swap_buffers: PatchedFunction,
-    _change_display_settings: PatchedFunction,
+impl FogState {
+    fn new() -> FogState {
+        FogState {
+            color_sum_enable: false,
+        }
+    }
+}
 
-    clear_color_red: GLfloat,
-    clear_color_green: GLfloat,
+// GL_DEPTH_BUFFER_BIT
+#[derive(Clone, Copy)]
+struct DepthBufferState {
+    depth_test: bool,
+    depth_mask: bool,
+}
+
+impl DepthBufferState {
+    fn new() -> DepthBufferState {
+        DepthBufferState {
+            depth_test: false,
+            depth_mask: true,
+        }
+    }
+}
+
+// GL_VIEWPORT_BIT
+#[derive(Clone, Copy)]
+struct ViewportState {
+    x: GLint,
+    y: GLint,
+    width: GLsizei,
+    height: GLsizei,
+}
+
+impl ViewportState {
+    fn new() -> ViewportState {
+        ViewportState {
+            x: 0,
+            y: 0,
+            width: WIDTH as _,
+            height: HEIGHT as _,
+        }
+    }
+}
+
+// GL_TRANSFORM_BIT
+#[derive(Clone, Copy)]
+struct TransformState {
+    matrix_mode: MatrixMode,
+}
+
+impl TransformState {
+    fn new() -> TransformState {
+        TransformState {
+            matrix_mode: MatrixMode::ModelView,
+        }
+    }
+}
+
+// GL_COLOR_BUFFER_BIT
+#[derive(Clone, Copy)]
+struct ColorBufferState {
+    clear_color_red: GLfloat,
+    clear_color_green: GLfloat,
     clear_color_blue: GLfloat,
     clear_color_alpha: GLfloat,
 
+    alpha_test_enable: bool,
+    alpha_test_func: CompareFunc,
+    alpha_test_ref: GLclampf,
+
+    color_mask_red: bool,
+    color_mask_green: bool,
+    color_mask_blue: bool,
+    color_mask_alpha: bool,
+
     blend_enable: bool,
     blend_src_factor: BlendSrcFactor,
     blend_dst_factor: BlendDstFactor,
+}
 
+impl ColorBufferState {
+    fn new() -> ColorBufferState {
+        ColorBufferState {
+            clear_color_red: 0.0,
+            clear_color_green: 0.0,
+            clear_color_blue: 0.0,
+            clear_color_alpha: 0.0,
+
+            alpha_test_enable: false,
+            alpha_test_func: CompareFunc::Always,
+            alpha_test_ref: 0.0,
+
+            color_mask_red: true,
+            color_mask_green: true,
+            color_mask_blue: true,
+            color_mask_alpha: true,
+
+            blend_enable: false,
+            // TODO: I wasn't able to find defaults for these factors, so they may be incorrect
+            blend_src_factor: BlendSrcFactor::SrcAlpha,
+            blend_dst_factor: BlendDstFactor::OneMinusSrcAlpha,
+        }
+    }
+}
+
+// GL_TEXTURE_BIT (along with the bound texture object's parameters)
+#[derive(Clone, Copy)]
+struct TextureState {
+    active_texture: usize,
+    texture_2d: GLuint,
+    texture_2d_enable: bool,
+    // TODO: Only tracked for state queries; the rasterizer always modulates
+    texture_env_mode: GLenum,
+    texture_env_color: Vec4,
+}
+
+impl TextureState {
+    fn new() -> TextureState {
+        TextureState {
+            active_texture: 0,
+            texture_2d: 0,
+            texture_2d_enable: false,
+            texture_env_mode: GL_MODULATE,
+            texture_env_color: Vec4::zero(),
+        }
+    }
+}
+
+// A grid of evenly spaced evaluator coordinates, set with glMapGrid
+#[derive(Clone, Copy)]
+struct EvalGrid {
+    segments: GLint,
+    c1: GLfloat,
+    c2: GLfloat,
+}
+
+impl EvalGrid {
+    fn new() -> EvalGrid {
+        EvalGrid {
+            segments: 1,
+            c1: 0.0,
+            c2: 1.0,
+        }
+    }
+
+    // The last grid point is exactly c2, regardless of rounding
+    fn coord(&self, i: GLint) -> GLfloat {
+        if i == self.segments {
+            self.c2
+        } else {
+            self.c1 + i as GLfloat * (self.c2 - self.c1) / self.segments as GLfloat
+        }
+    }
+}
+
+// GL_EVAL_BIT
+#[derive(Clone, Copy)]
+struct EvalState {
+    map1_enables: [bool; NUM_EVAL_MAPS],
+    map2_enables: [bool; NUM_EVAL_MAPS],
+    auto_normal: bool,
+    map1_grid: EvalGrid,
+    map2_grid_u: EvalGrid,
+    map2_grid_v: EvalGrid,
+}
+
+impl EvalState {
+    fn new() -> EvalState {
+        EvalState {
+            map1_enables: [false; NUM_EVAL_MAPS],
+            map2_enables: [false; NUM_EVAL_MAPS],
+            auto_normal: false,
+            map1_grid: EvalGrid::new(),
+            map2_grid_u: EvalGrid::new(),
+            map2_grid_v: EvalGrid::new(),
+        }
+    }
+}
+
+// Enables saved by GL_ENABLE_BIT, which otherwise live in the groups above
+#[derive(Clone, Copy)]
+struct EnableState {
+    auto_normal: bool,
+    alpha_test: bool,
+    blend: bool,
+    color_sum: bool,
     depth_test: bool,
-    depth_mask: bool,
+    lighting: bool,
+    lights: [bool; MAX_LIGHTS],
+    line_stipple: bool,
+    map1: [bool; NUM_EVAL_MAPS],
+    map2: [bool; NUM_EVAL_MAPS],
+    point_sprite: bool,
+    polygon_offset_fill: bool,
+    polygon_offset_line: bool,
+    polygon_offset_point: bool,
+    texture_2d: bool,
+}
+
+// An entry on the glPushAttrib stack. Every group is snapshotted, but only those in mask are restored on pop.
+struct ServerAttribs {
+    mask: GLbitfield,
+    current: CurrentState,
+    point: PointState,
+    line: LineState,
+    polygon: PolygonState,
+    pixel_mode: PixelModeState,
+    lighting: LightingState,
+    fog: FogState,
+    depth: DepthBufferState,
+    viewport: ViewportState,
+    transform: TransformState,
+    enable: EnableState,
+    color_buffer: ColorBufferState,
+    eval: EvalState,
+    list_base: GLuint,
+    texture: TextureState,
+    texture_parameters: TextureParameters,
+}
+
+#[derive(Clone, Copy)]
+struct PixelStore {
+    swap_bytes: GLint,
+    lsb_first: GLint,
+    row_length: GLint,
+    skip_rows: GLint,
+    skip_pixels: GLint,
+    alignment: GLint,
+}
+
+impl PixelStore {
+    fn new() -> PixelStore {
+        PixelStore {
+            swap_bytes: 0,
+            lsb_first: 0,
+            row_length: 0,
+            skip_rows: 0,
+            skip_pixels: 0,
+            alignment: 4,
+        }
+    }
+}
+
+// GL_CLIENT_PIXEL_STORE_BIT
+#[derive(Clone, Copy)]
+struct PixelStoreState {
+    unpack: PixelStore,
+    pack: PixelStore,
+}
+
+impl PixelStoreState {
+    fn new() -> PixelStoreState {
+        PixelStoreState {
+            unpack: PixelStore::new(),
+            pack: PixelStore::new(),
+        }
+    }
+}
+
+// GL_CLIENT_VERTEX_ARRAY_BIT
+#[derive(Clone, Copy)]
+struct VertexArrayState {
+    vertex_array: ClientArray,
+    normal_array: ClientArray,
+    color_array: ClientArray,
+    secondary_color_array: ClientArray,
+    tex_coord_arrays: [ClientArray; MAX_TEXTURE_UNITS],
+    edge_flag_array: ClientArray,
+    client_active_texture: usize,
+}
+
+impl VertexArrayState {
+    fn new() -> VertexArrayState {
+        VertexArrayState {
+            vertex_array: ClientArray::new(4, GL_FLOAT),
+            normal_array: ClientArray::new(3, GL_FLOAT),
+            color_array: ClientArray::new(4, GL_FLOAT),
+            secondary_color_array: ClientArray::new(3, GL_FLOAT),
+            tex_coord_arrays: [ClientArray::new(4, GL_FLOAT); MAX_TEXTURE_UNITS],
+            edge_flag_array: ClientArray::new(1, GL_UNSIGNED_BYTE),
+            client_active_texture: 0,
+        }
+    }
+}
+
+// An entry on the glPushClientAttrib stack
+struct ClientAttribs {
+    mask: GLbitfield,
+    pixel_store: PixelStoreState,
+    vertex_arrays: VertexArrayState,
+}
+
+// Vertices in the range locked with glLockArraysEXT, transformed on first use and then reused until the transform
+// state or current attributes change. Modifying the arrays themselves while they're locked is undefined per the spec.
+struct LockedArrays {
+    first: GLuint,
+    verts: Vec<Option<Vertex>>,
+}
+
+// Bytes moved between the framebuffer and other memories, reset every frame
+struct MemoryTraffic {
+    tile_loads: usize,
+    tile_stores: usize,
+    framebuffer_to_texture: usize,
+}
+
+impl MemoryTraffic {
+    fn new() -> MemoryTraffic {
+        MemoryTraffic {
+            tile_loads: 0,
+            tile_stores: 0,
+            framebuffer_to_texture: 0,
+        }
+    }
+}
+
+// The result of a state query in its natural type, converted to the type requested by the glGet* variant used
+enum StateValue {
+    Booleans(Vec<bool>),
+    Integers(Vec<GLint>),
+    Floats(Vec<GLfloat>),
+    // Floats in [-1, 1] that map linearly onto the full integer range when queried as integers (colors, normals, depths)
+    NormalizedFloats(Vec<GLfloat>),
+}
+
+impl StateValue {
+    fn boolean(value: bool) -> StateValue {
+        StateValue::Booleans(vec![value])
+    }
+
+    fn integer(value: GLint) -> StateValue {
+        StateValue::Integers(vec![value])
+    }
+
+    fn float(value: GLfloat) -> StateValue {
+        StateValue::Floats(vec![value])
+    }
+
+    fn vec4(value: Vec4) -> StateValue {
+        StateValue::Floats(vec![value.x(), value.y(), value.z(), value.w()])
+    }
+
+    fn color(value: Vec4) -> StateValue {
+        StateValue::NormalizedFloats(vec![value.x(), value.y(), value.z(), value.w()])
+    }
+
+    fn matrix(value: Matrix) -> StateValue {
+        StateValue::Floats(value.to_floats().to_vec())
+    }
+
+    fn to_booleans(&self) -> Vec<GLubyte> {
+        match *self {
+            StateValue::Booleans(ref values) => values.iter().map(|&value| value as _).collect(),
+            StateValue::Integers(ref values) => values.iter().map(|&value| (value != 0) as _).collect(),
+            StateValue::Floats(ref values) | StateValue::NormalizedFloats(ref values) => values.iter().map(|&value| (value != 0.0) as _).collect(),
+        }
+    }
+
+    fn to_integers(&self) -> Vec<GLint> {
+        match *self {
+            StateValue::Booleans(ref values) => values.iter().map(|&value| value as _).collect(),
+            StateValue::Integers(ref values) => values.clone(),
+            StateValue::Floats(ref values) => values.iter().map(|&value| value.round() as _).collect(),
+            StateValue::NormalizedFloats(ref values) => values.iter().map(|&value| ((4294967295.0 * value as f64 - 1.0) / 2.0).round() as _).collect(),
+        }
+    }
+
+    fn to_floats(&self) -> Vec<GLfloat> {
+        match *self {
+            StateValue::Booleans(ref values) => values.iter().map(|&value| value as u32 as _).collect(),
+            StateValue::Integers(ref values) => values.iter().map(|&value| value as _).collect(),
+            StateValue::Floats(ref values) | StateValue::NormalizedFloats(ref values) => values.clone(),
+        }
+    }
+
+    fn to_doubles(&self) -> Vec<GLdouble> {
+        match *self {
+            StateValue::Integers(ref values) => values.iter().map(|&value| value as _).collect(),
+            _ => self.to_floats().iter().map(|&value| value as _).collect(),
+        }
+    }
+}
+
+struct PatchedFunction {
+    original_addr: *mut u8,
+    restore_data: [u8; 5],
+}
+
+impl PatchedFunction {
+    fn new(original_addr: *mut u8, patch_addr: *const u8) -> PatchedFunction {
+        unsafe {
+            let mut old_protection = 0;
+            if VirtualProtect(original_addr as _, 5, PAGE_READWRITE, &mut old_protection as *mut _) == FALSE {
+                panic!("Couldn't make memory region readable/writable");
+            }
+
+            let patch_region = slice::from_raw_parts_mut(original_addr, 5);
+
+            let mut restore_data = [0; 5];
+            restore_data.copy_from_slice(patch_region);
+
+            let rel_addr = (patch_addr as usize).wrapping_sub((original_addr as usize).wrapping_add(5));
+            patch_region[0] = 0xe9; // JMP rel
+            patch_region[1..].copy_from_slice(&rel_addr.to_le_bytes());
+
+            if VirtualProtect(original_addr as _, 5, old_protection, &mut old_protection as *mut _) == FALSE {
+                panic!("Couldn't restore memory region protection");
+            }
+
+            PatchedFunction {
+                original_addr,
+                restore_data,
+            }
+        }
+    }
+}
+
+impl Drop for PatchedFunction {
+    fn drop(&mut self) {
+        unsafe {
+            let mut old_protection = 0;
+            if VirtualProtect(self.original_addr as _, 5, PAGE_READWRITE, &mut old_protection as *mut _) == FALSE {
+                panic!("Couldn't make memory region readable/writable");
+            }
+
+            let patch_region = slice::from_raw_parts_mut(self.original_addr, 5);
+            patch_region.copy_from_slice(&self.restore_data);
+
+            if VirtualProtect(self.original_addr as _, 5, old_protection, &mut old_protection as *mut _) == FALSE {
+                panic!("Couldn't restore memory region protection");
+            }
+        }
+    }
+}
+
+// Reports an invalid call (see Context::invalid_call) and returns from the current function, so the call is ignored
+macro_rules! invalid_call {
+    ($context:expr, $error:expr, $($arg:tt)+) => {{
+        $context.invalid_call($error, format_args!($($arg)+));
+        return;
+    }};
+}
+
+struct Context {
+    window: Window,
+    back_buffer: Vec<u32>,
+    depth_buffer: Vec<u16>,
+    memory_traffic: MemoryTraffic,
 
-    display_lists: Vec<Rc<RefCell<DisplayList>>>,
-    new_list: Option<GLuint>,
+    _swap_buffers: PatchedFunction,
+    _change_display_settings: PatchedFunction,
+
+    // Server state, grouped by glPushAttrib bit
+    current: CurrentState,
+    point: PointState,
+    line: LineState,
+    polygon: PolygonState,
+    pixel_mode: PixelModeState,
+    lighting: LightingState,
+    fog: FogState,
+    depth: DepthBufferState,
+    viewport: ViewportState,
+    transform: TransformState,
+    color_buffer: ColorBufferState,
+    eval: EvalState,
+    texture: TextureState,
+    attrib_stack: Vec<ServerAttribs>,
+
+    // Client state, grouped by glPushClientAttrib bit
+    pixel_store: PixelStoreState,
+    vertex_arrays: VertexArrayState,
+    client_attrib_stack: Vec<ClientAttribs>,
+
+    line_stipple_counter: f32,
+
+    error: GLenum,
+    // Panic on invalid calls instead of flagging GL errors, to make it easier to find the offending call
+    strict: bool,
+
+    // Space-separated EXTENSIONS, kept alive for the pointer returned by glGetString
+    extensions: CString,
+
+    display_lists: HashMap<GLuint, Rc<RefCell<DisplayList>>>,
+    // The list being defined only replaces any existing list with the same name at glEndList
+    new_list: Option<(GLuint, DisplayList)>,
     new_list_mode: GLenum,
+    list_base: GLuint,
 
     modelview: Matrix,
     projection: Matrix,
-    matrix_mode: MatrixMode,
-    matrix_stack: Vec<Matrix>,
+    texture_matrix: Matrix,
+    modelview_stack: Vec<Matrix>,
+    projection_stack: Vec<Matrix>,
+    texture_stack: Vec<Matrix>,
 
-    textures: Vec<Texture>,
-    texture_2d: GLuint,
-    texture_2d_enable: bool,
+    // Evaluator maps by dimension, indexed like their targets (see NUM_EVAL_MAPS)
+    eval_maps1: Vec<EvalMap>,
+    eval_maps2: Vec<EvalMap>,
 
-    primitive_mode: Option<PrimitiveMode>,
+    // Texture objects by name. Name 0 is the default texture, which always exists, and the bound name always refers to
+    // an existing object, as objects are created on first bind and deleting a bound object reverts the binding to 0.
+    textures: HashMap<GLuint, Texture>,
+    next_texture_name: GLuint,
 
-    current_normal: Vec3,
-    current_color: Vec4,
-    current_tex_coord: Vec2,
+    primitive_mode: Option<PrimitiveMode>,
 
     verts: Vec<Vertex>,
 
-    unpack_swap_bytes: GLint,
-    unpack_lsb_first: GLint,
-    unpack_row_length: GLint,
-    unpack_skip_rows: GLint,
-    unpack_skip_pixels: GLint,
-    unpack_alignment: GLint,
-    pack_swap_bytes: GLint,
-    pack_lsb_first: GLint,
-    pack_row_length: GLint,
-    pack_skip_rows: GLint,
-    pack_skip_pixels: GLint,
-    pack_alignment: GLint,
-
-    vertex_array_enabled: bool,
-    vertex_pointer: *const GLvoid,
-    vertex_size: GLint,
-    vertex_type: GLenum,
-    vertex_stride: GLsizei,
-
-    normal_array_enabled: bool,
-    normal_pointer: *const GLvoid,
-    normal_type: GLenum,
-    normal_stride: GLsizei,
-
-    viewport_x: GLint,
-    viewport_y: GLint,
-    viewport_width: GLsizei,
-    viewport_height: GLsizei,
+    vertex_cache: VertexCache<Vertex>,
+    locked_arrays: Option<LockedArrays>,
+    transformed_vert_count: u32,
 }
 
 impl Context {
@@ -379,123 +1945,572 @@ impl Context {
             }).expect("Could not create output window"),
             back_buffer: vec![0; WIDTH * HEIGHT],
             depth_buffer: vec![65535; WIDTH * HEIGHT],
+            memory_traffic: MemoryTraffic::new(),
 
             _swap_buffers: PatchedFunction::new(SwapBuffers as _, swap_buffers as _),
             _change_display_settings: PatchedFunction::new(ChangeDisplaySettingsExA as _, change_display_settings_ex_a as _),
 
-            clear_color_red: 0.0,
-            clear_color_green: 0.0,
-            clear_color_blue: 0.0,
-            clear_color_alpha: 0.0,
+            current: CurrentState::new(),
+            point: PointState::new(),
+            line: LineState::new(),
+            polygon: PolygonState::new(),
+            pixel_mode: PixelModeState::new(),
+            lighting: LightingState::new(),
+            fog: FogState::new(),
+            depth: DepthBufferState::new(),
+            viewport: ViewportState::new(),
+            transform: TransformState::new(),
+            color_buffer: ColorBufferState::new(),
+            eval: EvalState::new(),
+            texture: TextureState::new(),
+            attrib_stack: Vec::new(),
+
+            pixel_store: PixelStoreState::new(),
+            vertex_arrays: VertexArrayState::new(),
+            client_attrib_stack: Vec::new(),
+
+            line_stipple_counter: 0.0,
+
+            error: GL_NO_ERROR,
+            strict: cfg!(feature = "strict") || env::var("GLOAT_STRICT").is_ok(),
+
+            extensions: CString::new(EXTENSIONS.join(" ")).unwrap(),
+
+            display_lists: HashMap::new(),
+            new_list: None,
+            new_list_mode: 0,
+            list_base: 0,
 
-            blend_enable: false,
-            // TODO: I wasn't able to find defaults for these factors, so they may be incorrect
-            blend_src_factor: BlendSrcFactor::SrcAlpha,
-            blend_dst_factor: BlendDstFactor::OneMinusSrcAlpha,
+            modelview: Matrix::identity(),
+            projection: Matrix::identity(),
+            texture_matrix: Matrix::identity(),
+            modelview_stack: Vec::new(),
+            projection_stack: Vec::new(),
+            texture_stack: Vec::new(),
 
-            depth_test: false,
-            depth_mask: true,
+            eval_maps1: EvalMap::initial_maps(),
+            eval_maps2: EvalMap::initial_maps(),
 
-            display_lists: Vec::new(),
-            new_list: None,
-            new_list_mode: 0,
+            textures: {
+                let mut textures = HashMap::new();
+                textures.insert(0, Texture::new());
+                textures
+            },
+            next_texture_name: 1,
+
+            primitive_mode: None,
+
+            verts: Vec::new(),
+
+            vertex_cache: VertexCache::new(
+                match env::var("GLOAT_VERTEX_CACHE_POLICY").as_ref().map(|policy| policy.as_str()) {
+                    Ok("lru") => VertexCachePolicy::Lru,
+                    _ => VertexCachePolicy::Fifo,
+                },
+                env::var("GLOAT_VERTEX_CACHE_SIZE").ok().and_then(|size| size.parse().ok()).unwrap_or(DEFAULT_VERTEX_CACHE_SIZE)),
+            locked_arrays: None,
+            transformed_vert_count: 0,
+        }
+    }
+
+    fn current_matrix(&self) -> Matrix {
+        match self.transform.matrix_mode {
+            MatrixMode::ModelView => self.modelview,
+            MatrixMode::Projection => self.projection,
+            MatrixMode::Texture => self.texture_matrix,
+        }
+    }
+
+    fn current_matrix_stack(&mut self) -> (&mut Vec<Matrix>, usize) {
+        match self.transform.matrix_mode {
+            MatrixMode::ModelView => (&mut self.modelview_stack, MAX_MODELVIEW_STACK_DEPTH),
+            MatrixMode::Projection => (&mut self.projection_stack, MAX_PROJECTION_STACK_DEPTH),
+            MatrixMode::Texture => (&mut self.texture_stack, MAX_TEXTURE_STACK_DEPTH),
+        }
+    }
+
+    fn set_current_matrix(&mut self, m: Matrix) {
+        self.invalidate_locked_arrays();
+        match self.transform.matrix_mode {
+            MatrixMode::ModelView => {
+                self.modelview = m;
+            }
+            MatrixMode::Projection => {
+                self.projection = m;
+            }
+            MatrixMode::Texture => {
+                self.texture_matrix = m;
+            }
+        }
+    }
+
+    fn multiply_current_matrix(&mut self, m: Matrix) {
+        self.set_current_matrix(self.current_matrix() * m);
+    }
+
+    fn array_element(&mut self, index: GLint) {
+        if self.new_list.is_some() || self.primitive_mode.is_none() {
+            self.issue_array_element(index);
+            return;
+        }
+
+        // Within glBegin/glEnd, runs of array elements go through the post-transform cache just like glDrawElements
+        let vert = self.fetch_vertex(index);
+        self.current.normal = vert.normal;
+        self.current.color = vert.color;
+        self.current.secondary_color = vert.secondary_color;
+        self.current.tex_coord = vert.tex_coord;
+        self.current.edge_flag = vert.edge_flag;
+        if self.vertex_arrays.vertex_array.enabled {
+            let vert = self.transformed_array_vertex(index as _, true);
+            self.verts.push(vert);
+        }
+    }
+
+    // Issues the equivalent immediate-mode commands for an array element (so they can be recorded into display lists)
+    fn issue_array_element(&mut self, index: GLint) {
+        // The vertex has to be issued last, as it latches all of the current attributes
+        if self.vertex_arrays.edge_flag_array.enabled {
+            let flag = unsafe { *self.vertex_arrays.edge_flag_array.element(index) };
+            self.issue(Command::EdgeFlag { flag: flag as _ });
+        }
+        if self.vertex_arrays.tex_coord_arrays[0].enabled {
+            let tex_coord = unsafe { self.vertex_arrays.tex_coord_arrays[0].fetch(index, false) };
+            self.issue(Command::TexCoord2f { s: tex_coord[0], t: tex_coord[1] });
+        }
+        if self.vertex_arrays.color_array.enabled {
+            let color = unsafe { self.vertex_arrays.color_array.fetch(index, true) };
+            self.issue(Command::Color4f { red: color[0], green: color[1], blue: color[2], alpha: color[3] });
+        }
+        if self.vertex_arrays.secondary_color_array.enabled {
+            let color = unsafe { self.vertex_arrays.secondary_color_array.fetch(index, true) };
+            self.issue(Command::SecondaryColor3fEXT { red: color[0], green: color[1], blue: color[2] });
+        }
+        if self.vertex_arrays.normal_array.enabled {
+            let normal = unsafe { self.vertex_arrays.normal_array.fetch(index, true) };
+            self.issue(Command::Normal3f { nx: normal[0], ny: normal[1], nz: normal[2] });
+        }
+        if self.vertex_arrays.vertex_array.enabled {
+            let vertex = unsafe { self.vertex_arrays.vertex_array.fetch(index, false) };
+            self.issue(Command::Vertex4f { x: vertex[0], y: vertex[1], z: vertex[2], w: vertex[3] });
+        }
+    }
+
+    // Builds a vertex from the enabled client arrays, with the current attributes standing in for disabled ones
+    fn fetch_vertex(&self, index: GLint) -> Vertex {
+        let mut vert = Vertex {
+            position: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            normal: self.current.normal,
+            color: self.current.color,
+            secondary_color: self.current.secondary_color,
+            tex_coord: self.current.tex_coord,
+            edge_flag: self.current.edge_flag,
+        };
+        unsafe {
+            if self.vertex_arrays.edge_flag_array.enabled {
+                vert.edge_flag = *self.vertex_arrays.edge_flag_array.element(index) != 0;
+            }
+            if self.vertex_arrays.tex_coord_arrays[0].enabled {
+                let tex_coord = self.vertex_arrays.tex_coord_arrays[0].fetch(index, false);
+                vert.tex_coord = Vec2::new(tex_coord[0], tex_coord[1]);
+            }
+            if self.vertex_arrays.color_array.enabled {
+                let color = self.vertex_arrays.color_array.fetch(index, true);
+                vert.color = Vec4::new(color[0], color[1], color[2], color[3]);
+            }
+            if self.vertex_arrays.secondary_color_array.enabled {
+                let color = self.vertex_arrays.secondary_color_array.fetch(index, true);
+                vert.secondary_color = Vec3::new(color[0], color[1], color[2]).max(Vec3::zero()).min(Vec3::splat(1.0));
+            }
+            if self.vertex_arrays.normal_array.enabled {
+                let normal = self.vertex_arrays.normal_array.fetch(index, true);
+                vert.normal = Vec3::new(normal[0], normal[1], normal[2]);
+            }
+            if self.vertex_arrays.vertex_array.enabled {
+                let position = self.vertex_arrays.vertex_array.fetch(index, false);
+                vert.position = Vec4::new(position[0], position[1], position[2], position[3]);
+            }
+        }
+        vert
+    }
+
+    // Fetches and transforms an array vertex, reusing the locked arrays and (optionally) the post-transform cache
+    fn transformed_array_vertex(&mut self, index: GLuint, use_cache: bool) -> Vertex {
+        if let Some(vert) = self.locked_array_vertex(index) {
+            return vert;
+        }
+
+        if !use_cache {
+            return self.transform_vertex(self.fetch_vertex(index as _));
+        }
+
+        match self.vertex_cache.lookup(index) {
+            Some(vert) => vert,
+            None => {
+                let vert = self.transform_vertex(self.fetch_vertex(index as _));
+                self.vertex_cache.insert(index, vert);
+                vert
+            }
+        }
+    }
+
+    fn locked_array_vertex(&mut self, index: GLuint) -> Option<Vertex> {
+        let slot = match self.locked_arrays {
+            Some(ref locked_arrays) if index >= locked_arrays.first && ((index - locked_arrays.first) as usize) < locked_arrays.verts.len() => {
+                let slot = (index - locked_arrays.first) as usize;
+                if let Some(vert) = locked_arrays.verts[slot] {
+                    return Some(vert);
+                }
+                slot
+            }
+            _ => return None
+        };
+
+        let vert = self.transform_vertex(self.fetch_vertex(index as _));
+        self.locked_arrays.as_mut().unwrap().verts[slot] = Some(vert);
+        Some(vert)
+    }
+
+    fn invalidate_locked_arrays(&mut self) {
+        if let Some(ref mut locked_arrays) = self.locked_arrays {
+            for vert in locked_arrays.verts.iter_mut() {
+                *vert = None;
+            }
+        }
+    }
+
+    // Lighting decides the transformed secondary color, so locked vertices transformed under the old state are stale.
+    // The same goes for the light, material and light model setters.
+    fn set_lighting_enable(&mut self, enable: bool) {
+        if self.lighting.lighting_enable != enable {
+            self.lighting.lighting_enable = enable;
+            self.invalidate_locked_arrays();
+        }
+    }
+
+    // Transforms an object-space vertex into clip space
+    fn transform_vertex(&mut self, mut vert: Vertex) -> Vertex {
+        self.transformed_vert_count += 1;
+
+        let object = vert.position;
+        let eye = self.modelview * object;
+        let clip = self.projection * eye;
+        vert.position = clip;
+        let tex_coord = self.texture_matrix * Vec4::new(vert.tex_coord.x(), vert.tex_coord.y(), 0.0, 1.0);
+        vert.tex_coord = Vec2::new(tex_coord.x(), tex_coord.y()) / tex_coord.w();
+        if self.lighting.lighting_enable {
+            // Lit vertices don't use the current secondary color; it's the specular term with
+            // GL_SEPARATE_SPECULAR_COLOR, and zero with GL_SINGLE_COLOR (where specular is added to the primary color)
+            vert.secondary_color = if self.lighting.light_model_color_control == GL_SEPARATE_SPECULAR_COLOR {
+                // GL_NORMALIZE isn't modeled, so normals are always renormalized
+                let normal = self.modelview.normal_matrix() * Vec4::new(vert.normal.x(), vert.normal.y(), vert.normal.z(), 0.0);
+                let normal = Vec3::new(normal.x(), normal.y(), normal.z());
+                if normal.len() > 0.0 { self.lighting.specular(eye, normal.normalize()) } else { Vec3::zero() }
+            } else {
+                Vec3::zero()
+            };
+        }
+        vert
+    }
+
+    fn draw_vertices(&mut self, mode: GLenum, indices: &[GLuint], indexed: bool) {
+        let primitive_mode = match primitive_mode(mode) {
+            Some(primitive_mode) => primitive_mode,
+            _ => {
+                self.set_error(GL_INVALID_ENUM);
+                return;
+            }
+        };
+        if self.primitive_mode.is_some() {
+            self.set_error(GL_INVALID_OPERATION);
+            return;
+        }
+
+        if self.new_list.is_some() {
+            // Display lists capture array contents at compile time, so record the equivalent immediate-mode commands
+            self.issue(Command::Begin { mode });
+            for &index in indices.iter() {
+                self.issue_array_element(index as _);
+            }
+            self.issue(Command::End);
+            return;
+        }
+
+        if !self.vertex_arrays.vertex_array.enabled || indices.is_empty() {
+            return;
+        }
+
+        // Indexed vertices go through the post-transform cache, which only lives for the duration of a draw call since
+        // the transform state may change between calls
+        self.vertex_cache.invalidate();
+        let mut verts = Vec::with_capacity(indices.len());
+        for &index in indices.iter() {
+            let vert = self.transformed_array_vertex(index, indexed);
+            verts.push(vert);
+        }
+        self.assemble_primitives(primitive_mode, &verts);
+    }
+
+    // Decomposes clip-space vertices into points, lines and triangles. The provoking vertex (used for flat
+    // attributes) is the last vertex of each primitive, except for polygons, where it's the first.
+    fn assemble_primitives(&mut self, primitive_mode: PrimitiveMode, verts: &[Vertex]) {
+        // Trailing vertices that don't form a complete primitive are ignored
+        let num_verts = verts.len();
+        match primitive_mode {
+            PrimitiveMode::Points => {
+                for vert in verts.iter() {
+                    self.assemble_point(*vert, 0.0);
+                }
+            }
+            PrimitiveMode::Lines => {
+                for i in (0..num_verts / 2 * 2).step_by(2) {
+                    self.line_stipple_counter = 0.0;
+                    self.assemble_line([verts[i + 0], verts[i + 1]], verts[i + 1], 0.0);
+                }
+            }
+            PrimitiveMode::LineLoop | PrimitiveMode::LineStrip => {
+                if num_verts < 2 {
+                    return;
+                }
+                // The stipple pattern continues across connected segments
+                self.line_stipple_counter = 0.0;
+                for i in 0..num_verts - 1 {
+                    self.assemble_line([verts[i + 0], verts[i + 1]], verts[i + 1], 0.0);
+                }
+                if let PrimitiveMode::LineLoop = primitive_mode {
+                    self.assemble_line([verts[num_verts - 1], verts[0]], verts[0], 0.0);
+                }
+            }
+            // Edge flags mark which triangle edges (from vertex i to vertex i + 1) are polygon boundaries. They're only
+            // user-controllable for independent triangles, quads and polygons; the other modes draw every edge except
+            // the internal diagonals of quads.
+            PrimitiveMode::Triangles => {
+                for i in (0..num_verts / 3 * 3).step_by(3) {
+                    let edge_flags = [verts[i + 0].edge_flag, verts[i + 1].edge_flag, verts[i + 2].edge_flag];
+                    self.assemble_triangle([verts[i + 0], verts[i + 1], verts[i + 2]], edge_flags, verts[i + 2]);
+                }
+            }
+            PrimitiveMode::TriangleStrip => {
+                for i in 0..num_verts.saturating_sub(2) {
+                    // Every other triangle has its first two vertices swapped to preserve winding
+                    if i % 2 == 0 {
+                        self.assemble_triangle([verts[i + 0], verts[i + 1], verts[i + 2]], [true; 3], verts[i + 2]);
+                    } else {
+                        self.assemble_triangle([verts[i + 1], verts[i + 0], verts[i + 2]], [true; 3], verts[i + 2]);
+                    }
+                }
+            }
+            PrimitiveMode::TriangleFan => {
+                for i in 1..num_verts.saturating_sub(1) {
+                    self.assemble_triangle([verts[0], verts[i + 0], verts[i + 1]], [true; 3], verts[i + 1]);
+                }
+            }
+            PrimitiveMode::Quads => {
+                for i in (0..num_verts / 4 * 4).step_by(4) {
+                    self.assemble_triangle([verts[i + 0], verts[i + 1], verts[i + 2]], [verts[i + 0].edge_flag, verts[i + 1].edge_flag, false], verts[i + 3]);
+                    self.assemble_triangle([verts[i + 2], verts[i + 3], verts[i + 0]], [verts[i + 2].edge_flag, verts[i + 3].edge_flag, false], verts[i + 3]);
+                }
+            }
+            PrimitiveMode::QuadStrip => {
+                for i in (0..num_verts.saturating_sub(2) / 2 * 2).step_by(2) {
+                    self.assemble_triangle([verts[i + 0], verts[i + 1], verts[i + 3]], [true, true, false], verts[i + 3]);
+                    self.assemble_triangle([verts[i + 3], verts[i + 2], verts[i + 0]], [true, true, false], verts[i + 3]);
+                }
+            }
+            PrimitiveMode::Polygon => {
+                for i in 1..num_verts.saturating_sub(1) {
+                    let edge_flags = [
+                        i == 1 && verts[0].edge_flag,
+                        verts[i].edge_flag,
+                        i + 1 == num_verts - 1 && verts[i + 1].edge_flag,
+                    ];
+                    self.assemble_triangle([verts[0], verts[i + 0], verts[i + 1]], edge_flags, verts[0]);
+                }
+            }
+        }
+    }
 
-            modelview: Matrix::identity(),
-            projection: Matrix::identity(),
-            matrix_mode: MatrixMode::ModelView,
-            matrix_stack: Vec::new(),
+    fn window_position(&self, clip: Vec4) -> Vec2 {
+        let ndc = Vec2::new(clip.x(), clip.y()) / clip.w();
+        let viewport_scale = Vec2::new(self.viewport.width as f32 / 2.0, self.viewport.height as f32 / 2.0);
+        let viewport_bias = Vec2::new(self.viewport.x as f32, self.viewport.y as f32) + viewport_scale;
+        ndc * viewport_scale + viewport_bias
+    }
 
-            textures: Vec::new(),
-            texture_2d: 0,
-            texture_2d_enable: false,
+    // Moves a clip-space position by a window-space offset (in pixels)
+    fn offset_clip_position(&self, position: Vec4, dx: f32, dy: f32) -> Vec4 {
+        let ndc_dx = dx * 2.0 / self.viewport.width as f32;
+        let ndc_dy = dy * 2.0 / self.viewport.height as f32;
+        Vec4::new(position.x() + ndc_dx * position.w(), position.y() + ndc_dy * position.w(), position.z(), position.w())
+    }
 
-            primitive_mode: None,
+    // Points are rasterized as screen-aligned squares (GL_POINT_SMOOTH is ignored) so the rasterizer core only has to
+    // deal with triangles
+    fn assemble_point(&mut self, vert: Vertex, depth_offset: f32) {
+        // TODO: Proper clipping
+        if vert.position.w() <= 0.0 {
+            return;
+        }
 
-            current_normal: Vec3::zero(),
-            current_color: Vec4::new(0.0, 0.0, 0.0, 1.0),
-            current_tex_coord: Vec2::new(0.0, 0.0),
+        // Odd-sized points are centered on the pixel containing the vertex, and even-sized points on the nearest
+        // pixel corner, so the square always covers exactly size x size pixel centers
+        let size = self.point.point_size.round().max(1.0);
+        let window_position = self.window_position(vert.position);
+        let center = if size % 2.0 == 1.0 {
+            Vec2::new(window_position.x().floor() + 0.5, window_position.y().floor() + 0.5)
+        } else {
+            Vec2::new((window_position.x() + 0.5).floor(), (window_position.y() + 0.5).floor())
+        };
+        let min = center - window_position + -size / 2.0;
+        let max = center - window_position + size / 2.0;
+
+        let mut corners = [vert; 4];
+        corners[0].position = self.offset_clip_position(vert.position, min.x(), min.y());
+        corners[1].position = self.offset_clip_position(vert.position, max.x(), min.y());
+        corners[2].position = self.offset_clip_position(vert.position, max.x(), max.y());
+        corners[3].position = self.offset_clip_position(vert.position, min.x(), max.y());
+
+        if self.point.point_sprite_enable && self.point.point_sprite_coord_replace {
+            // Sprite coords have their origin in the upper left corner
+            corners[0].tex_coord = Vec2::new(0.0, 1.0);
+            corners[1].tex_coord = Vec2::new(1.0, 1.0);
+            corners[2].tex_coord = Vec2::new(1.0, 0.0);
+            corners[3].tex_coord = Vec2::new(0.0, 0.0);
+        }
 
-            verts: Vec::new(),
+        self.rasterize_triangle([corners[0], corners[1], corners[2]], vert, RasterPrimitive::Point, depth_offset);
+        self.rasterize_triangle([corners[2], corners[3], corners[0]], vert, RasterPrimitive::Point, depth_offset);
+    }
 
-            unpack_swap_bytes: 0,
-            unpack_lsb_first: 0,
-            unpack_row_length: 0,
-            unpack_skip_rows: 0,
-            unpack_skip_pixels: 0,
-            unpack_alignment: 4,
-            pack_swap_bytes: 0,
-            pack_lsb_first: 0,
-            pack_row_length: 0,
-            pack_skip_rows: 0,
-            pack_skip_pixels: 0,
-            pack_alignment: 4,
-
-            normal_array_enabled: false,
-            normal_pointer: ptr::null(),
-            normal_type: 0,
-            normal_stride: 0,
-
-            vertex_array_enabled: false,
-            vertex_pointer: ptr::null(),
-            vertex_size: 0,
-            vertex_type: 0,
-            vertex_stride: 0,
-
-            viewport_x: 0,
-            viewport_y: 0,
-            viewport_width: WIDTH as _,
-            viewport_height: HEIGHT as _,
+    // Lines are rasterized as quads, extruded along the window-space minor axis by the (integer) line width, so
+    // each column (or row) along the major axis gets exactly line_width fragments. The quad is capped at the outer
+    // edges of the first and last columns produced by the diamond-exit rule.
+    fn assemble_line(&mut self, verts: [Vertex; 2], provoking_vertex: Vertex, depth_offset: f32) {
+        let a = verts[0].position;
+        let b = verts[1].position;
+        // TODO: Proper clipping
+        if a.w() <= 0.0 || b.w() <= 0.0 {
+            return;
         }
-    }
 
-    fn current_matrix(&self) -> Matrix {
-        match self.matrix_mode {
-            MatrixMode::ModelView => self.modelview,
-            MatrixMode::Projection => self.projection,
+        let window_a = self.window_position(a);
+        let window_b = self.window_position(b);
+        let delta = window_b - window_a;
+        if delta.x() == 0.0 && delta.y() == 0.0 {
+            return;
         }
+
+        let half_width = self.line.line_width.round().max(1.0) / 2.0;
+        let (major, minor_axis) = if delta.x().abs() >= delta.y().abs() {
+            (Vec2::new(delta.x().signum(), 0.0), Vec2::new(0.0, 1.0))
+        } else {
+            (Vec2::new(0.0, delta.y().signum()), Vec2::new(1.0, 0.0))
+        };
+        let minor = minor_axis * half_width;
+
+        // Columns are indexed along the major axis, which may point in the negative direction
+        let major_a = window_a.dot(major);
+        let major_b = window_b.dot(major);
+        let (first_column, last_column) = match diamond_exit_columns(window_a, window_b, major, minor_axis) {
+            Some(columns) => columns,
+            _ => return,
+        };
+
+        // Slide the endpoints along the line to the caps
+        let cap_a = delta * ((first_column as f32 - major_a) / (major_b - major_a));
+        let cap_b = delta * ((last_column as f32 + 1.0 - major_b) / (major_b - major_a));
+
+        let mut corners = [verts[0], verts[1], verts[1], verts[0]];
+        corners[0].position = self.offset_clip_position(a, cap_a.x() - minor.x(), cap_a.y() - minor.y());
+        corners[1].position = self.offset_clip_position(b, cap_b.x() - minor.x(), cap_b.y() - minor.y());
+        corners[2].position = self.offset_clip_position(b, cap_b.x() + minor.x(), cap_b.y() + minor.y());
+        corners[3].position = self.offset_clip_position(a, cap_a.x() + minor.x(), cap_a.y() + minor.y());
+
+        // The stipple counter advances once per column, starting from the first cap
+        let primitive = RasterPrimitive::Line {
+            stipple_origin: major * (first_column as f32 - self.line_stipple_counter),
+            stipple_direction: major,
+        };
+        self.rasterize_triangle([corners[0], corners[1], corners[2]], provoking_vertex, primitive, depth_offset);
+        self.rasterize_triangle([corners[2], corners[3], corners[0]], provoking_vertex, primitive, depth_offset);
+
+        self.line_stipple_counter += (last_column - first_column + 1) as f32;
     }
 
-    fn set_current_matrix(&mut self, m: Matrix) {
-        match self.matrix_mode {
-            MatrixMode::ModelView => {
-                self.modelview = m;
-            }
-            MatrixMode::Projection => {
-                self.projection = m;
+    fn assemble_triangle(&mut self, verts: [Vertex; 3], edge_flags: [bool; 3], provoking_vertex: Vertex) {
+        // TODO: Proper clipping
+        for vert in verts.iter() {
+            if vert.position.w() <= 0.0 {
+                return;
             }
         }
-    }
 
-    fn multiply_current_matrix(&mut self, m: Matrix) {
-        self.set_current_matrix(self.current_matrix() * m);
-    }
+        let window_verts = [
+            self.window_position(verts[0].position),
+            self.window_position(verts[1].position),
+            self.window_position(verts[2].position),
+        ];
+        let edge_a = window_verts[1] - window_verts[0];
+        let edge_b = window_verts[2] - window_verts[0];
+        let signed_area = edge_a.x() * edge_b.y() - edge_a.y() * edge_b.x();
+        let is_front_facing = match self.polygon.front_face {
+            FrontFace::Ccw => signed_area >= 0.0,
+            FrontFace::Cw => signed_area < 0.0,
+        };
 
-    fn array_element(&mut self, index: GLint) {
-        if self.normal_array_enabled {
-            // TODO: Properly handle type, stride
-            let normal_buffer = self.normal_pointer as *const GLfloat;
-            unsafe {
-                let normal = normal_buffer.add((index * 3) as usize);
-                let nx = *normal.add(0);
-                let ny = *normal.add(1);
-                let nz = *normal.add(2);
-                self.issue(Command::Normal3f { nx, ny, nz });
+        let polygon_mode = if is_front_facing {
+            self.polygon.polygon_mode_front
+        } else {
+            self.polygon.polygon_mode_back
+        };
+
+        // The offset comes from the polygon's depth slope, so its points and lines in GL_POINT/GL_LINE mode are offset
+        // the same as its filled interior would be. The units term is scaled by the smallest resolvable difference in
+        // the 16-bit depth buffer.
+        let polygon_offset_enable = match polygon_mode {
+            PolygonMode::Point => self.polygon.polygon_offset_point_enable,
+            PolygonMode::Line => self.polygon.polygon_offset_line_enable,
+            PolygonMode::Fill => self.polygon.polygon_offset_fill_enable,
+        };
+        let depth_offset = if polygon_offset_enable {
+            let window_z = |clip: Vec4| clip.z() / clip.w() * 0.5 + 0.5;
+            let z_a = window_z(verts[1].position) - window_z(verts[0].position);
+            let z_b = window_z(verts[2].position) - window_z(verts[0].position);
+            let max_depth_slope = if signed_area != 0.0 {
+                let z_dx = (z_a * edge_b.y() - z_b * edge_a.y()) / signed_area;
+                let z_dy = (z_b * edge_a.x() - z_a * edge_b.x()) / signed_area;
+                z_dx.abs().max(z_dy.abs())
+            } else {
+                0.0
+            };
+            self.polygon.polygon_offset_factor * max_depth_slope + self.polygon.polygon_offset_units / (1 << 16) as f32
+        } else {
+            0.0
+        };
+
+        match polygon_mode {
+            PolygonMode::Point => {
+                for i in 0..3 {
+                    if edge_flags[i] {
+                        self.assemble_point(verts[i], depth_offset);
+                    }
+                }
             }
-        }
-        if self.vertex_array_enabled {
-            // TODO: Properly handle size, type, stride
-            let vertex_buffer = self.vertex_pointer as *const GLfloat;
-            unsafe {
-                let vertex = vertex_buffer.add((index * self.vertex_size) as usize);
-                let x = *vertex.add(0);
-                let y = *vertex.add(1);
-                let z = *vertex.add(2);
-                self.issue(Command::Vertex3f { x, y, z });
+            PolygonMode::Line => {
+                for i in 0..3 {
+                    if edge_flags[i] {
+                        self.line_stipple_counter = 0.0;
+                        self.assemble_line([verts[i], verts[(i + 1) % 3]], provoking_vertex, depth_offset);
+                    }
+                }
+            }
+            PolygonMode::Fill => {
+                self.rasterize_triangle(verts, provoking_vertex, RasterPrimitive::Triangle, depth_offset);
             }
         }
     }
 
-    fn assemble_triangle(&mut self, mut verts: [Vertex; 3]) {
+    fn rasterize_triangle(&mut self, mut verts: [Vertex; 3], provoking_vertex: Vertex, primitive: RasterPrimitive, depth_offset: f32) {
         // TODO: Clipping, culling, ...
         for vert in verts.iter() {
             if vert.position.z() < -vert.position.w() || vert.position.z() > vert.position.w() {
@@ -510,8 +2525,8 @@ impl Context {
             let ndc = Vec3::new(clip.x(), clip.y(), clip.z()) / clip.w();
             let viewport_near = 0.0;
             let viewport_far = 1.0;
-            let viewport_scale = Vec3::new(self.viewport_width as f32 / 2.0, self.viewport_height as f32 / 2.0, (viewport_far - viewport_near) / 2.0);
-            let viewport_bias = Vec3::new(self.viewport_x as f32 + self.viewport_width as f32 / 2.0, self.viewport_y as f32 + self.viewport_height as f32 / 2.0, (viewport_far + viewport_near) / 2.0);
+            let viewport_scale = Vec3::new(self.viewport.width as f32 / 2.0, self.viewport.height as f32 / 2.0, (viewport_far - viewport_near) / 2.0);
+            let viewport_bias = Vec3::new(self.viewport.x as f32 + self.viewport.width as f32 / 2.0, self.viewport.y as f32 + self.viewport.height as f32 / 2.0, (viewport_far + viewport_near) / 2.0);
             window_verts[i] = ndc * viewport_scale + viewport_bias;
         }
 
@@ -535,8 +2550,7 @@ impl Context {
             scaled_area = -scaled_area;
         }
 
-        let texture_dims = if self.texture_2d_enable && (self.texture_2d as usize) < self.textures.len() {
-            let texture = &self.textures[self.texture_2d as usize];
+        let texture_dims = if let Some(texture) = self.enabled_texture_2d() {
             Vec2::new(texture.width as f32, texture.height as f32)
         } else {
             Vec2::zero()
@@ -546,14 +2560,25 @@ impl Context {
             verts[i].tex_coord = (verts[i].tex_coord * texture_dims + st_bias) / verts[i].position.w();
         }
 
+        if let ShadeModel::Flat = self.lighting.shade_model {
+            for vert in verts.iter_mut() {
+                vert.color = provoking_vertex.color;
+                vert.secondary_color = provoking_vertex.secondary_color;
+            }
+        }
+        for i in 0..verts.len() {
+            verts[i].color = verts[i].color.max(Vec4::zero()).min(Vec4::splat(1.0)) / verts[i].position.w();
+            verts[i].secondary_color = verts[i].secondary_color.max(Vec3::zero()).min(Vec3::splat(1.0)) / verts[i].position.w();
+        }
+
         let mut bb_min = Vec2::new(window_verts[0].x(), window_verts[0].y());
         let mut bb_max = bb_min;
         for i in 1..verts.len() {
             bb_min = bb_min.min(Vec2::new(window_verts[i].x(), window_verts[i].y()));
             bb_max = bb_max.max(Vec2::new(window_verts[i].x(), window_verts[i].y()));
         }
-        bb_min = bb_min.max(Vec2::new(self.viewport_x as f32, self.viewport_y as f32));
-        bb_max = bb_max.min(Vec2::new((self.viewport_x + self.viewport_width as i32 - 1) as f32, (self.viewport_y + self.viewport_height as i32 - 1) as f32));
+        bb_min = bb_min.max(Vec2::new(self.viewport.x as f32, self.viewport.y as f32));
+        bb_max = bb_max.min(Vec2::new((self.viewport.x + self.viewport.width as i32 - 1) as f32, (self.viewport.y + self.viewport.height as i32 - 1) as f32));
         bb_min = bb_min.max(Vec2::zero());
         bb_max = bb_max.min(Vec2::new((WIDTH - 1) as f32, (HEIGHT - 1) as f32));
         let bb_min_x = bb_min.x().floor() as i32;
@@ -565,8 +2590,7 @@ impl Context {
         let mut color_thrust = ColorThrust::new();
 
         // TODO: if texturing isn't enabled, upload 2x2 white texture
-        if self.texture_2d_enable && (self.texture_2d as usize) < self.textures.len() {
-            let texture = &self.textures[self.texture_2d as usize];
+        if let Some(texture) = self.enabled_texture_2d() {
             for y in 0..texture.height {
                 for x in 0..texture.width {
                     let buffer_index = y * texture.width + x;
@@ -584,13 +2608,27 @@ impl Context {
         }
 
         // TODO: This only needs to happen once per drawcall, not once per primitive
-        color_thrust.depth_test_enable = self.depth_test;
-        color_thrust.depth_mask_enable = self.depth_mask;
+        color_thrust.depth_test_enable = self.depth.depth_test;
+        color_thrust.depth_mask_enable = self.depth.depth_mask;
+        color_thrust.alpha_test_enable = self.color_buffer.alpha_test_enable;
+        color_thrust.alpha_test_func = self.color_buffer.alpha_test_func;
+        color_thrust.alpha_test_ref = (self.color_buffer.alpha_test_ref * 255.0).round() as _;
+        color_thrust.color_mask = self.color_mask();
+        // Separate specular color implies the color sum while lighting is enabled
+        color_thrust.color_sum_enable = self.fog.color_sum_enable || (self.lighting.lighting_enable && self.lighting.light_model_color_control == GL_SEPARATE_SPECULAR_COLOR);
+
+        if let RasterPrimitive::Line { stipple_direction, .. } = primitive {
+            color_thrust.line_stipple_enable = self.line.line_stipple_enable;
+            color_thrust.line_stipple_pattern = self.line.line_stipple_pattern;
+            color_thrust.line_stipple_factor = self.line.line_stipple_factor as _;
+            color_thrust.stipple_dx = to_fixed(stipple_direction.x(), STIPPLE_FRACT_BITS);
+            color_thrust.stipple_dy = to_fixed(stipple_direction.y(), STIPPLE_FRACT_BITS);
+        }
 
         // TODO: This only needs to happen once per drawcall, not once per primitive
-        if self.blend_enable {
-            color_thrust.blend_src_factor = self.blend_src_factor;
-            color_thrust.blend_dst_factor = self.blend_dst_factor;
+        if self.color_buffer.blend_enable {
+            color_thrust.blend_src_factor = self.color_buffer.blend_src_factor;
+            color_thrust.blend_dst_factor = self.color_buffer.blend_dst_factor;
         } else {
             color_thrust.blend_src_factor = BlendSrcFactor::One;
             color_thrust.blend_dst_factor = BlendDstFactor::Zero;
@@ -649,6 +2687,9 @@ impl Context {
         let z_dy = window_verts[0].z() * w0_dy + window_verts[1].z() * w1_dy + window_verts[2].z() * w2_dy;
         color_thrust.z_dx = to_fixed(z_dx, Z_FRACT_BITS);
         color_thrust.z_dy = to_fixed(z_dy, Z_FRACT_BITS);
+        // The offset is applied after interpolation in depth buffer units, so it can't overflow the interpolated depth.
+        // Anything beyond +/-1 pushes every fragment to the same end of the depth range, so it's clamped there first.
+        color_thrust.depth_offset = (depth_offset.max(-1.0).min(1.0) * (1 << 16) as f32) as i32;
 
         let s_dx = verts[0].tex_coord.x() * w0_dx + verts[1].tex_coord.x() * w1_dx + verts[2].tex_coord.x() * w2_dx;
         let t_dx = verts[0].tex_coord.y() * w0_dx + verts[1].tex_coord.y() * w1_dx + verts[2].tex_coord.y() * w2_dx;
@@ -659,7 +2700,25 @@ impl Context {
         color_thrust.s_dy = to_fixed(s_dy, ST_FRACT_BITS);
         color_thrust.t_dy = to_fixed(t_dy, ST_FRACT_BITS);
 
-        color_thrust.color = verts[0].color;
+        let color_dx = verts[0].color * w0_dx + verts[1].color * w1_dx + verts[2].color * w2_dx;
+        let color_dy = verts[0].color * w0_dy + verts[1].color * w1_dy + verts[2].color * w2_dy;
+        color_thrust.r_dx = to_fixed(color_dx.x(), COLOR_FRACT_BITS);
+        color_thrust.g_dx = to_fixed(color_dx.y(), COLOR_FRACT_BITS);
+        color_thrust.b_dx = to_fixed(color_dx.z(), COLOR_FRACT_BITS);
+        color_thrust.a_dx = to_fixed(color_dx.w(), COLOR_FRACT_BITS);
+        color_thrust.r_dy = to_fixed(color_dy.x(), COLOR_FRACT_BITS);
+        color_thrust.g_dy = to_fixed(color_dy.y(), COLOR_FRACT_BITS);
+        color_thrust.b_dy = to_fixed(color_dy.z(), COLOR_FRACT_BITS);
+        color_thrust.a_dy = to_fixed(color_dy.w(), COLOR_FRACT_BITS);
+
+        let secondary_color_dx = verts[0].secondary_color * w0_dx + verts[1].secondary_color * w1_dx + verts[2].secondary_color * w2_dx;
+        let secondary_color_dy = verts[0].secondary_color * w0_dy + verts[1].secondary_color * w1_dy + verts[2].secondary_color * w2_dy;
+        color_thrust.secondary_r_dx = to_fixed(secondary_color_dx.x(), COLOR_FRACT_BITS);
+        color_thrust.secondary_g_dx = to_fixed(secondary_color_dx.y(), COLOR_FRACT_BITS);
+        color_thrust.secondary_b_dx = to_fixed(secondary_color_dx.z(), COLOR_FRACT_BITS);
+        color_thrust.secondary_r_dy = to_fixed(secondary_color_dy.x(), COLOR_FRACT_BITS);
+        color_thrust.secondary_g_dy = to_fixed(secondary_color_dy.y(), COLOR_FRACT_BITS);
+        color_thrust.secondary_b_dy = to_fixed(secondary_color_dy.z(), COLOR_FRACT_BITS);
 
         for tile_index_y in 0..HEIGHT / TILE_DIM {
             let tile_min_y = (tile_index_y * TILE_DIM) as i32;
@@ -677,15 +2736,7 @@ impl Context {
                     continue;
                 }
 
-                // Copy tile into rasterizer memory
-                for y in 0..TILE_DIM {
-                    for x in 0..TILE_DIM {
-                        let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x;
-                        let tile_buffer_index = y * TILE_DIM + x;
-                        color_thrust.color_buffer[tile_buffer_index] = self.back_buffer[buffer_index];
-                        color_thrust.depth_buffer[tile_buffer_index] = self.depth_buffer[buffer_index];
-                    }
-                }
+                self.load_tile(&mut color_thrust, tile_min_x, tile_min_y);
 
                 let p = Vec2::new(tile_min_x as f32, tile_min_y as f32) + 0.5; // Offset to sample pixel centers
 
@@ -712,216 +2763,1003 @@ impl Context {
                 color_thrust.s_min = to_fixed(s_min, ST_FRACT_BITS);
                 color_thrust.t_min = to_fixed(t_min, ST_FRACT_BITS);
 
+                let color_min = verts[0].color * w0_min + verts[1].color * w1_min + verts[2].color * w2_min;
+                color_thrust.r_min = to_fixed(color_min.x(), COLOR_FRACT_BITS);
+                color_thrust.g_min = to_fixed(color_min.y(), COLOR_FRACT_BITS);
+                color_thrust.b_min = to_fixed(color_min.z(), COLOR_FRACT_BITS);
+                color_thrust.a_min = to_fixed(color_min.w(), COLOR_FRACT_BITS);
+
+                let secondary_color_min = verts[0].secondary_color * w0_min + verts[1].secondary_color * w1_min + verts[2].secondary_color * w2_min;
+                color_thrust.secondary_r_min = to_fixed(secondary_color_min.x(), COLOR_FRACT_BITS);
+                color_thrust.secondary_g_min = to_fixed(secondary_color_min.y(), COLOR_FRACT_BITS);
+                color_thrust.secondary_b_min = to_fixed(secondary_color_min.z(), COLOR_FRACT_BITS);
+
+                if let RasterPrimitive::Line { stipple_origin, stipple_direction } = primitive {
+                    let stipple_min = (p - stipple_origin).dot(stipple_direction);
+                    color_thrust.stipple_min = to_fixed(stipple_min, STIPPLE_FRACT_BITS);
+                }
+
                 color_thrust.rasterize_primitive();
 
-                // Copy rasterizer memory back to tile
+                self.store_tile(&color_thrust, tile_min_x, tile_min_y);
+            }
+        }
+    }
+
+    // Copies a tile into rasterizer memory
+    fn load_tile(&mut self, color_thrust: &mut ColorThrust, tile_min_x: i32, tile_min_y: i32) {
+        for y in 0..TILE_DIM {
+            for x in 0..TILE_DIM {
+                let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x;
+                let tile_buffer_index = y * TILE_DIM + x;
+                color_thrust.color_buffer[tile_buffer_index] = self.back_buffer[buffer_index];
+                color_thrust.depth_buffer[tile_buffer_index] = self.depth_buffer[buffer_index];
+            }
+        }
+        self.memory_traffic.tile_loads += TILE_PIXELS * (mem::size_of::<u32>() + mem::size_of::<u16>());
+    }
+
+    // Copies rasterizer memory back to a tile
+    fn store_tile(&mut self, color_thrust: &ColorThrust, tile_min_x: i32, tile_min_y: i32) {
+        for y in 0..TILE_DIM {
+            for x in 0..TILE_DIM {
+                let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x;
+                let tile_buffer_index = y * TILE_DIM + x;
+                self.back_buffer[buffer_index] = color_thrust.color_buffer[tile_buffer_index];
+                self.depth_buffer[buffer_index] = color_thrust.depth_buffer[tile_buffer_index];
+            }
+        }
+        self.memory_traffic.tile_stores += TILE_PIXELS * (mem::size_of::<u32>() + mem::size_of::<u16>());
+    }
+
+    // Rasterizes a width x height pixel rectangle (bitmap or image) whose source pixel (x, y) covers the window area
+    // origin + scale * [x, x + 1) x [y, y + 1). scale can be negative, which flips the rectangle. fragment returns the
+    // color for a source pixel, or None if it produces no fragment. Fragments get depth z and go through the usual
+    // per-fragment operations.
+    fn rasterize_pixel_rectangle<F: Fn(usize, usize) -> Option<u32>>(&mut self, origin: Vec2, scale: Vec2, width: usize, height: usize, z: f32, fragment: F) {
+        if width == 0 || height == 0 || scale.x() == 0.0 || scale.y() == 0.0 {
+            return;
+        }
+
+        let corner = origin + scale * Vec2::new(width as f32, height as f32);
+        let bb_min = origin.min(corner).max(Vec2::zero());
+        let bb_max = origin.max(corner).min(Vec2::new(WIDTH as f32, HEIGHT as f32));
+        if bb_min.x() >= bb_max.x() || bb_min.y() >= bb_max.y() {
+            return;
+        }
+        let bb_min_x = bb_min.x().floor() as i32;
+        let bb_min_y = bb_min.y().floor() as i32;
+        let bb_max_x = bb_max.x().ceil() as i32 - 1;
+        let bb_max_y = bb_max.y().ceil() as i32 - 1;
+
+        let mut color_thrust = ColorThrust::new();
+        color_thrust.pixel_rectangle_enable = true;
+        color_thrust.depth_test_enable = self.depth.depth_test;
+        color_thrust.depth_mask_enable = self.depth.depth_mask;
+        color_thrust.alpha_test_enable = self.color_buffer.alpha_test_enable;
+        color_thrust.alpha_test_func = self.color_buffer.alpha_test_func;
+        color_thrust.alpha_test_ref = (self.color_buffer.alpha_test_ref * 255.0).round() as _;
+        color_thrust.color_mask = self.color_mask();
+        if self.color_buffer.blend_enable {
+            color_thrust.blend_src_factor = self.color_buffer.blend_src_factor;
+            color_thrust.blend_dst_factor = self.color_buffer.blend_dst_factor;
+        } else {
+            color_thrust.blend_src_factor = BlendSrcFactor::One;
+            color_thrust.blend_dst_factor = BlendDstFactor::Zero;
+        }
+        color_thrust.z_min = ((z.max(0.0).min(1.0) * 65535.0) as i32) << (Z_FRACT_BITS - 16);
+
+        for tile_index_y in (bb_min_y as usize / TILE_DIM)..=(bb_max_y as usize / TILE_DIM) {
+            let tile_min_y = (tile_index_y * TILE_DIM) as i32;
+            for tile_index_x in (bb_min_x as usize / TILE_DIM)..=(bb_max_x as usize / TILE_DIM) {
+                let tile_min_x = (tile_index_x * TILE_DIM) as i32;
+
+                self.load_tile(&mut color_thrust, tile_min_x, tile_min_y);
+
                 for y in 0..TILE_DIM {
                     for x in 0..TILE_DIM {
-                        let buffer_index = (HEIGHT - 1 - (tile_min_y as usize + y)) * WIDTH + tile_min_x as usize + x;
+                        // Sample at pixel centers
+                        let p = Vec2::new((tile_min_x + x as i32) as f32, (tile_min_y + y as i32) as f32) + 0.5;
+                        let source = (p - origin) / scale;
+                        let source_x = source.x().floor();
+                        let source_y = source.y().floor();
+                        let color = if source_x >= 0.0 && source_x < width as f32 && source_y >= 0.0 && source_y < height as f32 {
+                            fragment(source_x as usize, source_y as usize)
+                        } else {
+                            None
+                        };
                         let tile_buffer_index = y * TILE_DIM + x;
-                        self.back_buffer[buffer_index] = color_thrust.color_buffer[tile_buffer_index];
-                        self.depth_buffer[buffer_index] = color_thrust.depth_buffer[tile_buffer_index];
+                        color_thrust.pixel_coverage[tile_buffer_index] = color.is_some();
+                        color_thrust.pixel_colors[tile_buffer_index] = color.unwrap_or(0);
                     }
                 }
+
+                color_thrust.rasterize_primitive();
+
+                self.store_tile(&color_thrust, tile_min_x, tile_min_y);
             }
         }
     }
 
-    fn disable_client_state(&mut self, array: GLenum) {
-        match array {
-            GL_VERTEX_ARRAY => {
-                self.vertex_array_enabled = false;
+    fn call_list(&mut self, list: GLuint) {
+        // Calling an undefined list is silently ignored. The list is cloned out of the map so that it stays alive even
+        // if it's redefined or deleted by one of its own commands.
+        if let Some(display_list) = self.display_lists.get(&list).cloned() {
+            for op in display_list.borrow().ops.iter() {
+                match *op {
+                    DisplayListOp::Command(ref command) => self.execute(command),
+                    DisplayListOp::Batch(ref batch) => self.execute_batch(batch),
+                }
             }
-            GL_NORMAL_ARRAY => {
-                self.normal_array_enabled = false;
+        }
+    }
+
+    unsafe fn call_lists(&mut self, n: GLsizei, type_: GLenum, lists: *const GLvoid) {
+        // GLsizei is unsigned here, so negative sizes show up wrapped around
+        if (n as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        let n = n as usize;
+        // Names are offset by the list base at execution time, so signed offsets are kept as their two's complement
+        // and wrap around when added
+        let lists = match type_ {
+            GL_BYTE => slice::from_raw_parts(lists as *const i8, n).iter().map(|&list| list as GLuint).collect(),
+            GL_UNSIGNED_BYTE => slice::from_raw_parts(lists as *const u8, n).iter().map(|&list| list as GLuint).collect(),
+            GL_SHORT => slice::from_raw_parts(lists as *const i16, n).iter().map(|&list| list as GLuint).collect(),
+            GL_UNSIGNED_SHORT => slice::from_raw_parts(lists as *const u16, n).iter().map(|&list| list as GLuint).collect(),
+            GL_INT => slice::from_raw_parts(lists as *const i32, n).iter().map(|&list| list as GLuint).collect(),
+            GL_UNSIGNED_INT => slice::from_raw_parts(lists as *const u32, n).to_vec(),
+            GL_FLOAT => slice::from_raw_parts(lists as *const f32, n).iter().map(|&list| list as GLint as GLuint).collect(),
+            GL_2_BYTES | GL_3_BYTES | GL_4_BYTES => {
+                // Big-endian byte sequences, regardless of the host byte order
+                let bytes_per_list = (type_ - GL_2_BYTES + 2) as usize;
+                slice::from_raw_parts(lists as *const u8, n * bytes_per_list)
+                    .chunks(bytes_per_list)
+                    .map(|bytes| bytes.iter().fold(0, |list, &byte| (list << 8) | byte as GLuint))
+                    .collect()
             }
-            _ => panic!("DisableClientState called with invalid array: 0x{:08x}", array)
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glCallLists called with invalid type: 0x{:08x}", type_)
+        };
+        self.issue(Command::CallLists { lists });
+    }
+
+    fn client_active_texture(&mut self, texture: GLenum) {
+        if texture >= GL_TEXTURE0_ARB && texture < GL_TEXTURE0_ARB + MAX_TEXTURE_UNITS as GLenum {
+            self.vertex_arrays.client_active_texture = (texture - GL_TEXTURE0_ARB) as _;
+        } else {
+            invalid_call!(self, GL_INVALID_ENUM, "ClientActiveTextureARB called with invalid texture: 0x{:08x}", texture);
         }
     }
 
-    fn enable_client_state(&mut self, array: GLenum) {
+    fn client_array(&mut self, array: GLenum) -> Option<&mut ClientArray> {
         match array {
-            GL_VERTEX_ARRAY => {
-                self.vertex_array_enabled = true;
+            GL_VERTEX_ARRAY => Some(&mut self.vertex_arrays.vertex_array),
+            GL_NORMAL_ARRAY => Some(&mut self.vertex_arrays.normal_array),
+            GL_COLOR_ARRAY => Some(&mut self.vertex_arrays.color_array),
+            GL_SECONDARY_COLOR_ARRAY_EXT => Some(&mut self.vertex_arrays.secondary_color_array),
+            GL_TEXTURE_COORD_ARRAY => Some(&mut self.vertex_arrays.tex_coord_arrays[self.vertex_arrays.client_active_texture]),
+            GL_EDGE_FLAG_ARRAY => Some(&mut self.vertex_arrays.edge_flag_array),
+            _ => None
+        }
+    }
+
+    fn color_pointer(&mut self, size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        match size {
+            3 | 4 => (),
+            _ => invalid_call!(self, GL_INVALID_VALUE, "ColorPointer called with invalid size: {}", size)
+        }
+        match type_ {
+            GL_BYTE | GL_UNSIGNED_BYTE | GL_SHORT | GL_UNSIGNED_SHORT | GL_INT | GL_UNSIGNED_INT | GL_FLOAT | GL_DOUBLE => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "ColorPointer called with invalid type: 0x{:08x}", type_)
+        }
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "ColorPointer called with negative stride: {}", stride as GLint);
+        }
+        self.vertex_arrays.color_array.set_pointer(size, type_, stride, pointer);
+    }
+
+    fn secondary_color_pointer(&mut self, size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        if size != 3 {
+            invalid_call!(self, GL_INVALID_VALUE, "SecondaryColorPointerEXT called with invalid size: {}", size);
+        }
+        match type_ {
+            GL_BYTE | GL_UNSIGNED_BYTE | GL_SHORT | GL_UNSIGNED_SHORT | GL_INT | GL_UNSIGNED_INT | GL_FLOAT | GL_DOUBLE => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "SecondaryColorPointerEXT called with invalid type: 0x{:08x}", type_)
+        }
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "SecondaryColorPointerEXT called with negative stride: {}", stride as GLint);
+        }
+        self.vertex_arrays.secondary_color_array.set_pointer(size, type_, stride, pointer);
+    }
+
+    unsafe fn are_textures_resident(&mut self, n: GLsizei, textures: *const GLuint, _residences: *mut GLboolean) -> GLboolean {
+        if (n as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return GL_FALSE;
+        }
+        // Everything lives in system memory, so every texture is always resident and residences is left untouched
+        for &texture in slice::from_raw_parts(textures, n as _) {
+            if texture == 0 || !self.textures.contains_key(&texture) {
+                self.set_error(GL_INVALID_VALUE);
+                return GL_FALSE;
+            }
+        }
+        GL_TRUE
+    }
+
+    unsafe fn bitmap(&mut self, width: GLsizei, height: GLsizei, xorig: GLfloat, yorig: GLfloat, xmove: GLfloat, ymove: GLfloat, bitmap: *const GLubyte) {
+        if (width as GLint) < 0 || (height as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        // A null bitmap is allowed and just moves the raster position
+        let bitmap = if bitmap.is_null() {
+            vec![false; (width * height) as usize]
+        } else {
+            self.unpack_bitmap(width as _, height as _, bitmap)
+        };
+        self.issue(Command::Bitmap { width, height, xorig, yorig, xmove, ymove, bitmap });
+    }
+
+    fn bound_texture_2d_mut(&mut self) -> &mut Texture {
+        self.textures.get_mut(&self.texture.texture_2d).unwrap()
+    }
+
+    fn copy_tex_image_2d(&mut self, target: GLenum, level: GLint, internalformat: GLenum, x: GLint, y: GLint, width: GLsizei, height: GLsizei, border: GLint) {
+        if target != GL_TEXTURE_2D {
+            invalid_call!(self, GL_INVALID_ENUM, "glCopyTexImage2D called with invalid target: 0x{:08x}", target);
+        }
+
+        if level > 0 {
+            println!("Skipping glCopyTexImage2D call with level > 0, level: 0x{:08x}", level);
+            return;
+        }
+
+        if border != 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "glCopyTexImage2D called with border != 0, border: 0x{:08x}", border);
+        }
+
+        if (width as GLint) < 0 || (height as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "glCopyTexImage2D called with negative size: {}x{}", width as GLint, height as GLint);
+        }
+
+        // Textures are always stored as RGBA, so only the RGB(A) formats can be copied into (the spec doesn't allow the
+        // 1-4 component counts here)
+        match internalformat {
+            GL_RGB | GL_RGBA => (),
+            _ => invalid_call!(self, GL_INVALID_VALUE, "glCopyTexImage2D called with unsupported internalformat: 0x{:08x}", internalformat)
+        }
+
+        let texture_data = self.read_framebuffer(x, y, width as _, height as _);
+        self.set_texture_image(internalformat as _, width, height, texture_data);
+    }
+
+    fn copy_tex_sub_image_2d(&mut self, target: GLenum, level: GLint, xoffset: GLint, yoffset: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        if target != GL_TEXTURE_2D {
+            invalid_call!(self, GL_INVALID_ENUM, "glCopyTexSubImage2D called with invalid target: 0x{:08x}", target);
+        }
+
+        if level > 0 {
+            println!("Skipping glCopyTexSubImage2D call with level > 0, level: 0x{:08x}", level);
+            return;
+        }
+
+        if !self.is_valid_texture_region(xoffset, yoffset, width, height) {
+            return;
+        }
+
+        let region = self.read_framebuffer(x, y, width as _, height as _);
+        self.set_texture_region(xoffset as _, yoffset as _, width as _, height as _, &region);
+    }
+
+    fn delete_lists(&mut self, list: GLuint, range: GLsizei) {
+        if (range as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        self.display_lists.retain(|&name, _| name < list || name - list >= range);
+    }
+
+    unsafe fn delete_textures(&mut self, n: GLsizei, textures: *const GLuint) {
+        if (n as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        for &texture in slice::from_raw_parts(textures, n as _) {
+            // The default texture can't be deleted, and unused names are silently ignored
+            if texture == 0 {
+                continue;
+            }
+            if self.textures.remove(&texture).is_some() && self.texture.texture_2d == texture {
+                self.texture.texture_2d = 0;
+            }
+        }
+    }
+
+    fn disable_client_state(&mut self, array: GLenum) {
+        match self.client_array(array) {
+            Some(client_array) => {
+                client_array.enabled = false;
+            }
+            _ => invalid_call!(self, GL_INVALID_ENUM, "DisableClientState called with invalid array: 0x{:08x}", array)
+        }
+    }
+
+    fn draw_arrays(&mut self, mode: GLenum, first: GLint, count: GLsizei) {
+        if first < 0 || (count as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        let end = match (first as GLuint).checked_add(count) {
+            Some(end) => end,
+            _ => {
+                self.set_error(GL_INVALID_VALUE);
+                return;
+            }
+        };
+        let indices = (first as GLuint..end).collect::<Vec<_>>();
+        self.draw_vertices(mode, &indices, false);
+    }
+
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, type_: GLenum, indices: *const GLvoid) {
+        if (count as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        let indices = unsafe {
+            match type_ {
+                GL_UNSIGNED_BYTE => slice::from_raw_parts(indices as *const u8, count as _).iter().map(|&index| index as GLuint).collect::<Vec<_>>(),
+                GL_UNSIGNED_SHORT => slice::from_raw_parts(indices as *const u16, count as _).iter().map(|&index| index as GLuint).collect::<Vec<_>>(),
+                GL_UNSIGNED_INT => slice::from_raw_parts(indices as *const u32, count as _).to_vec(),
+                _ => {
+                    self.set_error(GL_INVALID_ENUM);
+                    return;
+                }
             }
-            GL_NORMAL_ARRAY => {
-                self.normal_array_enabled = true;
+        };
+        self.draw_vertices(mode, &indices, true);
+    }
+
+    unsafe fn draw_pixels(&mut self, width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, pixels: *const GLvoid) {
+        if (width as GLint) < 0 || (height as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        let num_components = match format {
+            GL_RGB => 3,
+            GL_RGBA => 4,
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glDrawPixels called with invalid format: 0x{:08x}", format)
+        };
+        match type_ {
+            GL_UNSIGNED_BYTE | GL_UNSIGNED_SHORT => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glDrawPixels called with invalid type: 0x{:08x}", type_)
+        }
+        let pixels = self.unpack_image(width as _, height as _, num_components, type_, pixels);
+        self.issue(Command::DrawPixels { width, height, pixels });
+    }
+
+    fn draw_range_elements(&mut self, mode: GLenum, start: GLuint, end: GLuint, count: GLsizei, type_: GLenum, indices: *const GLvoid) {
+        if end < start || (count as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        // The range is only a hint; draw_vertices derives the exact range from the indices anyway
+        self.draw_elements(mode, count, type_, indices);
+    }
+
+    fn edge_flag_pointer(&mut self, stride: GLsizei, pointer: *const GLvoid) {
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "EdgeFlagPointer called with negative stride: {}", stride as GLint);
+        }
+        self.vertex_arrays.edge_flag_array.set_pointer(1, GL_UNSIGNED_BYTE, stride, pointer);
+    }
+
+    fn enable_client_state(&mut self, array: GLenum) {
+        match self.client_array(array) {
+            Some(client_array) => {
+                client_array.enabled = true;
             }
-            _ => panic!("EnableClientState called with invalid array: 0x{:08x}", array)
+            _ => invalid_call!(self, GL_INVALID_ENUM, "EnableClientState called with invalid array: 0x{:08x}", array)
+        }
+    }
+
+    // The bound texture if texturing is enabled and the texture has an image; otherwise texturing is effectively disabled
+    fn enabled_texture_2d(&self) -> Option<&Texture> {
+        if !self.texture.texture_2d_enable {
+            return None;
         }
+        let texture = &self.textures[&self.texture.texture_2d];
+        if texture.data.is_empty() {
+            return None;
+        }
+        Some(texture)
+    }
+
+    // The color write mask packed to match the ARGB color buffer layout
+    fn color_mask(&self) -> u32 {
+        let channel_mask = |enabled: bool, shift: u32| if enabled { 0xff << shift } else { 0 };
+        channel_mask(self.color_buffer.color_mask_alpha, 24) |
+        channel_mask(self.color_buffer.color_mask_red, 16) |
+        channel_mask(self.color_buffer.color_mask_green, 8) |
+        channel_mask(self.color_buffer.color_mask_blue, 0)
+    }
+
+    // Returns whether a capability is enabled, or None if cap isn't one we track
+    fn enabled(&self, cap: GLenum) -> Option<bool> {
+        Some(match cap {
+            GL_AUTO_NORMAL => self.eval.auto_normal,
+            GL_ALPHA_TEST => self.color_buffer.alpha_test_enable,
+            GL_BLEND => self.color_buffer.blend_enable,
+            GL_COLOR_SUM_EXT => self.fog.color_sum_enable,
+            GL_DEPTH_TEST => self.depth.depth_test,
+            GL_LIGHTING => self.lighting.lighting_enable,
+            GL_LINE_STIPPLE => self.line.line_stipple_enable,
+            GL_POINT_SPRITE_ARB => self.point.point_sprite_enable,
+            GL_POLYGON_OFFSET_FILL => self.polygon.polygon_offset_fill_enable,
+            GL_POLYGON_OFFSET_LINE => self.polygon.polygon_offset_line_enable,
+            GL_POLYGON_OFFSET_POINT => self.polygon.polygon_offset_point_enable,
+            GL_TEXTURE_2D => self.texture.texture_2d_enable,
+            GL_VERTEX_ARRAY => self.vertex_arrays.vertex_array.enabled,
+            GL_NORMAL_ARRAY => self.vertex_arrays.normal_array.enabled,
+            GL_COLOR_ARRAY => self.vertex_arrays.color_array.enabled,
+            GL_TEXTURE_COORD_ARRAY => self.vertex_arrays.tex_coord_arrays[self.vertex_arrays.client_active_texture].enabled,
+            GL_EDGE_FLAG_ARRAY => self.vertex_arrays.edge_flag_array.enabled,
+            GL_SECONDARY_COLOR_ARRAY_EXT => self.vertex_arrays.secondary_color_array.enabled,
+            _ if cap >= GL_LIGHT0 && cap < GL_LIGHT0 + MAX_LIGHTS as GLenum => self.lighting.lights[(cap - GL_LIGHT0) as usize].enabled,
+            _ => {
+                if let Some(index) = eval_map_index(cap, GL_MAP1_COLOR_4) {
+                    self.eval.map1_enables[index]
+                } else if let Some(index) = eval_map_index(cap, GL_MAP2_COLOR_4) {
+                    self.eval.map2_enables[index]
+                } else {
+                    return None;
+                }
+            }
+        })
     }
 
     fn end_list(&mut self) {
-        self.new_list = None;
+        match self.new_list.take() {
+            Some((list, mut display_list)) => {
+                display_list.compile();
+                self.display_lists.insert(list, Rc::new(RefCell::new(display_list)));
+            }
+            _ => self.set_error(GL_INVALID_OPERATION)
+        }
+    }
+
+    // Evaluates the enabled maps of a dimension at (u, v) and issues the resulting vertex as if by the equivalent
+    // immediate-mode calls, except that the current attributes are left as they were. Without an enabled vertex map,
+    // nothing is issued.
+    fn eval_coord(&mut self, two_dimensional: bool, u: GLfloat, v: GLfloat) {
+        let (maps, enables) = if two_dimensional {
+            (&self.eval_maps2, self.eval.map2_enables)
+        } else {
+            (&self.eval_maps1, self.eval.map1_enables)
+        };
+        let vertex_map = if enables[EVAL_MAP_VERTEX_4] {
+            EVAL_MAP_VERTEX_4
+        } else if enables[EVAL_MAP_VERTEX_3] {
+            EVAL_MAP_VERTEX_3
+        } else {
+            return;
+        };
+        let (position, du, dv) = maps[vertex_map].evaluate(u, v);
+
+        let color = if enables[EVAL_MAP_COLOR_4] {
+            Some(maps[EVAL_MAP_COLOR_4].evaluate(u, v).0)
+        } else {
+            None
+        };
+        let normal = if two_dimensional && self.eval.auto_normal {
+            Some(auto_normal(position, du, dv))
+        } else if enables[EVAL_MAP_NORMAL] {
+            let normal = maps[EVAL_MAP_NORMAL].evaluate(u, v).0;
+            Some(Vec3::new(normal.x(), normal.y(), normal.z()))
+        } else {
+            None
+        };
+        // Only the enabled texture coordinate map with the most components is used
+        let tex_coord = (EVAL_MAP_TEXTURE_COORD_1..=EVAL_MAP_TEXTURE_COORD_4).rev()
+            .find(|&index| enables[index])
+            .map(|index| maps[index].evaluate(u, v).0);
+
+        let current = self.current;
+        if let Some(color) = color {
+            self.execute(&Command::Color4f { red: color.x(), green: color.y(), blue: color.z(), alpha: color.w() });
+        }
+        if let Some(normal) = normal {
+            self.execute(&Command::Normal3f { nx: normal.x(), ny: normal.y(), nz: normal.z() });
+        }
+        if let Some(tex_coord) = tex_coord {
+            self.execute(&Command::TexCoord2f { s: tex_coord.x(), t: tex_coord.y() });
+        }
+        self.execute(&Command::Vertex4f { x: position.x(), y: position.y(), z: position.z(), w: position.w() });
+        self.current = current;
+    }
+
+    fn eval_point(&mut self, two_dimensional: bool, i: GLint, j: GLint) {
+        if two_dimensional {
+            let u = self.eval.map2_grid_u.coord(i);
+            let v = self.eval.map2_grid_v.coord(j);
+            self.eval_coord(true, u, v);
+        } else {
+            let u = self.eval.map1_grid.coord(i);
+            self.eval_coord(false, u, 0.0);
+        }
     }
 
     fn execute(&mut self, command: &Command) {
+        match *command {
+            // Cached and locked array elements captured the current values of attributes without enabled arrays, so they go
+            // stale as soon as any of those change
+            Command::Color3f { .. } |
+            Command::Color4f { .. } |
+            Command::EdgeFlag { .. } |
+            Command::MultiTexCoord2fARB { .. } |
+            Command::Normal3f { .. } |
+            Command::SecondaryColor3fEXT { .. } |
+            Command::TexCoord2f { .. } => {
+                self.vertex_cache.invalidate();
+                self.invalidate_locked_arrays();
+            }
+            _ => ()
+        }
+
         match *command {
             Command::ActiveTextureARB { texture } => {
-                // TODO
-                println!("ActiveTextureARB: texture: 0x{:08x}", texture);
+                if texture < GL_TEXTURE0_ARB || texture >= GL_TEXTURE0_ARB + MAX_TEXTURE_UNITS as GLenum {
+                    invalid_call!(self, GL_INVALID_ENUM, "glActiveTextureARB called with invalid texture: 0x{:08x}", texture);
+                }
+                self.texture.active_texture = (texture - GL_TEXTURE0_ARB) as _;
+            }
+            Command::AlphaFunc { func, ref_ } => {
+                self.color_buffer.alpha_test_func = match func {
+                    GL_NEVER => CompareFunc::Never,
+                    GL_LESS => CompareFunc::Less,
+                    GL_EQUAL => CompareFunc::Equal,
+                    GL_LEQUAL => CompareFunc::LessEqual,
+                    GL_GREATER => CompareFunc::Greater,
+                    GL_NOTEQUAL => CompareFunc::NotEqual,
+                    GL_GEQUAL => CompareFunc::GreaterEqual,
+                    GL_ALWAYS => CompareFunc::Always,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glAlphaFunc called with invalid func: 0x{:08x}", func)
+                };
+                self.color_buffer.alpha_test_ref = ref_.max(0.0).min(1.0);
             }
             Command::Begin { mode } => {
                 if self.primitive_mode.is_some() {
-                    panic!("glBegin called twice with no glEnd call");
+                    invalid_call!(self, GL_INVALID_OPERATION, "glBegin called twice with no glEnd call");
                 }
-                self.primitive_mode = Some(match mode {
-                    GL_TRIANGLES => PrimitiveMode::Triangles,
-                    GL_QUADS => PrimitiveMode::Quads,
-                    _ => panic!("glBegin called with invalid mode: 0x{:08x}", mode)
+                self.vertex_cache.invalidate();
+                self.primitive_mode = Some(match primitive_mode(mode) {
+                    Some(primitive_mode) => primitive_mode,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glBegin called with invalid mode: 0x{:08x}", mode)
                 });
             }
             Command::BindTexture { target, texture } => {
                 match target {
                     GL_TEXTURE_2D => {
-                        self.texture_2d = texture;
+                        self.textures.entry(texture).or_insert_with(Texture::new);
+                        self.texture.texture_2d = texture;
                     }
-                    _ => panic!("glBindTexture called with invalid target: 0x{:08x}", target)
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glBindTexture called with invalid target: 0x{:08x}", target)
+                }
+            }
+            Command::Bitmap { width, height, xorig, yorig, xmove, ymove, ref bitmap } => {
+                if self.current.raster_position_valid {
+                    let origin = Vec2::new(self.current.raster_position.x(), self.current.raster_position.y()) - Vec2::new(xorig, yorig) * FRAMEBUFFER_SCALE;
+                    let color = self.current.raster_color * 255.0;
+                    let color = ((color.w() as u32) << 24) | ((color.x() as u32) << 16) | ((color.y() as u32) << 8) | ((color.z() as u32) << 0);
+                    self.rasterize_pixel_rectangle(origin, Vec2::splat(FRAMEBUFFER_SCALE), width as _, height as _, self.current.raster_position.z(), |x, y| {
+                        if bitmap[y * width as usize + x] { Some(color) } else { None }
+                    });
+                    self.current.raster_position += Vec3::new(xmove, ymove, 0.0) * FRAMEBUFFER_SCALE;
                 }
             }
             Command::BlendFunc { sfactor, dfactor } => {
-                self.blend_src_factor = match sfactor {
+                self.color_buffer.blend_src_factor = match sfactor {
                     GL_ZERO => BlendSrcFactor::Zero,
                     GL_SRC_COLOR => BlendSrcFactor::SrcColor,
                     GL_SRC_ALPHA => BlendSrcFactor::SrcAlpha,
-                    _ => panic!("glBlendFunc called with invalid sfactor: 0x{:08x}", sfactor)
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glBlendFunc called with invalid sfactor: 0x{:08x}", sfactor)
                 };
-                self.blend_dst_factor = match dfactor {
+                self.color_buffer.blend_dst_factor = match dfactor {
                     GL_ONE => BlendDstFactor::One,
                     GL_SRC_ALPHA => BlendDstFactor::SrcAlpha,
                     GL_ONE_MINUS_SRC_ALPHA => BlendDstFactor::OneMinusSrcAlpha,
-                    _ => panic!("glBlendFunc called with invalid dfactor: 0x{:08x}", dfactor)
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glBlendFunc called with invalid dfactor: 0x{:08x}", dfactor)
                 };
             }
             Command::CallList { list } => {
-                if (list as usize) < self.display_lists.len() {
-                    for command in self.display_lists[list as usize].clone().borrow().commands.iter() {
-                        self.execute(command);
-                    }
-                } else {
-                    println!("glCallList called with invalid list: {}", list);
+                self.call_list(list);
+            }
+            Command::CallLists { ref lists } => {
+                for &list in lists.iter() {
+                    self.call_list(self.list_base.wrapping_add(list));
                 }
             }
             Command::Clear { mask } => {
                 // TODO: Only clear within viewport
-                if (mask & GL_DEPTH_BUFFER_BIT) != 0 {
+                if (mask & GL_DEPTH_BUFFER_BIT) != 0 && self.depth.depth_mask {
                     for depth in self.depth_buffer.iter_mut() {
                         *depth = 65535;
                     }
                 }
                 if (mask & GL_COLOR_BUFFER_BIT) != 0 {
                     let clear_value =
-                        (((self.clear_color_alpha * 255.0) as u32) << 24) |
-                        (((self.clear_color_red * 255.0) as u32) << 16) |
-                        (((self.clear_color_green * 255.0) as u32) << 8) |
-                        (((self.clear_color_blue * 255.0) as u32) << 0);
+                        (((self.color_buffer.clear_color_alpha * 255.0) as u32) << 24) |
+                        (((self.color_buffer.clear_color_red * 255.0) as u32) << 16) |
+                        (((self.color_buffer.clear_color_green * 255.0) as u32) << 8) |
+                        (((self.color_buffer.clear_color_blue * 255.0) as u32) << 0);
+                    let color_mask = self.color_mask();
                     for pixel in self.back_buffer.iter_mut() {
-                        *pixel = clear_value;
+                        *pixel = (clear_value & color_mask) | (*pixel & !color_mask);
                     }
                 }
                 println!("Clear: mask: 0x{:08x}", mask);
             }
             Command::ClearColor { red, green, blue, alpha } => {
-                self.clear_color_red = red;
-                self.clear_color_green = green;
-                self.clear_color_blue = blue;
-                self.clear_color_alpha = alpha;
+                self.color_buffer.clear_color_red = red;
+                self.color_buffer.clear_color_green = green;
+                self.color_buffer.clear_color_blue = blue;
+                self.color_buffer.clear_color_alpha = alpha;
             }
             Command::Color3f { red, green, blue } => {
-                self.current_color = Vec4::new(red, green, blue, 1.0).max(Vec4::splat(0.0)).min(Vec4::splat(1.0));
+                self.current.color = Vec4::new(red, green, blue, 1.0).max(Vec4::splat(0.0)).min(Vec4::splat(1.0));
             }
             Command::Color4f { red, green, blue, alpha } => {
-                self.current_color = Vec4::new(red, green, blue, alpha);
+                self.current.color = Vec4::new(red, green, blue, alpha);
+            }
+            Command::ColorMask { red, green, blue, alpha } => {
+                self.color_buffer.color_mask_red = red != GL_FALSE;
+                self.color_buffer.color_mask_green = green != GL_FALSE;
+                self.color_buffer.color_mask_blue = blue != GL_FALSE;
+                self.color_buffer.color_mask_alpha = alpha != GL_FALSE;
             }
             Command::CullFace { mode } => {
                 // TODO
                 println!("CullFace: mode: {}", mode);
             }
             Command::DepthMask { flag } => {
-                self.depth_mask = match flag {
-                    GL_FALSE => false,
-                    GL_TRUE => true,
-                    _ => panic!("glDepthMask called with invalid flag: 0x{:08x}", flag)
-                };
+                self.depth.depth_mask = flag != GL_FALSE;
             }
             Command::Disable { cap } => {
                 match cap {
+                    GL_ALPHA_TEST => {
+                        self.color_buffer.alpha_test_enable = false;
+                    }
                     GL_DEPTH_TEST => {
-                        self.depth_test = false;
+                        self.depth.depth_test = false;
                     }
                     GL_BLEND => {
-                        self.blend_enable = false;
+                        self.color_buffer.blend_enable = false;
+                    }
+                    GL_COLOR_SUM_EXT => {
+                        self.fog.color_sum_enable = false;
+                    }
+                    GL_LIGHTING => {
+                        self.set_lighting_enable(false);
+                    }
+                    GL_LINE_STIPPLE => {
+                        self.line.line_stipple_enable = false;
+                    }
+                    GL_POINT_SPRITE_ARB => {
+                        self.point.point_sprite_enable = false;
+                    }
+                    GL_POLYGON_OFFSET_FILL => {
+                        self.polygon.polygon_offset_fill_enable = false;
+                    }
+                    GL_POLYGON_OFFSET_LINE => {
+                        self.polygon.polygon_offset_line_enable = false;
+                    }
+                    GL_POLYGON_OFFSET_POINT => {
+                        self.polygon.polygon_offset_point_enable = false;
                     }
                     GL_TEXTURE_2D => {
-                        self.texture_2d_enable = false;
+                        self.texture.texture_2d_enable = false;
+                    }
+                    GL_AUTO_NORMAL => {
+                        self.eval.auto_normal = false;
+                    }
+                    _ if cap >= GL_LIGHT0 && cap < GL_LIGHT0 + MAX_LIGHTS as GLenum => {
+                        self.lighting.lights[(cap - GL_LIGHT0) as usize].enabled = false;
+                        self.invalidate_locked_arrays();
+                    }
+                    _ if eval_map_index(cap, GL_MAP1_COLOR_4).is_some() => {
+                        self.eval.map1_enables[(cap - GL_MAP1_COLOR_4) as usize] = false;
+                    }
+                    _ if eval_map_index(cap, GL_MAP2_COLOR_4).is_some() => {
+                        self.eval.map2_enables[(cap - GL_MAP2_COLOR_4) as usize] = false;
                     }
                     _ => println!("Disable: cap: 0x{:08x}", cap)
                 }
             }
+            Command::DrawPixels { width, height, ref pixels } => {
+                if self.current.raster_position_valid {
+                    let origin = Vec2::new(self.current.raster_position.x(), self.current.raster_position.y());
+                    let scale = Vec2::new(self.pixel_mode.pixel_zoom_x, self.pixel_mode.pixel_zoom_y) * FRAMEBUFFER_SCALE;
+                    self.rasterize_pixel_rectangle(origin, scale, width as _, height as _, self.current.raster_position.z(), |x, y| {
+                        Some(pixels[y * width as usize + x])
+                    });
+                }
+            }
+            Command::EdgeFlag { flag } => {
+                self.current.edge_flag = flag != GL_FALSE;
+            }
             Command::Enable { cap } => {
                 match cap {
+                    GL_ALPHA_TEST => {
+                        self.color_buffer.alpha_test_enable = true;
+                    }
                     GL_DEPTH_TEST => {
-                        self.depth_test = true;
+                        self.depth.depth_test = true;
                     }
                     GL_BLEND => {
-                        self.blend_enable = true;
+                        self.color_buffer.blend_enable = true;
+                    }
+                    GL_COLOR_SUM_EXT => {
+                        self.fog.color_sum_enable = true;
+                    }
+                    GL_LIGHTING => {
+                        self.set_lighting_enable(true);
+                    }
+                    GL_LINE_STIPPLE => {
+                        self.line.line_stipple_enable = true;
+                    }
+                    GL_POINT_SPRITE_ARB => {
+                        self.point.point_sprite_enable = true;
+                    }
+                    GL_POLYGON_OFFSET_FILL => {
+                        self.polygon.polygon_offset_fill_enable = true;
+                    }
+                    GL_POLYGON_OFFSET_LINE => {
+                        self.polygon.polygon_offset_line_enable = true;
+                    }
+                    GL_POLYGON_OFFSET_POINT => {
+                        self.polygon.polygon_offset_point_enable = true;
                     }
                     GL_TEXTURE_2D => {
-                        self.texture_2d_enable = true;
+                        self.texture.texture_2d_enable = true;
+                    }
+                    GL_AUTO_NORMAL => {
+                        self.eval.auto_normal = true;
+                    }
+                    _ if cap >= GL_LIGHT0 && cap < GL_LIGHT0 + MAX_LIGHTS as GLenum => {
+                        self.lighting.lights[(cap - GL_LIGHT0) as usize].enabled = true;
+                        self.invalidate_locked_arrays();
+                    }
+                    _ if eval_map_index(cap, GL_MAP1_COLOR_4).is_some() => {
+                        self.eval.map1_enables[(cap - GL_MAP1_COLOR_4) as usize] = true;
+                    }
+                    _ if eval_map_index(cap, GL_MAP2_COLOR_4).is_some() => {
+                        self.eval.map2_enables[(cap - GL_MAP2_COLOR_4) as usize] = true;
                     }
                     _ => println!("Enable: cap: 0x{:08x}", cap)
                 }
             }
             Command::End => {
                 if let Some(primitive_mode) = self.primitive_mode {
-                    let verts_per_primitive = match primitive_mode {
-                        PrimitiveMode::Triangles => 3,
-                        PrimitiveMode::Quads => 4,
-                    };
-                    if self.verts.len() % verts_per_primitive != 0 {
-                        panic!("Incorrect number of vertices specified for primitive type");
-                    }
-                    for vert in self.verts.iter_mut() {
-                        let object = vert.position;
-                        let eye = self.modelview * object;
-                        let clip = self.projection * eye;
-                        vert.position = clip;
+                    let mut verts = mem::replace(&mut self.verts, Vec::new());
+                    self.assemble_primitives(primitive_mode, &verts);
+                    // Hand the buffer back so its allocation is reused by the next glBegin/glEnd pair
+                    verts.clear();
+                    self.verts = verts;
+                    self.primitive_mode = None;
+                } else {
+                    invalid_call!(self, GL_INVALID_OPERATION, "glEnd called with no matching glBegin call");
+                }
+            }
+            Command::EvalCoord1f { u } => {
+                self.eval_coord(false, u, 0.0);
+            }
+            Command::EvalCoord2f { u, v } => {
+                self.eval_coord(true, u, v);
+            }
+            Command::EvalMesh1 { mode, i1, i2 } => {
+                let mode = match mode {
+                    GL_POINT => GL_POINTS,
+                    GL_LINE => GL_LINE_STRIP,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glEvalMesh1 called with invalid mode: 0x{:08x}", mode)
+                };
+                if self.primitive_mode.is_some() {
+                    invalid_call!(self, GL_INVALID_OPERATION, "glEvalMesh1 called between glBegin and glEnd");
+                }
+                self.execute(&Command::Begin { mode });
+                for i in i1..=i2 {
+                    self.eval_point(false, i, 0);
+                }
+                self.execute(&Command::End);
+            }
+            Command::EvalMesh2 { mode, i1, i2, j1, j2 } => {
+                if mode != GL_POINT && mode != GL_LINE && mode != GL_FILL {
+                    invalid_call!(self, GL_INVALID_ENUM, "glEvalMesh2 called with invalid mode: 0x{:08x}", mode);
+                }
+                if self.primitive_mode.is_some() {
+                    invalid_call!(self, GL_INVALID_OPERATION, "glEvalMesh2 called between glBegin and glEnd");
+                }
+                match mode {
+                    GL_POINT => {
+                        self.execute(&Command::Begin { mode: GL_POINTS });
+                        for j in j1..=j2 {
+                            for i in i1..=i2 {
+                                self.eval_point(true, i, j);
+                            }
+                        }
+                        self.execute(&Command::End);
                     }
-                    for i in (0..self.verts.len()).step_by(verts_per_primitive) {
-                        match primitive_mode {
-                            PrimitiveMode::Triangles => {
-                                self.assemble_triangle([self.verts[i + 0], self.verts[i + 1], self.verts[i + 2]]);
+                    GL_LINE => {
+                        for j in j1..=j2 {
+                            self.execute(&Command::Begin { mode: GL_LINE_STRIP });
+                            for i in i1..=i2 {
+                                self.eval_point(true, i, j);
                             }
-                            PrimitiveMode::Quads => {
-                                self.assemble_triangle([self.verts[i + 0], self.verts[i + 1], self.verts[i + 2]]);
-                                self.assemble_triangle([self.verts[i + 2], self.verts[i + 3], self.verts[i + 0]]);
+                            self.execute(&Command::End);
+                        }
+                        for i in i1..=i2 {
+                            self.execute(&Command::Begin { mode: GL_LINE_STRIP });
+                            for j in j1..=j2 {
+                                self.eval_point(true, i, j);
                             }
+                            self.execute(&Command::End);
+                        }
+                    }
+                    _ => {
+                        // The spec draws each row as a quad strip; a triangle strip with the same vertex order covers
+                        // the same area with the same winding
+                        for j in j1..j2 {
+                            self.execute(&Command::Begin { mode: GL_TRIANGLE_STRIP });
+                            for i in i1..=i2 {
+                                self.eval_point(true, i, j);
+                                self.eval_point(true, i, j + 1);
+                            }
+                            self.execute(&Command::End);
                         }
                     }
-                    self.verts.clear();
-                    self.primitive_mode = None;
-                } else {
-                    panic!("glEnd called with no matching glBegin call");
                 }
             }
+            Command::EvalPoint1 { i } => {
+                self.eval_point(false, i, 0);
+            }
+            Command::EvalPoint2 { i, j } => {
+                self.eval_point(true, i, j);
+            }
+            Command::FrontFace { mode } => {
+                self.polygon.front_face = match mode {
+                    GL_CW => FrontFace::Cw,
+                    GL_CCW => FrontFace::Ccw,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glFrontFace called with invalid mode: 0x{:08x}", mode)
+                };
+            }
+            Command::Frustum { left, right, bottom, top, zNear, zFar } => {
+                self.multiply_current_matrix(Matrix::frustum(left as f32, right as f32, bottom as f32, top as f32, zNear as f32, zFar as f32));
+            }
             Command::Lightf { light, pname, param } => {
-                // TODO
-                println!("Lightf: light: 0x{:08x}, pname: 0x{:08x}, param: {}", light, pname, param);
+                if light_param_count(pname) != Some(1) {
+                    invalid_call!(self, GL_INVALID_ENUM, "glLightf called with invalid pname: 0x{:08x}", pname);
+                }
+                self.light(light, pname, [param, 0.0, 0.0, 0.0]);
+            }
+            Command::Lightfv { light, pname, params } => {
+                self.light(light, pname, params);
+            }
+            Command::LightModelfv { pname, params } => {
+                self.invalidate_locked_arrays();
+                let [x, y, z, w] = params;
+                match pname {
+                    GL_LIGHT_MODEL_AMBIENT => {
+                        self.lighting.light_model_ambient = Vec4::new(x, y, z, w);
+                    }
+                    GL_LIGHT_MODEL_LOCAL_VIEWER => {
+                        self.lighting.light_model_local_viewer = x != 0.0;
+                    }
+                    GL_LIGHT_MODEL_TWO_SIDE => {
+                        self.lighting.light_model_two_side = x != 0.0;
+                    }
+                    GL_LIGHT_MODEL_COLOR_CONTROL => {
+                        self.lighting.light_model_color_control = match x as GLenum {
+                            GL_SINGLE_COLOR => GL_SINGLE_COLOR,
+                            GL_SEPARATE_SPECULAR_COLOR => GL_SEPARATE_SPECULAR_COLOR,
+                            _ => invalid_call!(self, GL_INVALID_ENUM, "glLightModel called with invalid GL_LIGHT_MODEL_COLOR_CONTROL: {}", x)
+                        };
+                    }
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glLightModel called with invalid pname: 0x{:08x}", pname)
+                }
+            }
+            Command::LineStipple { factor, pattern } => {
+                self.line.line_stipple_factor = factor.max(1).min(256);
+                self.line.line_stipple_pattern = pattern;
+            }
+            Command::LineWidth { width } => {
+                if width > 0.0 {
+                    self.line.line_width = width;
+                } else {
+                    self.set_error(GL_INVALID_VALUE);
+                }
+            }
+            Command::ListBase { base } => {
+                self.list_base = base;
             }
             Command::LoadIdentity => {
                 self.set_current_matrix(Matrix::identity());
             }
+            Command::LoadMatrixd { m } => {
+                self.set_current_matrix(Matrix::from_doubles(&m));
+            }
+            Command::LoadMatrixf { m } => {
+                self.set_current_matrix(Matrix::from_floats(&m));
+            }
+            Command::LoadTransposeMatrixdARB { m } => {
+                self.set_current_matrix(Matrix::from_doubles(&m).transpose());
+            }
+            Command::LoadTransposeMatrixfARB { m } => {
+                self.set_current_matrix(Matrix::from_floats(&m).transpose());
+            }
+            Command::Map1f { target, u1, u2, order, ref points } => {
+                if let Some(index) = eval_map_index(target, GL_MAP1_COLOR_4) {
+                    self.eval_maps1[index] = EvalMap {
+                        u1,
+                        u2,
+                        uorder: order as _,
+                        v1: 0.0,
+                        v2: 1.0,
+                        vorder: 1,
+                        points: points.clone(),
+                    };
+                }
+            }
+            Command::Map2f { target, u1, u2, uorder, v1, v2, vorder, ref points } => {
+                if let Some(index) = eval_map_index(target, GL_MAP2_COLOR_4) {
+                    self.eval_maps2[index] = EvalMap {
+                        u1,
+                        u2,
+                        uorder: uorder as _,
+                        v1,
+                        v2,
+                        vorder: vorder as _,
+                        points: points.clone(),
+                    };
+                }
+            }
+            Command::MapGrid1f { un, u1, u2 } => {
+                if un <= 0 {
+                    invalid_call!(self, GL_INVALID_VALUE, "glMapGrid1 called with invalid un: {}", un);
+                }
+                self.eval.map1_grid = EvalGrid { segments: un, c1: u1, c2: u2 };
+            }
+            Command::MapGrid2f { un, u1, u2, vn, v1, v2 } => {
+                if un <= 0 || vn <= 0 {
+                    invalid_call!(self, GL_INVALID_VALUE, "glMapGrid2 called with invalid un/vn: {}/{}", un, vn);
+                }
+                self.eval.map2_grid_u = EvalGrid { segments: un, c1: u1, c2: u2 };
+                self.eval.map2_grid_v = EvalGrid { segments: vn, c1: v1, c2: v2 };
+            }
+            Command::Materialf { face, pname, param } => {
+                if material_param_count(pname) != Some(1) {
+                    invalid_call!(self, GL_INVALID_ENUM, "glMaterialf called with invalid pname: 0x{:08x}", pname);
+                }
+                self.material(face, pname, [param, 0.0, 0.0, 0.0]);
+            }
+            Command::Materialfv { face, pname, params } => {
+                self.material(face, pname, params);
+            }
             Command::MatrixMode { mode } => {
-                self.matrix_mode = match mode {
+                self.transform.matrix_mode = match mode {
                     GL_MODELVIEW => MatrixMode::ModelView,
                     GL_PROJECTION => MatrixMode::Projection,
-                    _ => panic!("glMatrixMode called with invalid mode: 0x{:08x}", mode),
+                    GL_TEXTURE => MatrixMode::Texture,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glMatrixMode called with invalid mode: 0x{:08x}", mode),
                 };
             }
             Command::MultiTexCoord2fARB { target, s, t } => {
-                // TODO
-                //println!("MultiTexCoord2fARB: target: 0x{:08x}, s: {}, t: {}", target, s, t);
-                self.current_tex_coord = Vec2::new(s, t);
+                if target < GL_TEXTURE0_ARB || target >= GL_TEXTURE0_ARB + MAX_TEXTURE_UNITS as GLenum {
+                    invalid_call!(self, GL_INVALID_ENUM, "glMultiTexCoordARB called with invalid target: 0x{:08x}", target);
+                }
+                // Only the first unit's coordinates reach the rasterizer
+                if target == GL_TEXTURE0_ARB {
+                    self.current.tex_coord = Vec2::new(s, t);
+                }
             }
             Command::MultMatrixd { m } => {
                 self.multiply_current_matrix(Matrix::from_doubles(&m));
@@ -929,36 +3767,137 @@ impl Context {
             Command::MultMatrixf { m } => {
                 self.multiply_current_matrix(Matrix::from_floats(&m));
             }
-            Command::Normal3f { nx, ny, nz } => {
-                self.current_normal = Vec3::new(nx, ny, nz);
+            Command::MultTransposeMatrixdARB { m } => {
+                self.multiply_current_matrix(Matrix::from_doubles(&m).transpose());
+            }
+            Command::MultTransposeMatrixfARB { m } => {
+                self.multiply_current_matrix(Matrix::from_floats(&m).transpose());
             }
-            Command::Normal3fv { v } => {
-                self.current_normal = Vec3::new(v[0], v[1], v[2]);
+            Command::Normal3f { nx, ny, nz } => {
+                self.current.normal = Vec3::new(nx, ny, nz);
             }
             Command::Ortho { left, right, bottom, top, zNear, zFar } => {
                 self.multiply_current_matrix(Matrix::ortho(left as f32, right as f32, bottom as f32, top as f32, zNear as f32, zFar as f32));
             }
+            Command::PixelZoom { xfactor, yfactor } => {
+                self.pixel_mode.pixel_zoom_x = xfactor;
+                self.pixel_mode.pixel_zoom_y = yfactor;
+            }
+            Command::PointSize { size } => {
+                if size > 0.0 {
+                    self.point.point_size = size;
+                } else {
+                    self.set_error(GL_INVALID_VALUE);
+                }
+            }
             Command::PolygonMode { face, mode } => {
-                // TODO
-                println!("PolygonMode: face: 0x{:08x}, mode: 0x{:08x}", face, mode);
+                let mode = match mode {
+                    GL_POINT => PolygonMode::Point,
+                    GL_LINE => PolygonMode::Line,
+                    GL_FILL => PolygonMode::Fill,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glPolygonMode called with invalid mode: 0x{:08x}", mode)
+                };
+                match face {
+                    GL_FRONT => {
+                        self.polygon.polygon_mode_front = mode;
+                    }
+                    GL_BACK => {
+                        self.polygon.polygon_mode_back = mode;
+                    }
+                    GL_FRONT_AND_BACK => {
+                        self.polygon.polygon_mode_front = mode;
+                        self.polygon.polygon_mode_back = mode;
+                    }
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glPolygonMode called with invalid face: 0x{:08x}", face)
+                }
+            }
+            Command::PolygonOffset { factor, units } => {
+                self.polygon.polygon_offset_factor = factor;
+                self.polygon.polygon_offset_units = units;
+            }
+            Command::PopAttrib => {
+                self.pop_attrib();
             }
             Command::PopMatrix => {
-                let m = self.matrix_stack.pop().expect("Matrix stack underflow");
-                self.set_current_matrix(m);
+                let (stack, _) = self.current_matrix_stack();
+                match stack.pop() {
+                    Some(m) => self.set_current_matrix(m),
+                    None => self.set_error(GL_STACK_UNDERFLOW),
+                }
+            }
+            Command::PushAttrib { mask } => {
+                self.push_attrib(mask);
             }
             Command::PushMatrix => {
-                self.matrix_stack.push(self.current_matrix());
+                let m = self.current_matrix();
+                let (stack, max_depth) = self.current_matrix_stack();
+                if stack.len() + 1 < max_depth {
+                    stack.push(m);
+                } else {
+                    self.set_error(GL_STACK_OVERFLOW);
+                }
+            }
+            Command::RasterPos4f { x, y, z, w } => {
+                // The raster position goes through the same transforms as a vertex, but it's either entirely in or out
+                let clip = self.projection * (self.modelview * Vec4::new(x, y, z, w));
+                self.current.raster_position_valid =
+                    clip.x().abs() <= clip.w() && clip.y().abs() <= clip.w() && clip.z().abs() <= clip.w();
+                if self.current.raster_position_valid {
+                    let window = self.window_position(clip);
+                    self.current.raster_position = Vec3::new(window.x(), window.y(), clip.z() / clip.w() * 0.5 + 0.5);
+                    self.current.raster_color = self.current.color.max(Vec4::zero()).min(Vec4::splat(1.0));
+                }
+            }
+            Command::Rotated { angle, x, y, z } => {
+                self.multiply_current_matrix(Matrix::rotation(angle as f32, x as f32, y as f32, z as f32));
             }
             Command::Rotatef { angle, x, y, z } => {
-                // TODO
-                println!("Rotatef: angle: {}, x: {}, y: {}, z: {}", angle, x, y, z);
+                self.multiply_current_matrix(Matrix::rotation(angle, x, y, z));
+            }
+            Command::Scaled { x, y, z } => {
+                self.multiply_current_matrix(Matrix::scale(x as f32, y as f32, z as f32));
+            }
+            Command::Scalef { x, y, z } => {
+                self.multiply_current_matrix(Matrix::scale(x, y, z));
+            }
+            Command::SecondaryColor3fEXT { red, green, blue } => {
+                self.current.secondary_color = Vec3::new(red, green, blue).max(Vec3::zero()).min(Vec3::splat(1.0));
             }
             Command::ShadeModel { mode } => {
-                // TODO
-                println!("ShadeModel: mode: 0x{:08x}", mode);
+                self.lighting.shade_model = match mode {
+                    GL_FLAT => ShadeModel::Flat,
+                    GL_SMOOTH => ShadeModel::Smooth,
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glShadeModel called with invalid mode: 0x{:08x}", mode)
+                };
             }
             Command::TexCoord2f { s, t } => {
-                self.current_tex_coord = Vec2::new(s, t);
+                self.current.tex_coord = Vec2::new(s, t);
+            }
+            Command::TexEnvfv { target, pname, params } => {
+                match (target, pname) {
+                    (GL_TEXTURE_ENV, GL_TEXTURE_ENV_COLOR) => {
+                        self.texture.texture_env_color = Vec4::new(params[0], params[1], params[2], params[3]).max(Vec4::zero()).min(Vec4::splat(1.0));
+                    }
+                    // Everything else takes a single enum or boolean, which is handled like glTexEnvi
+                    _ => self.execute(&Command::TexEnvi { target, pname, param: params[0] as _ })
+                }
+            }
+            Command::TexEnvi { target, pname, param } => {
+                match (target, pname) {
+                    (GL_TEXTURE_ENV, GL_TEXTURE_ENV_MODE) => {
+                        self.texture.texture_env_mode = match param as GLenum {
+                            GL_MODULATE | GL_DECAL | GL_BLEND | GL_REPLACE => param as _,
+                            _ => invalid_call!(self, GL_INVALID_ENUM, "glTexEnvi called with invalid param for GL_TEXTURE_ENV_MODE: 0x{:08x}", param)
+                        };
+                    }
+                    (GL_POINT_SPRITE_ARB, GL_COORD_REPLACE_ARB) => {
+                        self.point.point_sprite_coord_replace = param != 0;
+                    }
+                    _ => {
+                        // TODO
+                        println!("TexEnvi: target: 0x{:08x}, pname: 0x{:08x}, param: 0x{:08x}", target, pname, param);
+                    }
+                }
             }
             Command::TexGenf { coord, pname, param } => {
                 // TODO
@@ -971,14 +3910,17 @@ impl Context {
             Command::TexParameteri { target, pname, param } => {
                 match target {
                     GL_TEXTURE_2D => {
-                        let texture = &mut self.textures[self.texture_2d as usize];
+                        let texture = self.bound_texture_2d_mut();
                         match pname {
                             // TODO: De-dupe filter param decoding
                             GL_TEXTURE_MAG_FILTER => match param {
+                                GL_NEAREST => {
+                                    texture.mag_filter = TextureFilter::Nearest;
+                                }
                                 GL_LINEAR => {
                                     texture.mag_filter = TextureFilter::Linear;
                                 }
-                                _ => panic!("glTexParameteri called with invalid param for GL_TEXTURE_MAG_FILTER: 0x{:08x}", param)
+                                _ => invalid_call!(self, GL_INVALID_ENUM, "glTexParameteri called with invalid param for GL_TEXTURE_MAG_FILTER: 0x{:08x}", param)
                             }
                             GL_TEXTURE_MIN_FILTER => match param {
                                 GL_NEAREST => {
@@ -993,24 +3935,30 @@ impl Context {
                                 GL_LINEAR_MIPMAP_LINEAR => {
                                     texture.min_filter = TextureFilter::LinearMipmapLinear;
                                 }
-                                _ => panic!("glTexParameteri called with invalid param for GL_TEXTURE_MIN_FILTER: 0x{:08x}", param)
+                                _ => invalid_call!(self, GL_INVALID_ENUM, "glTexParameteri called with invalid param for GL_TEXTURE_MIN_FILTER: 0x{:08x}", param)
                             }
                             GL_TEXTURE_WRAP_S => match param {
                                 GL_CLAMP => {
                                     texture.wrap_s = WrapParameter::Clamp;
                                 }
-                                _ => panic!("glTexParameteri called with invalid param for GL_TEXTURE_WRAP_S: 0x{:08x}", param)
+                                GL_REPEAT => {
+                                    texture.wrap_s = WrapParameter::Repeat;
+                                }
+                                _ => invalid_call!(self, GL_INVALID_ENUM, "glTexParameteri called with invalid param for GL_TEXTURE_WRAP_S: 0x{:08x}", param)
                             }
                             GL_TEXTURE_WRAP_T => match param {
                                 GL_CLAMP => {
                                     texture.wrap_t = WrapParameter::Clamp;
                                 }
-                                _ => panic!("glTexParameteri called with invalid param for GL_TEXTURE_WRAP_S: 0x{:08x}", param)
+                                GL_REPEAT => {
+                                    texture.wrap_t = WrapParameter::Repeat;
+                                }
+                                _ => invalid_call!(self, GL_INVALID_ENUM, "glTexParameteri called with invalid param for GL_TEXTURE_WRAP_T: 0x{:08x}", param)
                             }
-                            _ => panic!("glTexParameteri called with invalid pname: 0x{:08x}", pname)
+                            _ => invalid_call!(self, GL_INVALID_ENUM, "glTexParameteri called with invalid pname: 0x{:08x}", pname)
                         }
                     }
-                    _ => panic!("glTexParameteri called with invalid target: 0x{:08x}", target)
+                    _ => invalid_call!(self, GL_INVALID_ENUM, "glTexParameteri called with invalid target: 0x{:08x}", target)
                 }
             }
             Command::Translated { x, y, z } => {
@@ -1019,13 +3967,17 @@ impl Context {
             Command::Translatef { x, y, z } => {
                 self.multiply_current_matrix(Matrix::translation(x, y, z));
             }
-            Command::Vertex3f { x, y, z } => {
-                self.verts.push(Vertex {
-                    position: Vec4::new(x, y, z, 1.0),
-                    normal: self.current_normal,
-                    color: self.current_color,
-                    tex_coord: self.current_tex_coord,
+            Command::Vertex4f { x, y, z, w } => {
+                // The transform state can't change within glBegin/glEnd, so vertices are transformed as they come in
+                let vert = self.transform_vertex(Vertex {
+                    position: Vec4::new(x, y, z, w),
+                    normal: self.current.normal,
+                    color: self.current.color,
+                    secondary_color: self.current.secondary_color,
+                    tex_coord: self.current.tex_coord,
+                    edge_flag: self.current.edge_flag,
                 });
+                self.verts.push(vert);
             }
             Command::Viewport { x, y, width, height } => {
                 let x = x / 2;
@@ -1033,211 +3985,808 @@ impl Context {
                 let width = width / 2;
                 let height = height / 2;
                 println!("Auto-scaling viewport: {}, {}, {}, {}", x, y, width, height);
-                self.viewport_x = x;
-                self.viewport_y = y;
-                self.viewport_width = width;
-                self.viewport_height = height;
+                self.viewport.x = x;
+                self.viewport.y = y;
+                self.viewport.width = width;
+                self.viewport.height = height;
+            }
+        }
+    }
+
+    // Equivalent to executing the batch's original glBegin/glEnd run command by command
+    fn execute_batch(&mut self, batch: &VertexBatch) {
+        if self.primitive_mode.is_some() {
+            invalid_call!(self, GL_INVALID_OPERATION, "glBegin called twice with no glEnd call");
+        }
+        self.vertex_cache.invalidate();
+
+        let mut verts = mem::replace(&mut self.verts, Vec::new());
+        for (i, vert) in batch.verts.iter().enumerate() {
+            let mut vert = *vert;
+            if i < batch.inherited_normals {
+                vert.normal = self.current.normal;
+            }
+            if i < batch.inherited_colors {
+                vert.color = self.current.color;
+            }
+            if i < batch.inherited_secondary_colors {
+                vert.secondary_color = self.current.secondary_color;
+            }
+            if i < batch.inherited_tex_coords {
+                vert.tex_coord = self.current.tex_coord;
             }
+            if i < batch.inherited_edge_flags {
+                vert.edge_flag = self.current.edge_flag;
+            }
+            verts.push(self.transform_vertex(vert));
+        }
+        self.assemble_primitives(batch.primitive_mode, &verts);
+        verts.clear();
+        self.verts = verts;
+
+        if batch.sets_attributes() {
+            self.invalidate_locked_arrays();
+        }
+        if let Some(normal) = batch.normal {
+            self.current.normal = normal;
+        }
+        if let Some(color) = batch.color {
+            self.current.color = color;
+        }
+        if let Some(secondary_color) = batch.secondary_color {
+            self.current.secondary_color = secondary_color;
+        }
+        if let Some(tex_coord) = batch.tex_coord {
+            self.current.tex_coord = tex_coord;
+        }
+        if let Some(edge_flag) = batch.edge_flag {
+            self.current.edge_flag = edge_flag;
         }
     }
 
     fn gen_lists(&mut self, range: GLsizei) -> GLuint {
-        let ret = self.display_lists.len() as _;
-        for _ in 0..range {
-            self.display_lists.push(Rc::new(RefCell::new(DisplayList::new())));
+        if (range as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return 0;
         }
-        ret
+        if range == 0 {
+            return 0;
+        }
+
+        // Find the first run of unused names, skipping past any used name we run into (0 is never a valid list name)
+        let mut base: GLuint = 1;
+        let end = loop {
+            let end = match base.checked_add(range) {
+                Some(end) => end,
+                _ => {
+                    self.set_error(GL_OUT_OF_MEMORY);
+                    return 0;
+                }
+            };
+            match (base..end).rev().find(|name| self.display_lists.contains_key(name)) {
+                Some(used) => base = used + 1,
+                _ => break end
+            }
+        };
+
+        for name in base..end {
+            self.display_lists.insert(name, Rc::new(RefCell::new(DisplayList::new())));
+        }
+        base
     }
 
+    // Generated names are only reserved by the counter; the objects themselves are created on first bind
     unsafe fn gen_textures(&mut self, n: GLsizei, textures: *mut GLuint) {
+        if (n as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
         for i in 0..n {
-            *textures.add(i as _) = self.textures.len() as _;
-            self.textures.push(Texture::new());
+            while self.textures.contains_key(&self.next_texture_name) {
+                self.next_texture_name += 1;
+            }
+            *textures.add(i as _) = self.next_texture_name;
+            self.next_texture_name += 1;
         }
     }
 
-    fn get_integerv(&self, pname: GLenum, params: *mut GLint) {
-        match pname {
-            GL_UNPACK_SWAP_BYTES => unsafe {
-                *params = self.unpack_swap_bytes;
+    fn get_error(&mut self) -> GLenum {
+        let error = self.error;
+        self.error = GL_NO_ERROR;
+        error
+    }
+
+    fn get_string(&mut self, name: GLenum) -> *const GLubyte {
+        match name {
+            GL_VENDOR => b"gloat\0".as_ptr(),
+            GL_RENDERER => b"gloat ColorThrust\0".as_ptr(),
+            GL_VERSION => b"1.2 gloat\0".as_ptr(),
+            GL_EXTENSIONS => self.extensions.as_ptr() as _,
+            _ => {
+                self.set_error(GL_INVALID_ENUM);
+                ptr::null()
+            }
+        }
+    }
+
+    fn interleaved_arrays(&mut self, format: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        // (tex coord size, color size, color type, has normal, vertex size)
+        let (tex_coord_size, color_size, color_type, has_normal, vertex_size) = match format {
+            GL_V2F => (0, 0, GL_FLOAT, false, 2),
+            GL_V3F => (0, 0, GL_FLOAT, false, 3),
+            GL_C4UB_V2F => (0, 4, GL_UNSIGNED_BYTE, false, 2),
+            GL_C4UB_V3F => (0, 4, GL_UNSIGNED_BYTE, false, 3),
+            GL_C3F_V3F => (0, 3, GL_FLOAT, false, 3),
+            GL_N3F_V3F => (0, 0, GL_FLOAT, true, 3),
+            GL_C4F_N3F_V3F => (0, 4, GL_FLOAT, true, 3),
+            GL_T2F_V3F => (2, 0, GL_FLOAT, false, 3),
+            GL_T4F_V4F => (4, 0, GL_FLOAT, false, 4),
+            GL_T2F_C4UB_V3F => (2, 4, GL_UNSIGNED_BYTE, false, 3),
+            GL_T2F_C3F_V3F => (2, 3, GL_FLOAT, false, 3),
+            GL_T2F_N3F_V3F => (2, 0, GL_FLOAT, true, 3),
+            GL_T2F_C4F_N3F_V3F => (2, 4, GL_FLOAT, true, 3),
+            GL_T4F_C4F_N3F_V4F => (4, 4, GL_FLOAT, true, 4),
+            _ => {
+                self.set_error(GL_INVALID_ENUM);
+                return;
+            }
+        };
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "InterleavedArrays called with negative stride: {}", stride as GLint);
+        }
+
+        let float_size = type_size(GL_FLOAT);
+        let color_offset = tex_coord_size * float_size;
+        let normal_offset = color_offset + color_size * type_size(color_type);
+        let vertex_offset = normal_offset + if has_normal { 3 * float_size } else { 0 };
+        let stride = if stride != 0 {
+            stride
+        } else {
+            (vertex_offset + vertex_size * float_size) as _
+        };
+        let pointer = pointer as *const u8;
+
+        self.vertex_arrays.edge_flag_array.enabled = false;
+
+        let tex_coord_array = &mut self.vertex_arrays.tex_coord_arrays[self.vertex_arrays.client_active_texture];
+        tex_coord_array.enabled = tex_coord_size != 0;
+        if tex_coord_size != 0 {
+            tex_coord_array.set_pointer(tex_coord_size as _, GL_FLOAT, stride, pointer as _);
+        }
+
+        self.vertex_arrays.color_array.enabled = color_size != 0;
+        if color_size != 0 {
+            self.vertex_arrays.color_array.set_pointer(color_size as _, color_type, stride, unsafe { pointer.add(color_offset) } as _);
+        }
+
+        self.vertex_arrays.normal_array.enabled = has_normal;
+        if has_normal {
+            self.vertex_arrays.normal_array.set_pointer(3, GL_FLOAT, stride, unsafe { pointer.add(normal_offset) } as _);
+        }
+
+        self.vertex_arrays.vertex_array.enabled = true;
+        self.vertex_arrays.vertex_array.set_pointer(vertex_size as _, GL_FLOAT, stride, unsafe { pointer.add(vertex_offset) } as _);
+    }
+
+    // Flags an error for a call that's invalid per the spec, which the caller then ignores. In strict mode (the "strict"
+    // feature or the GLOAT_STRICT env var) we panic with the message instead.
+    fn invalid_call(&mut self, error: GLenum, message: fmt::Arguments) {
+        if self.strict {
+            panic!("{}", message);
+        }
+        self.set_error(error);
+    }
+
+    fn is_enabled(&mut self, cap: GLenum) -> GLboolean {
+        match self.enabled(cap) {
+            Some(true) => GL_TRUE,
+            Some(false) => GL_FALSE,
+            None => {
+                self.set_error(GL_INVALID_ENUM);
+                GL_FALSE
+            }
+        }
+    }
+
+    fn is_list(&self, list: GLuint) -> GLboolean {
+        if self.display_lists.contains_key(&list) { GL_TRUE } else { GL_FALSE }
+    }
+
+    fn is_texture(&self, texture: GLuint) -> GLboolean {
+        if texture != 0 && self.textures.contains_key(&texture) { GL_TRUE } else { GL_FALSE }
+    }
+
+    // Checks a sub-image region against the bound texture's level 0 image, flagging an error if it doesn't fit
+    fn is_valid_texture_region(&mut self, xoffset: GLint, yoffset: GLint, width: GLsizei, height: GLsizei) -> bool {
+        let texture = &self.textures[&self.texture.texture_2d];
+        if texture.data.is_empty() {
+            self.set_error(GL_INVALID_OPERATION);
+            return false;
+        }
+        let (level_width, level_height) = (texture.level_width as i64, texture.level_height as i64);
+        if xoffset < 0 || yoffset < 0 || (width as GLint) < 0 || (height as GLint) < 0 ||
+            xoffset as i64 + width as i64 > level_width || yoffset as i64 + height as i64 > level_height {
+            self.set_error(GL_INVALID_VALUE);
+            return false;
+        }
+        true
+    }
+
+    fn issue(&mut self, command: Command) {
+        if self.new_list.is_some() {
+            if self.new_list_mode == GL_COMPILE_AND_EXECUTE {
+                self.execute(&command);
             }
-            GL_UNPACK_LSB_FIRST => unsafe {
-                *params = self.unpack_lsb_first;
+            if let Some((_, ref mut display_list)) = self.new_list {
+                display_list.commands.push(command);
             }
-            GL_UNPACK_ROW_LENGTH => unsafe {
-                *params = self.unpack_row_length;
+        } else {
+            self.execute(&command);
+        }
+    }
+
+    fn light(&mut self, light: GLenum, pname: GLenum, params: [GLfloat; 4]) {
+        if light < GL_LIGHT0 || light >= GL_LIGHT0 + MAX_LIGHTS as GLenum {
+            invalid_call!(self, GL_INVALID_ENUM, "glLight called with invalid light: 0x{:08x}", light);
+        }
+        let [x, y, z, w] = params;
+        let is_valid = match pname {
+            GL_SPOT_EXPONENT => x >= 0.0 && x <= 128.0,
+            GL_SPOT_CUTOFF => (x >= 0.0 && x <= 90.0) || x == 180.0,
+            GL_CONSTANT_ATTENUATION | GL_LINEAR_ATTENUATION | GL_QUADRATIC_ATTENUATION => x >= 0.0,
+            _ => true
+        };
+        if !is_valid {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        self.invalidate_locked_arrays();
+        let modelview = self.modelview;
+        let light = &mut self.lighting.lights[(light - GL_LIGHT0) as usize];
+        match pname {
+            GL_AMBIENT => {
+                light.ambient = Vec4::new(x, y, z, w);
             }
-            GL_UNPACK_SKIP_ROWS => unsafe {
-                *params = self.unpack_skip_rows;
+            GL_DIFFUSE => {
+                light.diffuse = Vec4::new(x, y, z, w);
             }
-            GL_UNPACK_SKIP_PIXELS => unsafe {
-                *params = self.unpack_skip_pixels;
+            GL_SPECULAR => {
+                light.specular = Vec4::new(x, y, z, w);
             }
-            GL_UNPACK_ALIGNMENT => unsafe {
-                *params = self.unpack_alignment;
+            GL_POSITION => {
+                light.position = modelview * Vec4::new(x, y, z, w);
             }
-            GL_PACK_SWAP_BYTES => unsafe {
-                *params = self.pack_swap_bytes;
+            GL_SPOT_DIRECTION => {
+                let direction = modelview * Vec4::new(x, y, z, 0.0);
+                light.spot_direction = Vec3::new(direction.x(), direction.y(), direction.z());
             }
-            GL_PACK_LSB_FIRST => unsafe {
-                *params = self.pack_lsb_first;
+            GL_SPOT_EXPONENT => {
+                light.spot_exponent = x;
             }
-            GL_PACK_ROW_LENGTH => unsafe {
-                *params = self.pack_row_length;
+            GL_SPOT_CUTOFF => {
+                light.spot_cutoff = x;
             }
-            GL_PACK_SKIP_ROWS => unsafe {
-                *params = self.pack_skip_rows;
+            GL_CONSTANT_ATTENUATION => {
+                light.constant_attenuation = x;
             }
-            GL_PACK_SKIP_PIXELS => unsafe {
-                *params = self.pack_skip_pixels;
+            GL_LINEAR_ATTENUATION => {
+                light.linear_attenuation = x;
             }
-            GL_PACK_ALIGNMENT => unsafe {
-                *params = self.pack_alignment;
+            GL_QUADRATIC_ATTENUATION => {
+                light.quadratic_attenuation = x;
             }
-            GL_MAX_TEXTURE_SIZE => unsafe {
-                *params = 4096; // TODO: Is this big enough? :)
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glLight called with invalid pname: 0x{:08x}", pname)
+        }
+    }
+
+    // Returns the value of a light parameter as queried with glGetLight*
+    fn light_state(&self, light: GLenum, pname: GLenum) -> Option<StateValue> {
+        if light < GL_LIGHT0 || light >= GL_LIGHT0 + MAX_LIGHTS as GLenum {
+            return None;
+        }
+        let light = &self.lighting.lights[(light - GL_LIGHT0) as usize];
+        Some(match pname {
+            GL_AMBIENT => StateValue::color(light.ambient),
+            GL_DIFFUSE => StateValue::color(light.diffuse),
+            GL_SPECULAR => StateValue::color(light.specular),
+            GL_POSITION => StateValue::vec4(light.position),
+            GL_SPOT_DIRECTION => StateValue::Floats(vec![light.spot_direction.x(), light.spot_direction.y(), light.spot_direction.z()]),
+            GL_SPOT_EXPONENT => StateValue::float(light.spot_exponent),
+            GL_SPOT_CUTOFF => StateValue::float(light.spot_cutoff),
+            GL_CONSTANT_ATTENUATION => StateValue::float(light.constant_attenuation),
+            GL_LINEAR_ATTENUATION => StateValue::float(light.linear_attenuation),
+            GL_QUADRATIC_ATTENUATION => StateValue::float(light.quadratic_attenuation),
+            _ => return None
+        })
+    }
+
+    fn lock_arrays(&mut self, first: GLint, count: GLsizei) {
+        if first < 0 || (count as GLint) <= 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        if self.locked_arrays.is_some() {
+            self.set_error(GL_INVALID_OPERATION);
+            return;
+        }
+        self.locked_arrays = Some(LockedArrays {
+            first: first as _,
+            verts: vec![None; count as usize],
+        });
+    }
+
+    // Control points are copied out of client memory at call time, so they're validated here rather than at execution
+    unsafe fn map1<T: Component>(&mut self, target: GLenum, u1: GLfloat, u2: GLfloat, stride: GLint, order: GLint, points: *const T) {
+        let index = match eval_map_index(target, GL_MAP1_COLOR_4) {
+            Some(index) => index,
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glMap1 called with invalid target: 0x{:08x}", target)
+        };
+        let components = EVAL_MAP_COMPONENTS[index];
+        if u1 == u2 || stride < components as GLint || order < 1 || order > MAX_EVAL_ORDER as GLint {
+            invalid_call!(self, GL_INVALID_VALUE, "glMap1 called with invalid domain, stride or order");
+        }
+        let points = read_control_points(points, components, stride as _, order as _, 0, 1);
+        self.issue(Command::Map1f { target, u1, u2, order, points });
+    }
+
+    unsafe fn map2<T: Component>(&mut self, target: GLenum, u1: GLfloat, u2: GLfloat, ustride: GLint, uorder: GLint, v1: GLfloat, v2: GLfloat, vstride: GLint, vorder: GLint, points: *const T) {
+        let index = match eval_map_index(target, GL_MAP2_COLOR_4) {
+            Some(index) => index,
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glMap2 called with invalid target: 0x{:08x}", target)
+        };
+        let components = EVAL_MAP_COMPONENTS[index];
+        if u1 == u2 || v1 == v2 || ustride < components as GLint || vstride < components as GLint ||
+            uorder < 1 || uorder > MAX_EVAL_ORDER as GLint || vorder < 1 || vorder > MAX_EVAL_ORDER as GLint {
+            invalid_call!(self, GL_INVALID_VALUE, "glMap2 called with invalid domain, stride or order");
+        }
+        let points = read_control_points(points, components, ustride as _, uorder as _, vstride as _, vorder as _);
+        self.issue(Command::Map2f { target, u1, u2, uorder, v1, v2, vorder, points });
+    }
+
+    fn material(&mut self, face: GLenum, pname: GLenum, params: [GLfloat; 4]) {
+        let [x, y, z, w] = params;
+        if pname == GL_SHININESS && (x < 0.0 || x > 128.0) {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        self.invalidate_locked_arrays();
+        let materials = match face {
+            GL_FRONT => vec![&mut self.lighting.front_material],
+            GL_BACK => vec![&mut self.lighting.back_material],
+            GL_FRONT_AND_BACK => vec![&mut self.lighting.front_material, &mut self.lighting.back_material],
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glMaterial called with invalid face: 0x{:08x}", face)
+        };
+        for material in materials {
+            match pname {
+                GL_AMBIENT => {
+                    material.ambient = Vec4::new(x, y, z, w);
+                }
+                GL_DIFFUSE => {
+                    material.diffuse = Vec4::new(x, y, z, w);
+                }
+                GL_SPECULAR => {
+                    material.specular = Vec4::new(x, y, z, w);
+                }
+                GL_EMISSION => {
+                    material.emission = Vec4::new(x, y, z, w);
+                }
+                GL_SHININESS => {
+                    material.shininess = x;
+                }
+                GL_AMBIENT_AND_DIFFUSE => {
+                    material.ambient = Vec4::new(x, y, z, w);
+                    material.diffuse = Vec4::new(x, y, z, w);
+                }
+                _ => invalid_call!(self, GL_INVALID_ENUM, "glMaterial called with invalid pname: 0x{:08x}", pname)
             }
-            _ => panic!("glGetIntegerv called with invalid pname: 0x{:08x}", pname)
         }
     }
 
-    fn issue(&mut self, command: Command) {
-        if let Some(list) = self.new_list {
-            if self.new_list_mode == GL_COMPILE_AND_EXECUTE {
-                self.execute(&command);
-            }
-            self.display_lists[list as usize].borrow_mut().commands.push(command);
-        } else {
-            self.execute(&command);
-        }
+    // Returns the value of a material parameter as queried with glGetMaterial*
+    fn material_state(&self, face: GLenum, pname: GLenum) -> Option<StateValue> {
+        let material = match face {
+            GL_FRONT => &self.lighting.front_material,
+            GL_BACK => &self.lighting.back_material,
+            _ => return None
+        };
+        Some(match pname {
+            GL_AMBIENT => StateValue::color(material.ambient),
+            GL_DIFFUSE => StateValue::color(material.diffuse),
+            GL_SPECULAR => StateValue::color(material.specular),
+            GL_EMISSION => StateValue::color(material.emission),
+            GL_SHININESS => StateValue::float(material.shininess),
+            _ => return None
+        })
     }
 
     fn new_list(&mut self, list: GLuint, mode: GLenum) {
-        self.new_list = Some(list);
+        if list == 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
+        }
+        if self.new_list.is_some() {
+            self.set_error(GL_INVALID_OPERATION);
+            return;
+        }
         self.new_list_mode = match mode {
             GL_COMPILE | GL_COMPILE_AND_EXECUTE => mode,
-            _ => panic!("glNewList called with invalid mode: 0x{:08x}", mode)
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glNewList called with invalid mode: 0x{:08x}", mode)
         };
-        self.display_lists[list as usize].borrow_mut().commands.clear();
+        self.new_list = Some((list, DisplayList::new()));
     }
 
     fn normal_pointer(&mut self, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
         match type_ {
-            GL_FLOAT => {
-                self.normal_type = type_;
-            }
-            _ => panic!("NormalPointer called with invalid type: 0x{:08x}", type_)
+            GL_BYTE | GL_SHORT | GL_INT | GL_FLOAT | GL_DOUBLE => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "NormalPointer called with invalid type: 0x{:08x}", type_)
         }
-        match stride {
-            0 => {
-                self.normal_stride = stride;
-            }
-            _ => panic!("NormalPointer called with invalid stride: {}", stride)
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "NormalPointer called with negative stride: {}", stride as GLint);
         }
-        self.normal_pointer = pointer;
+        self.vertex_arrays.normal_array.set_pointer(3, type_, stride, pointer);
     }
 
     fn pixel_storei(&mut self, pname: GLenum, param: GLint) {
         match pname {
-            GL_UNPACK_SWAP_BYTES => match param {
-                0 | 1 => {
-                    self.unpack_swap_bytes = param;
-                }
-                _ => panic!("glPixelStorei called with invalid param for GL_UNPACK_SWAP_BYTES: 0x{:08x}", param)
+            GL_UNPACK_SWAP_BYTES => {
+                self.pixel_store.unpack.swap_bytes = (param != 0) as _;
             }
-            GL_UNPACK_LSB_FIRST => match param {
-                0 | 1 => {
-                    self.unpack_lsb_first = param;
-                }
-                _ => panic!("glPixelStorei called with invalid param for GL_UNPACK_LSB_FIRST: 0x{:08x}", param)
+            GL_UNPACK_LSB_FIRST => {
+                self.pixel_store.unpack.lsb_first = (param != 0) as _;
             }
             GL_UNPACK_ROW_LENGTH => {
                 if param >= 0 {
-                    self.unpack_row_length = param;
+                    self.pixel_store.unpack.row_length = param;
                 } else {
-                    panic!("glPixelStorei called with invalid param for GL_UNPACK_ROW_LENGTH: 0x{:08x}", param);
+                    invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_UNPACK_ROW_LENGTH: 0x{:08x}", param);
                 }
             }
             GL_UNPACK_SKIP_ROWS => {
                 if param >= 0 {
-                    self.unpack_skip_rows = param;
+                    self.pixel_store.unpack.skip_rows = param;
                 } else {
-                    panic!("glPixelStorei called with invalid param for GL_UNPACK_SKIP_ROWS: 0x{:08x}", param);
+                    invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_UNPACK_SKIP_ROWS: 0x{:08x}", param);
                 }
             }
             GL_UNPACK_SKIP_PIXELS => {
                 if param >= 0 {
-                    self.unpack_skip_pixels = param;
+                    self.pixel_store.unpack.skip_pixels = param;
                 } else {
-                    panic!("glPixelStorei called with invalid param for GL_UNPACK_SKIP_PIXELS: 0x{:08x}", param);
+                    invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_UNPACK_SKIP_PIXELS: 0x{:08x}", param);
                 }
             }
             GL_UNPACK_ALIGNMENT => match param {
                 1 | 2 | 4 | 8 => {
-                    self.unpack_alignment = param;
+                    self.pixel_store.unpack.alignment = param;
+                }
+                _ => invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_UNPACK_ALIGNMENT: 0x{:08x}", param)
+            }
+            GL_PACK_SWAP_BYTES => {
+                self.pixel_store.pack.swap_bytes = (param != 0) as _;
+            }
+            GL_PACK_LSB_FIRST => {
+                self.pixel_store.pack.lsb_first = (param != 0) as _;
+            }
+            GL_PACK_ROW_LENGTH => {
+                if param >= 0 {
+                    self.pixel_store.pack.row_length = param;
+                } else {
+                    invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_PACK_ROW_LENGTH: 0x{:08x}", param);
+                }
+            }
+            GL_PACK_SKIP_ROWS => {
+                if param >= 0 {
+                    self.pixel_store.pack.skip_rows = param;
+                } else {
+                    invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_PACK_SKIP_ROWS: 0x{:08x}", param);
+                }
+            }
+            GL_PACK_SKIP_PIXELS => {
+                if param >= 0 {
+                    self.pixel_store.pack.skip_pixels = param;
+                } else {
+                    invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_PACK_SKIP_PIXELS: 0x{:08x}", param);
+                }
+            }
+            GL_PACK_ALIGNMENT => match param {
+                1 | 2 | 4 | 8 => {
+                    self.pixel_store.pack.alignment = param;
                 }
-                _ => panic!("glPixelStorei called with invalid param for GL_UNPACK_ALIGNMENT: 0x{:08x}", param)
+                _ => invalid_call!(self, GL_INVALID_VALUE, "glPixelStorei called with invalid param for GL_PACK_ALIGNMENT: 0x{:08x}", param)
             }
-            _ => panic!("glPixelStorei called with invalid pname: 0x{:08x}", pname)
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glPixelStorei called with invalid pname: 0x{:08x}", pname)
         }
     }
 
-    fn swap_buffers(&mut self, dc: HDC) -> BOOL {
-        println!("swap_buffers: dc: 0x{:08x}", dc as u32);
-        self.window.update_with_buffer(&self.back_buffer).expect("Couldn't swap buffers");
-        TRUE
+    fn pop_attrib(&mut self) {
+        let attribs = match self.attrib_stack.pop() {
+            Some(attribs) => attribs,
+            _ => {
+                self.set_error(GL_STACK_UNDERFLOW);
+                return;
+            }
+        };
+        let mask = attribs.mask;
+        if (mask & GL_CURRENT_BIT) != 0 {
+            self.current = attribs.current;
+            self.vertex_cache.invalidate();
+            self.invalidate_locked_arrays();
+        }
+        if (mask & GL_POINT_BIT) != 0 {
+            self.point = attribs.point;
+        }
+        if (mask & GL_LINE_BIT) != 0 {
+            self.line = attribs.line;
+        }
+        if (mask & GL_POLYGON_BIT) != 0 {
+            self.polygon = attribs.polygon;
+        }
+        if (mask & GL_PIXEL_MODE_BIT) != 0 {
+            self.pixel_mode = attribs.pixel_mode;
+        }
+        if (mask & GL_LIGHTING_BIT) != 0 {
+            self.lighting = attribs.lighting;
+        }
+        if (mask & GL_FOG_BIT) != 0 {
+            self.fog = attribs.fog;
+        }
+        if (mask & GL_DEPTH_BUFFER_BIT) != 0 {
+            self.depth = attribs.depth;
+        }
+        if (mask & GL_VIEWPORT_BIT) != 0 {
+            self.viewport = attribs.viewport;
+        }
+        if (mask & GL_TRANSFORM_BIT) != 0 {
+            self.transform = attribs.transform;
+        }
+        if (mask & GL_COLOR_BUFFER_BIT) != 0 {
+            self.color_buffer = attribs.color_buffer;
+        }
+        if (mask & GL_EVAL_BIT) != 0 {
+            self.eval = attribs.eval;
+        }
+        if (mask & GL_LIST_BIT) != 0 {
+            self.list_base = attribs.list_base;
+        }
+        if (mask & GL_TEXTURE_BIT) != 0 {
+            self.texture = attribs.texture;
+            // The saved binding may have been deleted since, in which case it's recreated like on first bind
+            self.textures.entry(self.texture.texture_2d).or_insert_with(Texture::new).set_parameters(attribs.texture_parameters);
+        }
+        if (mask & GL_ENABLE_BIT) != 0 {
+            self.eval.auto_normal = attribs.enable.auto_normal;
+            self.color_buffer.alpha_test_enable = attribs.enable.alpha_test;
+            self.color_buffer.blend_enable = attribs.enable.blend;
+            self.fog.color_sum_enable = attribs.enable.color_sum;
+            self.depth.depth_test = attribs.enable.depth_test;
+            self.lighting.lighting_enable = attribs.enable.lighting;
+            for (light, &enabled) in self.lighting.lights.iter_mut().zip(attribs.enable.lights.iter()) {
+                light.enabled = enabled;
+            }
+            self.line.line_stipple_enable = attribs.enable.line_stipple;
+            self.eval.map1_enables = attribs.enable.map1;
+            self.eval.map2_enables = attribs.enable.map2;
+            self.point.point_sprite_enable = attribs.enable.point_sprite;
+            self.polygon.polygon_offset_fill_enable = attribs.enable.polygon_offset_fill;
+            self.polygon.polygon_offset_line_enable = attribs.enable.polygon_offset_line;
+            self.polygon.polygon_offset_point_enable = attribs.enable.polygon_offset_point;
+            self.texture.texture_2d_enable = attribs.enable.texture_2d;
+        }
+        // Lighting decides the transformed secondary color, so locked vertices may be stale
+        if (mask & (GL_LIGHTING_BIT | GL_ENABLE_BIT)) != 0 {
+            self.invalidate_locked_arrays();
+        }
     }
 
-    fn tex_image_2d(&mut self, target: GLenum, level: GLint, internalformat: GLint, mut width: GLsizei, mut height: GLsizei, border: GLint, format: GLenum, type_: GLenum, data: *const GLvoid) {
-        if target != GL_TEXTURE_2D {
-            panic!("glTexImage2D called with invalid target: 0x{:08x}", target);
+    fn pop_client_attrib(&mut self) {
+        let attribs = match self.client_attrib_stack.pop() {
+            Some(attribs) => attribs,
+            _ => {
+                self.set_error(GL_STACK_UNDERFLOW);
+                return;
+            }
+        };
+        if (attribs.mask & GL_CLIENT_PIXEL_STORE_BIT) != 0 {
+            self.pixel_store = attribs.pixel_store;
+        }
+        if (attribs.mask & GL_CLIENT_VERTEX_ARRAY_BIT) != 0 {
+            self.vertex_arrays = attribs.vertex_arrays;
+            self.vertex_cache.invalidate();
+            self.invalidate_locked_arrays();
         }
+    }
 
-        if level > 0 {
-            println!("Skipping glTexImage2D call with level > 0, level: 0x{:08x}", level);
+    unsafe fn prioritize_textures(&mut self, n: GLsizei, textures: *const GLuint, priorities: *const GLclampf) {
+        if (n as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
             return;
         }
+        let textures = slice::from_raw_parts(textures, n as _);
+        let priorities = slice::from_raw_parts(priorities, n as _);
+        for (texture, &priority) in textures.iter().zip(priorities.iter()) {
+            // Priorities for the default texture and unused names are silently ignored
+            if *texture == 0 {
+                continue;
+            }
+            if let Some(texture) = self.textures.get_mut(texture) {
+                texture.priority = priority.max(0.0).min(1.0);
+            }
+        }
+    }
 
-        if border != 0 {
-            panic!("glTexImage2D called with border != 0, border: 0x{:08x}", border);
+    // GL_SCISSOR_BIT, GL_STENCIL_BUFFER_BIT and the other groups we don't model are accepted but save nothing
+    fn push_attrib(&mut self, mask: GLbitfield) {
+        if self.attrib_stack.len() >= MAX_ATTRIB_STACK_DEPTH {
+            self.set_error(GL_STACK_OVERFLOW);
+            return;
+        }
+        self.attrib_stack.push(ServerAttribs {
+            mask,
+            current: self.current,
+            point: self.point,
+            line: self.line,
+            polygon: self.polygon,
+            pixel_mode: self.pixel_mode,
+            lighting: self.lighting,
+            fog: self.fog,
+            depth: self.depth,
+            viewport: self.viewport,
+            transform: self.transform,
+            enable: EnableState {
+                auto_normal: self.eval.auto_normal,
+                alpha_test: self.color_buffer.alpha_test_enable,
+                blend: self.color_buffer.blend_enable,
+                color_sum: self.fog.color_sum_enable,
+                depth_test: self.depth.depth_test,
+                lighting: self.lighting.lighting_enable,
+                lights: {
+                    let mut lights = [false; MAX_LIGHTS];
+                    for (enabled, light) in lights.iter_mut().zip(self.lighting.lights.iter()) {
+                        *enabled = light.enabled;
+                    }
+                    lights
+                },
+                line_stipple: self.line.line_stipple_enable,
+                map1: self.eval.map1_enables,
+                map2: self.eval.map2_enables,
+                point_sprite: self.point.point_sprite_enable,
+                polygon_offset_fill: self.polygon.polygon_offset_fill_enable,
+                polygon_offset_line: self.polygon.polygon_offset_line_enable,
+                polygon_offset_point: self.polygon.polygon_offset_point_enable,
+                texture_2d: self.texture.texture_2d_enable,
+            },
+            color_buffer: self.color_buffer,
+            eval: self.eval,
+            list_base: self.list_base,
+            texture: self.texture,
+            texture_parameters: self.textures[&self.texture.texture_2d].parameters(),
+        });
+    }
+
+    fn push_client_attrib(&mut self, mask: GLbitfield) {
+        if self.client_attrib_stack.len() >= MAX_CLIENT_ATTRIB_STACK_DEPTH {
+            self.set_error(GL_STACK_OVERFLOW);
+            return;
+        }
+        self.client_attrib_stack.push(ClientAttribs {
+            mask,
+            pixel_store: self.pixel_store,
+            vertex_arrays: self.vertex_arrays,
+        });
+    }
+
+    // Reads a region of the back buffer into texels (bottom row first, like everything else in GL), counting it as an
+    // explicit framebuffer-to-texture transfer. Pixels outside the framebuffer are undefined and read as 0.
+    fn read_framebuffer(&mut self, x: GLint, y: GLint, width: usize, height: usize) -> Vec<u32> {
+        let mut texels = vec![0; width * height];
+        for j in 0..height {
+            for i in 0..width {
+                if let Some(buffer_index) = framebuffer_index(x + i as GLint, y + j as GLint) {
+                    texels[j * width + i] = self.back_buffer[buffer_index];
+                }
+            }
+        }
+
+        if width > 0 && height > 0 {
+            let framebuffer_width = ((x + width as GLint - 1).div_euclid(2) - x.div_euclid(2) + 1) as usize;
+            let framebuffer_height = ((y + height as GLint - 1).div_euclid(2) - y.div_euclid(2) + 1) as usize;
+            self.memory_traffic.framebuffer_to_texture += framebuffer_width * framebuffer_height * mem::size_of::<u32>();
+        }
+
+        texels
+    }
+
+    unsafe fn read_pixels(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, pixels: *mut GLvoid) {
+        if (width as GLint) < 0 || (height as GLint) < 0 {
+            self.set_error(GL_INVALID_VALUE);
+            return;
         }
 
         let num_components = match format {
+            GL_DEPTH_COMPONENT => 1,
             GL_RGB => 3,
-            GL_RGBA => 4,
-            _ => panic!("glTexImage2D called with invalid format: 0x{:08x}", format)
+            GL_RGBA | GL_BGRA_EXT => 4,
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glReadPixels called with invalid format: 0x{:08x}", format)
         };
+        match (format, type_) {
+            (GL_DEPTH_COMPONENT, GL_UNSIGNED_SHORT) | (GL_DEPTH_COMPONENT, GL_UNSIGNED_INT) | (GL_DEPTH_COMPONENT, GL_FLOAT) => (),
+            (GL_RGB, GL_UNSIGNED_BYTE) | (GL_RGBA, GL_UNSIGNED_BYTE) | (GL_BGRA_EXT, GL_UNSIGNED_BYTE) => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glReadPixels called with invalid type for format 0x{:08x}: 0x{:08x}", format, type_)
+        }
 
-        println!("TexImage2D: internalformat: 0x{:08x}, width: 0x{:08x}, height: 0x{:08x}, data: 0x{:08x}", internalformat, width, height, data as u32);
-
-        let mut texture_data = vec![0; (width * height) as usize];
-
-        for y in 0..height as usize {
-            for x in 0..width as usize {
-                let buffer_index = y * width as usize + x;
-                let color = match type_ {
-                    GL_UNSIGNED_BYTE => {
-                        let data = unsafe { (data as *const u8).add(buffer_index * num_components) };
-                        let red = unsafe { *data.add(0) } as u32;
-                        let green = unsafe { *data.add(1) } as u32;
-                        let blue = unsafe { *data.add(2) } as u32;
-                        let alpha = if num_components == 4 { unsafe { *data.add(3) } } else { 255 } as u32;
-                        (alpha << 24) | (red << 16) | (green << 8) | (blue << 0)
-                    }
-                    GL_UNSIGNED_SHORT => {
-                        let data = unsafe { (data as *const u16).add(buffer_index * num_components) };
-                        let red = unsafe { *data.add(0) } as u32 >> 8;
-                        let green = unsafe { *data.add(1) } as u32 >> 8;
-                        let blue = unsafe { *data.add(2) } as u32 >> 8;
-                        let alpha = if num_components == 4 { unsafe { *data.add(3) } } else { 65535 } as u32 >> 8;
-                        (alpha << 24) | (red << 16) | (green << 8) | (blue << 0)
-                    }
-                    _ => panic!("glTexImage2D called with invalid type: 0x{:08x}", type_)
+        let width = width as usize;
+        let height = height as usize;
+        let element_size = type_size(type_);
+        let (offset, row_stride) = pixel_layout(width, num_components, element_size, self.pixel_store.pack.row_length, self.pixel_store.pack.skip_pixels, self.pixel_store.pack.skip_rows, self.pixel_store.pack.alignment);
+        let start = (pixels as *mut u8).add(offset);
+        let swap_bytes = self.pixel_store.pack.swap_bytes != 0;
+
+        for j in 0..height {
+            for i in 0..width {
+                // Pixels outside the framebuffer are undefined, so we leave them untouched
+                let buffer_index = match framebuffer_index(x + i as GLint, y + j as GLint) {
+                    Some(buffer_index) => buffer_index,
+                    _ => continue
                 };
-                texture_data[buffer_index] = color;
+                let pixel = start.add(j * row_stride + i * num_components * element_size);
+                if format == GL_DEPTH_COMPONENT {
+                    let depth = self.depth_buffer[buffer_index];
+                    match type_ {
+                        GL_UNSIGNED_SHORT => {
+                            let value = if swap_bytes { depth.swap_bytes() } else { depth };
+                            ptr::write_unaligned(pixel as *mut u16, value);
+                        }
+                        GL_UNSIGNED_INT => {
+                            // Replicating the bits maps 0xffff exactly to 0xffffffff
+                            let value = (depth as u32) * 0x10001;
+                            ptr::write_unaligned(pixel as *mut u32, if swap_bytes { value.swap_bytes() } else { value });
+                        }
+                        _ => {
+                            let value = (depth as f32 / 65535.0).to_bits();
+                            ptr::write_unaligned(pixel as *mut u32, if swap_bytes { value.swap_bytes() } else { value });
+                        }
+                    }
+                } else {
+                    let color = self.back_buffer[buffer_index];
+                    let red = (color >> 16) as u8;
+                    let green = (color >> 8) as u8;
+                    let blue = (color >> 0) as u8;
+                    let alpha = (color >> 24) as u8;
+                    let components = match format {
+                        GL_RGB => [red, green, blue, 0],
+                        GL_RGBA => [red, green, blue, alpha],
+                        _ => [blue, green, red, alpha]
+                    };
+                    ptr::copy_nonoverlapping(components.as_ptr(), pixel, num_components);
+                }
+            }
+        }
+    }
+
+    // Errors raised without a message still panic in strict mode, so no error path goes unnoticed (run with
+    // RUST_BACKTRACE=1 to find the call)
+    fn set_error(&mut self, error: GLenum) {
+        if self.strict {
+            panic!("GL error 0x{:04x} flagged in strict mode", error);
+        }
+        // Only the first error is recorded until it's cleared by glGetError
+        if self.error == GL_NO_ERROR {
+            self.error = error;
+        }
+    }
+
+    // Stores a new level 0 image for the bound texture, downscaling it to fit the rasterizer's texture memory
+    fn set_texture_image(&mut self, internalformat: GLint, mut width: GLsizei, mut height: GLsizei, mut texture_data: Vec<u32>) {
+        if !internal_format_has_alpha(internalformat) {
+            for texel in texture_data.iter_mut() {
+                *texel |= 0xff000000;
             }
         }
 
+        let level_width = width as usize;
+        let level_height = height as usize;
+        let mut downscale_shift = 0;
+
         while width > MAX_TEXTURE_DIM as u32 || height > MAX_TEXTURE_DIM as u32 {
             let scaled_width = width / 2;
             let scaled_height = height / 2;
@@ -1282,34 +4831,453 @@ impl Context {
             width = scaled_width;
             height = scaled_height;
             texture_data = scaled_data;
+            downscale_shift += 1;
         }
 
-        let texture = &mut self.textures[self.texture_2d as usize];
+        let texture = self.bound_texture_2d_mut();
+        texture.internal_format = internalformat;
+        texture.level_width = level_width;
+        texture.level_height = level_height;
+        texture.downscale_shift = downscale_shift;
         texture.width = width as usize;
         texture.height = height as usize;
         texture.data = texture_data;
     }
 
-    fn vertex_pointer(&mut self, size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
-        match size {
-            3 => {
-                self.vertex_size = size;
+    // Writes a region given in level 0 texels into the bound texture. If the texture is stored downscaled, each stored
+    // texel touched by the region becomes the average of the region texels covering it.
+    fn set_texture_region(&mut self, xoffset: usize, yoffset: usize, width: usize, height: usize, region: &[u32]) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let texture = self.bound_texture_2d_mut();
+        let alpha_mask = if internal_format_has_alpha(texture.internal_format) { 0 } else { 0xff000000 };
+        let shift = texture.downscale_shift;
+        for texel_y in (yoffset >> shift)..=((yoffset + height - 1) >> shift) {
+            for texel_x in (xoffset >> shift)..=((xoffset + width - 1) >> shift) {
+                let mut sum = [0; 4];
+                let mut count = 0;
+                for y in (texel_y << shift).max(yoffset)..((texel_y + 1) << shift).min(yoffset + height) {
+                    for x in (texel_x << shift).max(xoffset)..((texel_x + 1) << shift).min(xoffset + width) {
+                        let color = region[(y - yoffset) * width + (x - xoffset)];
+                        for (i, channel) in sum.iter_mut().enumerate() {
+                            *channel += (color >> (i * 8)) & 0xff;
+                        }
+                        count += 1;
+                    }
+                }
+                let color = sum.iter().enumerate().fold(0, |color, (i, &channel)| color | ((channel / count) << (i * 8)));
+                texture.data[texel_y * texture.width + texel_x] = color | alpha_mask;
+            }
+        }
+    }
+
+    // Returns the value of a piece of state as queried with glGet*, or None if pname isn't a valid query
+    fn state(&self, pname: GLenum) -> Option<StateValue> {
+        if let Some(enabled) = self.enabled(pname) {
+            return Some(StateValue::boolean(enabled));
+        }
+        let current_array = &self.vertex_arrays.tex_coord_arrays[self.vertex_arrays.client_active_texture];
+        Some(match pname {
+            GL_CURRENT_COLOR => StateValue::color(self.current.color),
+            GL_CURRENT_SECONDARY_COLOR_EXT => {
+                let secondary_color = self.current.secondary_color;
+                StateValue::color(Vec4::new(secondary_color.x(), secondary_color.y(), secondary_color.z(), 0.0))
+            }
+            GL_CURRENT_NORMAL => {
+                let normal = self.current.normal;
+                StateValue::NormalizedFloats(vec![normal.x(), normal.y(), normal.z()])
+            }
+            GL_CURRENT_TEXTURE_COORDS => StateValue::Floats(vec![self.current.tex_coord.x(), self.current.tex_coord.y(), 0.0, 1.0]),
+            GL_CURRENT_RASTER_COLOR => StateValue::color(self.current.raster_color),
+            GL_CURRENT_RASTER_POSITION => {
+                // The raster position is kept in framebuffer pixels, but queried in the app's window coordinates
+                let position = self.current.raster_position;
+                StateValue::Floats(vec![position.x() / FRAMEBUFFER_SCALE, position.y() / FRAMEBUFFER_SCALE, position.z(), 1.0])
+            }
+            GL_CURRENT_RASTER_POSITION_VALID => StateValue::boolean(self.current.raster_position_valid),
+            GL_EDGE_FLAG => StateValue::boolean(self.current.edge_flag),
+
+            GL_POINT_SIZE => StateValue::float(self.point.point_size),
+            GL_LINE_WIDTH => StateValue::float(self.line.line_width),
+            GL_LINE_STIPPLE_PATTERN => StateValue::integer(self.line.line_stipple_pattern as _),
+            GL_LINE_STIPPLE_REPEAT => StateValue::integer(self.line.line_stipple_factor),
+            GL_POLYGON_MODE => {
+                let polygon_mode = |mode| match mode {
+                    PolygonMode::Point => GL_POINT as _,
+                    PolygonMode::Line => GL_LINE as _,
+                    PolygonMode::Fill => GL_FILL as _,
+                };
+                StateValue::Integers(vec![polygon_mode(self.polygon.polygon_mode_front), polygon_mode(self.polygon.polygon_mode_back)])
             }
-            _ => panic!("VertexPointer called with invalid size: {}", size)
+            GL_FRONT_FACE => StateValue::integer(match self.polygon.front_face {
+                FrontFace::Cw => GL_CW,
+                FrontFace::Ccw => GL_CCW,
+            } as _),
+            GL_POLYGON_OFFSET_FACTOR => StateValue::float(self.polygon.polygon_offset_factor),
+            GL_POLYGON_OFFSET_UNITS => StateValue::float(self.polygon.polygon_offset_units),
+            GL_LIGHT_MODEL_AMBIENT => StateValue::color(self.lighting.light_model_ambient),
+            GL_LIGHT_MODEL_LOCAL_VIEWER => StateValue::boolean(self.lighting.light_model_local_viewer),
+            GL_LIGHT_MODEL_TWO_SIDE => StateValue::boolean(self.lighting.light_model_two_side),
+            GL_LIGHT_MODEL_COLOR_CONTROL => StateValue::integer(self.lighting.light_model_color_control as _),
+            GL_SHADE_MODEL => StateValue::integer(match self.lighting.shade_model {
+                ShadeModel::Flat => GL_FLAT,
+                ShadeModel::Smooth => GL_SMOOTH,
+            } as _),
+            GL_ZOOM_X => StateValue::float(self.pixel_mode.pixel_zoom_x),
+            GL_ZOOM_Y => StateValue::float(self.pixel_mode.pixel_zoom_y),
+
+            GL_MAP1_GRID_DOMAIN => StateValue::Floats(vec![self.eval.map1_grid.c1, self.eval.map1_grid.c2]),
+            GL_MAP1_GRID_SEGMENTS => StateValue::integer(self.eval.map1_grid.segments),
+            GL_MAP2_GRID_DOMAIN => StateValue::Floats(vec![self.eval.map2_grid_u.c1, self.eval.map2_grid_u.c2, self.eval.map2_grid_v.c1, self.eval.map2_grid_v.c2]),
+            GL_MAP2_GRID_SEGMENTS => StateValue::Integers(vec![self.eval.map2_grid_u.segments, self.eval.map2_grid_v.segments]),
+
+            // Depth range, clear value and function are fixed
+            GL_DEPTH_RANGE => StateValue::NormalizedFloats(vec![0.0, 1.0]),
+            GL_DEPTH_WRITEMASK => StateValue::boolean(self.depth.depth_mask),
+            GL_DEPTH_CLEAR_VALUE => StateValue::NormalizedFloats(vec![1.0]),
+            GL_DEPTH_FUNC => StateValue::integer(GL_LESS as _),
+
+            GL_ALPHA_TEST_FUNC => StateValue::integer(match self.color_buffer.alpha_test_func {
+                CompareFunc::Never => GL_NEVER,
+                CompareFunc::Less => GL_LESS,
+                CompareFunc::Equal => GL_EQUAL,
+                CompareFunc::LessEqual => GL_LEQUAL,
+                CompareFunc::Greater => GL_GREATER,
+                CompareFunc::NotEqual => GL_NOTEQUAL,
+                CompareFunc::GreaterEqual => GL_GEQUAL,
+                CompareFunc::Always => GL_ALWAYS,
+            } as _),
+            GL_ALPHA_TEST_REF => StateValue::NormalizedFloats(vec![self.color_buffer.alpha_test_ref]),
+            GL_BLEND_SRC => StateValue::integer(match self.color_buffer.blend_src_factor {
+                BlendSrcFactor::Zero => GL_ZERO,
+                BlendSrcFactor::One => GL_ONE,
+                BlendSrcFactor::SrcColor => GL_SRC_COLOR,
+                BlendSrcFactor::SrcAlpha => GL_SRC_ALPHA,
+            } as _),
+            GL_BLEND_DST => StateValue::integer(match self.color_buffer.blend_dst_factor {
+                BlendDstFactor::Zero => GL_ZERO,
+                BlendDstFactor::One => GL_ONE,
+                BlendDstFactor::SrcAlpha => GL_SRC_ALPHA,
+                BlendDstFactor::OneMinusSrcAlpha => GL_ONE_MINUS_SRC_ALPHA,
+            } as _),
+            GL_COLOR_CLEAR_VALUE => StateValue::NormalizedFloats(vec![
+                self.color_buffer.clear_color_red,
+                self.color_buffer.clear_color_green,
+                self.color_buffer.clear_color_blue,
+                self.color_buffer.clear_color_alpha,
+            ]),
+            GL_COLOR_WRITEMASK => StateValue::Booleans(vec![
+                self.color_buffer.color_mask_red,
+                self.color_buffer.color_mask_green,
+                self.color_buffer.color_mask_blue,
+                self.color_buffer.color_mask_alpha,
+            ]),
+
+            GL_MATRIX_MODE => StateValue::integer(match self.transform.matrix_mode {
+                MatrixMode::ModelView => GL_MODELVIEW,
+                MatrixMode::Projection => GL_PROJECTION,
+                MatrixMode::Texture => GL_TEXTURE,
+            } as _),
+            // Undo the viewport auto-scaling (see Command::Viewport)
+            GL_VIEWPORT => StateValue::Integers(vec![self.viewport.x * 2, self.viewport.y * 2, self.viewport.width as GLint * 2, self.viewport.height as GLint * 2]),
+            GL_MODELVIEW_MATRIX => StateValue::matrix(self.modelview),
+            GL_PROJECTION_MATRIX => StateValue::matrix(self.projection),
+            GL_TEXTURE_MATRIX => StateValue::matrix(self.texture_matrix),
+            GL_TRANSPOSE_MODELVIEW_MATRIX_ARB => StateValue::matrix(self.modelview.transpose()),
+            GL_TRANSPOSE_PROJECTION_MATRIX_ARB => StateValue::matrix(self.projection.transpose()),
+            GL_TRANSPOSE_TEXTURE_MATRIX_ARB => StateValue::matrix(self.texture_matrix.transpose()),
+            GL_MODELVIEW_STACK_DEPTH => StateValue::integer((self.modelview_stack.len() + 1) as _),
+            GL_PROJECTION_STACK_DEPTH => StateValue::integer((self.projection_stack.len() + 1) as _),
+            GL_TEXTURE_STACK_DEPTH => StateValue::integer((self.texture_stack.len() + 1) as _),
+            GL_ATTRIB_STACK_DEPTH => StateValue::integer(self.attrib_stack.len() as _),
+            GL_CLIENT_ATTRIB_STACK_DEPTH => StateValue::integer(self.client_attrib_stack.len() as _),
+
+            GL_LIST_MODE => StateValue::integer(if self.new_list.is_some() { self.new_list_mode as _ } else { 0 }),
+            GL_LIST_BASE => StateValue::integer(self.list_base as _),
+            GL_LIST_INDEX => StateValue::integer(match self.new_list {
+                Some((list, _)) => list as _,
+                _ => 0
+            }),
+
+            GL_TEXTURE_BINDING_2D => StateValue::integer(self.texture.texture_2d as _),
+
+            GL_UNPACK_SWAP_BYTES => StateValue::boolean(self.pixel_store.unpack.swap_bytes != 0),
+            GL_UNPACK_LSB_FIRST => StateValue::boolean(self.pixel_store.unpack.lsb_first != 0),
+            GL_UNPACK_ROW_LENGTH => StateValue::integer(self.pixel_store.unpack.row_length),
+            GL_UNPACK_SKIP_ROWS => StateValue::integer(self.pixel_store.unpack.skip_rows),
+            GL_UNPACK_SKIP_PIXELS => StateValue::integer(self.pixel_store.unpack.skip_pixels),
+            GL_UNPACK_ALIGNMENT => StateValue::integer(self.pixel_store.unpack.alignment),
+            GL_PACK_SWAP_BYTES => StateValue::boolean(self.pixel_store.pack.swap_bytes != 0),
+            GL_PACK_LSB_FIRST => StateValue::boolean(self.pixel_store.pack.lsb_first != 0),
+            GL_PACK_ROW_LENGTH => StateValue::integer(self.pixel_store.pack.row_length),
+            GL_PACK_SKIP_ROWS => StateValue::integer(self.pixel_store.pack.skip_rows),
+            GL_PACK_SKIP_PIXELS => StateValue::integer(self.pixel_store.pack.skip_pixels),
+            GL_PACK_ALIGNMENT => StateValue::integer(self.pixel_store.pack.alignment),
+
+            GL_VERTEX_ARRAY_SIZE => StateValue::integer(self.vertex_arrays.vertex_array.size),
+            GL_VERTEX_ARRAY_TYPE => StateValue::integer(self.vertex_arrays.vertex_array.type_ as _),
+            GL_VERTEX_ARRAY_STRIDE => StateValue::integer(self.vertex_arrays.vertex_array.stride as _),
+            GL_NORMAL_ARRAY_TYPE => StateValue::integer(self.vertex_arrays.normal_array.type_ as _),
+            GL_NORMAL_ARRAY_STRIDE => StateValue::integer(self.vertex_arrays.normal_array.stride as _),
+            GL_COLOR_ARRAY_SIZE => StateValue::integer(self.vertex_arrays.color_array.size),
+            GL_COLOR_ARRAY_TYPE => StateValue::integer(self.vertex_arrays.color_array.type_ as _),
+            GL_COLOR_ARRAY_STRIDE => StateValue::integer(self.vertex_arrays.color_array.stride as _),
+            GL_SECONDARY_COLOR_ARRAY_SIZE_EXT => StateValue::integer(self.vertex_arrays.secondary_color_array.size),
+            GL_SECONDARY_COLOR_ARRAY_TYPE_EXT => StateValue::integer(self.vertex_arrays.secondary_color_array.type_ as _),
+            GL_SECONDARY_COLOR_ARRAY_STRIDE_EXT => StateValue::integer(self.vertex_arrays.secondary_color_array.stride as _),
+            GL_TEXTURE_COORD_ARRAY_SIZE => StateValue::integer(current_array.size),
+            GL_TEXTURE_COORD_ARRAY_TYPE => StateValue::integer(current_array.type_ as _),
+            GL_TEXTURE_COORD_ARRAY_STRIDE => StateValue::integer(current_array.stride as _),
+            GL_EDGE_FLAG_ARRAY_STRIDE => StateValue::integer(self.vertex_arrays.edge_flag_array.stride as _),
+            GL_ACTIVE_TEXTURE_ARB => StateValue::integer((GL_TEXTURE0_ARB + self.texture.active_texture as GLenum) as _),
+            GL_CLIENT_ACTIVE_TEXTURE_ARB => StateValue::integer((GL_TEXTURE0_ARB + self.vertex_arrays.client_active_texture as GLenum) as _),
+            GL_ARRAY_ELEMENT_LOCK_FIRST_EXT => StateValue::integer(self.locked_arrays.as_ref().map_or(0, |locked_arrays| locked_arrays.first as _)),
+            GL_ARRAY_ELEMENT_LOCK_COUNT_EXT => StateValue::integer(self.locked_arrays.as_ref().map_or(0, |locked_arrays| locked_arrays.verts.len() as _)),
+
+            GL_MAX_EVAL_ORDER => StateValue::integer(MAX_EVAL_ORDER as _),
+            GL_MAX_LIGHTS => StateValue::integer(MAX_LIGHTS as _),
+            GL_MAX_TEXTURE_SIZE => StateValue::integer(4096), // TODO: Is this big enough? :)
+            GL_MAX_ATTRIB_STACK_DEPTH => StateValue::integer(MAX_ATTRIB_STACK_DEPTH as _),
+            GL_MAX_MODELVIEW_STACK_DEPTH => StateValue::integer(MAX_MODELVIEW_STACK_DEPTH as _),
+            GL_MAX_PROJECTION_STACK_DEPTH => StateValue::integer(MAX_PROJECTION_STACK_DEPTH as _),
+            GL_MAX_TEXTURE_STACK_DEPTH => StateValue::integer(MAX_TEXTURE_STACK_DEPTH as _),
+            GL_MAX_VIEWPORT_DIMS => StateValue::Integers(vec![(WIDTH * 2) as _, (HEIGHT * 2) as _]),
+            GL_MAX_CLIENT_ATTRIB_STACK_DEPTH => StateValue::integer(MAX_CLIENT_ATTRIB_STACK_DEPTH as _),
+            GL_MAX_TEXTURE_UNITS_ARB => StateValue::integer(MAX_TEXTURE_UNITS as _),
+
+            GL_RGBA_MODE => StateValue::boolean(true),
+            GL_DOUBLEBUFFER => StateValue::boolean(true),
+            GL_RED_BITS | GL_GREEN_BITS | GL_BLUE_BITS | GL_ALPHA_BITS => StateValue::integer(8),
+            GL_DEPTH_BITS => StateValue::integer(16),
+            GL_STENCIL_BITS => StateValue::integer(0),
+
+            _ => return None
+        })
+    }
+
+    fn swap_buffers(&mut self, dc: HDC) -> BOOL {
+        println!("swap_buffers: dc: 0x{:08x}", dc as u32);
+        let lookups = self.vertex_cache.hits + self.vertex_cache.misses;
+        if lookups > 0 {
+            println!("  Vertex cache: {} hits, {} misses ({:.1}% hit rate)", self.vertex_cache.hits, self.vertex_cache.misses, self.vertex_cache.hits as f32 * 100.0 / lookups as f32);
+        }
+        println!("  Transformed vertices: {}", self.transformed_vert_count);
+        println!("  Texture memory: {} bytes in {} textures", self.texture_memory(), self.textures.len());
+        println!("  Memory traffic: {} bytes tile loads, {} bytes tile stores, {} bytes framebuffer to texture", self.memory_traffic.tile_loads, self.memory_traffic.tile_stores, self.memory_traffic.framebuffer_to_texture);
+        self.memory_traffic = MemoryTraffic::new();
+        self.vertex_cache.reset_stats();
+        self.transformed_vert_count = 0;
+        self.window.update_with_buffer(&self.back_buffer).expect("Couldn't swap buffers");
+        TRUE
+    }
+
+    fn tex_image_2d(&mut self, target: GLenum, level: GLint, internalformat: GLint, width: GLsizei, height: GLsizei, border: GLint, format: GLenum, type_: GLenum, data: *const GLvoid) {
+        if target != GL_TEXTURE_2D {
+            invalid_call!(self, GL_INVALID_ENUM, "glTexImage2D called with invalid target: 0x{:08x}", target);
+        }
+
+        if level > 0 {
+            println!("Skipping glTexImage2D call with level > 0, level: 0x{:08x}", level);
+            return;
+        }
+
+        if border != 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "glTexImage2D called with border != 0, border: 0x{:08x}", border);
+        }
+
+        if (width as GLint) < 0 || (height as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "glTexImage2D called with negative size: {}x{}", width as GLint, height as GLint);
+        }
+
+        let num_components = match format {
+            GL_RGB => 3,
+            GL_RGBA => 4,
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glTexImage2D called with invalid format: 0x{:08x}", format)
+        };
+        match type_ {
+            GL_UNSIGNED_BYTE | GL_UNSIGNED_SHORT => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glTexImage2D called with invalid type: 0x{:08x}", type_)
+        }
+
+        println!("TexImage2D: internalformat: 0x{:08x}, width: 0x{:08x}, height: 0x{:08x}, data: 0x{:08x}", internalformat, width, height, data as u32);
+
+        // A null pointer allocates the image without initializing it
+        let texture_data = if data.is_null() {
+            vec![0; (width * height) as usize]
+        } else {
+            unsafe { self.unpack_image(width as _, height as _, num_components, type_, data) }
+        };
+        self.set_texture_image(internalformat, width, height, texture_data);
+    }
+
+    fn tex_sub_image_2d(&mut self, target: GLenum, level: GLint, xoffset: GLint, yoffset: GLint, width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, data: *const GLvoid) {
+        if target != GL_TEXTURE_2D {
+            invalid_call!(self, GL_INVALID_ENUM, "glTexSubImage2D called with invalid target: 0x{:08x}", target);
+        }
+
+        if level > 0 {
+            println!("Skipping glTexSubImage2D call with level > 0, level: 0x{:08x}", level);
+            return;
+        }
+
+        let num_components = match format {
+            GL_RGB => 3,
+            GL_RGBA => 4,
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glTexSubImage2D called with invalid format: 0x{:08x}", format)
+        };
+        match type_ {
+            GL_UNSIGNED_BYTE | GL_UNSIGNED_SHORT => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "glTexSubImage2D called with invalid type: 0x{:08x}", type_)
+        }
+
+        if !self.is_valid_texture_region(xoffset, yoffset, width, height) {
+            return;
+        }
+
+        let region = unsafe { self.unpack_image(width as _, height as _, num_components, type_, data) };
+        self.set_texture_region(xoffset as _, yoffset as _, width as _, height as _, &region);
+    }
+
+    fn texture_memory(&self) -> usize {
+        self.textures.values().map(|texture| texture.data.len() * mem::size_of::<u32>()).sum()
+    }
+
+    // Returns the value of a texture environment parameter as queried with glGetTexEnv*
+    fn texture_env_state(&self, target: GLenum, pname: GLenum) -> Option<StateValue> {
+        Some(match (target, pname) {
+            (GL_TEXTURE_ENV, GL_TEXTURE_ENV_MODE) => StateValue::integer(self.texture.texture_env_mode as _),
+            (GL_TEXTURE_ENV, GL_TEXTURE_ENV_COLOR) => StateValue::color(self.texture.texture_env_color),
+            (GL_POINT_SPRITE_ARB, GL_COORD_REPLACE_ARB) => StateValue::boolean(self.point.point_sprite_coord_replace),
+            _ => return None
+        })
+    }
+
+    // Returns the value of a parameter of the bound texture as queried with glGetTexParameter*
+    fn texture_parameter_state(&self, target: GLenum, pname: GLenum) -> Option<StateValue> {
+        if target != GL_TEXTURE_2D {
+            return None;
+        }
+        let texture = &self.textures[&self.texture.texture_2d];
+        let texture_filter = |filter: &TextureFilter| match *filter {
+            TextureFilter::Nearest => GL_NEAREST,
+            TextureFilter::Linear => GL_LINEAR,
+            TextureFilter::LinearMipmapNearest => GL_LINEAR_MIPMAP_NEAREST,
+            TextureFilter::LinearMipmapLinear => GL_LINEAR_MIPMAP_LINEAR,
+        };
+        let wrap_parameter = |wrap: &WrapParameter| match *wrap {
+            WrapParameter::Clamp => GL_CLAMP,
+            WrapParameter::Repeat => GL_REPEAT,
+        };
+        Some(match pname {
+            GL_TEXTURE_MAG_FILTER => StateValue::integer(texture_filter(&texture.mag_filter)),
+            GL_TEXTURE_MIN_FILTER => StateValue::integer(texture_filter(&texture.min_filter)),
+            GL_TEXTURE_WRAP_S => StateValue::integer(wrap_parameter(&texture.wrap_s)),
+            GL_TEXTURE_WRAP_T => StateValue::integer(wrap_parameter(&texture.wrap_t)),
+            GL_TEXTURE_PRIORITY => StateValue::float(texture.priority),
+            GL_TEXTURE_RESIDENT => StateValue::boolean(true),
+            _ => return None
+        })
+    }
+
+    fn tex_coord_pointer(&mut self, size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        match size {
+            1 | 2 | 3 | 4 => (),
+            _ => invalid_call!(self, GL_INVALID_VALUE, "TexCoordPointer called with invalid size: {}", size)
         }
         match type_ {
-            GL_FLOAT => {
-                self.vertex_type = type_;
+            GL_SHORT | GL_INT | GL_FLOAT | GL_DOUBLE => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "TexCoordPointer called with invalid type: 0x{:08x}", type_)
+        }
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "TexCoordPointer called with negative stride: {}", stride as GLint);
+        }
+        self.vertex_arrays.tex_coord_arrays[self.vertex_arrays.client_active_texture].set_pointer(size, type_, stride, pointer);
+    }
+
+    // Reads a width x height 1 bit per pixel bitmap from client memory, honoring the unpack state
+    unsafe fn unpack_bitmap(&self, width: usize, height: usize, bitmap: *const GLubyte) -> Vec<bool> {
+        let row_length = if self.pixel_store.unpack.row_length > 0 { self.pixel_store.unpack.row_length as usize } else { width };
+        let alignment = self.pixel_store.unpack.alignment as usize;
+        let row_stride = alignment * ((row_length + 8 * alignment - 1) / (8 * alignment));
+        let start = bitmap.add(self.pixel_store.unpack.skip_rows as usize * row_stride);
+
+        let mut bits = vec![false; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let bit_index = self.pixel_store.unpack.skip_pixels as usize + x;
+                let byte = *start.add(y * row_stride + bit_index / 8);
+                let mask = if self.pixel_store.unpack.lsb_first != 0 { 0x01 << (bit_index % 8) } else { 0x80 >> (bit_index % 8) };
+                bits[y * width + x] = (byte & mask) != 0;
+            }
+        }
+        bits
+    }
+
+    // Reads a width x height image of RGB/RGBA unsigned byte/short pixels from client memory, honoring the unpack state
+    unsafe fn unpack_image(&self, width: usize, height: usize, num_components: usize, type_: GLenum, data: *const GLvoid) -> Vec<u32> {
+        let element_size = type_size(type_);
+        let (offset, row_stride) = pixel_layout(width, num_components, element_size, self.pixel_store.unpack.row_length, self.pixel_store.unpack.skip_pixels, self.pixel_store.unpack.skip_rows, self.pixel_store.unpack.alignment);
+        let start = (data as *const u8).add(offset);
+
+        let mut texels = vec![0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = start.add(y * row_stride + x * num_components * element_size);
+                let mut components = [0, 0, 0, 255];
+                for (i, component) in components.iter_mut().take(num_components).enumerate() {
+                    *component = match type_ {
+                        GL_UNSIGNED_BYTE => *pixel.add(i) as u32,
+                        GL_UNSIGNED_SHORT => {
+                            let mut value = ptr::read_unaligned((pixel as *const u16).add(i));
+                            if self.pixel_store.unpack.swap_bytes != 0 {
+                                value = value.swap_bytes();
+                            }
+                            value as u32 >> 8
+                        }
+                        _ => unreachable!()
+                    };
+                }
+                let [red, green, blue, alpha] = components;
+                texels[y * width + x] = (alpha << 24) | (red << 16) | (green << 8) | (blue << 0);
             }
-            _ => panic!("VertexPointer called with invalid type: 0x{:08x}", type_)
         }
-        match stride {
-            0 => {
-                self.vertex_stride = stride;
+        texels
+    }
+
+    fn unlock_arrays(&mut self) {
+        if self.locked_arrays.is_none() {
+            self.set_error(GL_INVALID_OPERATION);
+            return;
+        }
+        self.locked_arrays = None;
+    }
+
+    fn vertex_pointer(&mut self, size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        match size {
+            2 | 3 | 4 => (),
+            _ => invalid_call!(self, GL_INVALID_VALUE, "VertexPointer called with invalid size: {}", size)
+        }
+        match type_ {
+            GL_SHORT | GL_INT | GL_FLOAT | GL_DOUBLE => (),
+            _ => invalid_call!(self, GL_INVALID_ENUM, "VertexPointer called with invalid type: 0x{:08x}", type_)
+        }
+        if (stride as GLint) < 0 {
+            invalid_call!(self, GL_INVALID_VALUE, "VertexPointer called with negative stride: {}", stride as GLint);
+        }
+        self.vertex_arrays.vertex_array.set_pointer(size, type_, stride, pointer);
+    }
+
+    // Writes the result of a state query converted to the requested type, or flags an invalid query
+    unsafe fn write_state<T>(&mut self, value: Option<StateValue>, params: *mut T, convert: fn(&StateValue) -> Vec<T>) {
+        match value {
+            Some(value) => {
+                let values = convert(&value);
+                ptr::copy_nonoverlapping(values.as_ptr(), params, values.len());
             }
-            _ => panic!("VertexPointer called with invalid stride: {}", stride)
+            _ => self.set_error(GL_INVALID_ENUM),
         }
-        self.vertex_pointer = pointer;
     }
 }
 
@@ -1324,9 +5292,216 @@ fn context() -> &'static mut Context {
     }
 }
 
+// Fills in missing attribute components from (0, 0, 0, 1)
+fn attribute_components<I: IntoIterator<Item = GLfloat>>(components: I) -> [GLfloat; 4] {
+    let mut ret = [0.0, 0.0, 0.0, 1.0];
+    for (i, c) in components.into_iter().enumerate() {
+        ret[i] = c;
+    }
+    ret
+}
+
+fn color3_command(v: [GLfloat; 4]) -> Command {
+    Command::Color3f { red: v[0], green: v[1], blue: v[2] }
+}
+
+fn color4_command(v: [GLfloat; 4]) -> Command {
+    Command::Color4f { red: v[0], green: v[1], blue: v[2], alpha: v[3] }
+}
+
+fn eval_coord1_command(v: [GLfloat; 4]) -> Command {
+    Command::EvalCoord1f { u: v[0] }
+}
+
+fn eval_coord2_command(v: [GLfloat; 4]) -> Command {
+    Command::EvalCoord2f { u: v[0], v: v[1] }
+}
+
+fn multi_tex_coord_command(target: GLenum, v: [GLfloat; 4]) -> Command {
+    // TODO: r and q are dropped, as texture coordinates are 2D throughout
+    Command::MultiTexCoord2fARB { target, s: v[0], t: v[1] }
+}
+
+fn normal_command(v: [GLfloat; 4]) -> Command {
+    Command::Normal3f { nx: v[0], ny: v[1], nz: v[2] }
+}
+
+fn raster_pos_command(v: [GLfloat; 4]) -> Command {
+    Command::RasterPos4f { x: v[0], y: v[1], z: v[2], w: v[3] }
+}
+
+fn secondary_color_command(v: [GLfloat; 4]) -> Command {
+    Command::SecondaryColor3fEXT { red: v[0], green: v[1], blue: v[2] }
+}
+
+fn tex_coord_command(v: [GLfloat; 4]) -> Command {
+    // TODO: r and q are dropped, as texture coordinates are 2D throughout
+    Command::TexCoord2f { s: v[0], t: v[1] }
+}
+
+fn vertex_command(v: [GLfloat; 4]) -> Command {
+    Command::Vertex4f { x: v[0], y: v[1], z: v[2], w: v[3] }
+}
+
+// Generates the scalar and vector (v suffix) entry points for immediate-mode attributes. Components are converted to
+// floats (normalized for colors and normals) and issued as the attribute's canonical float command.
+macro_rules! attribute_entry_points {
+    ($($(#[$attr:meta])* $vis:vis fn $name:ident/$vname:ident(target, $($component:ident),+: $type:ty) => $command:ident($convert:ident);)+) => {
+        $(
+            $(#[$attr])*
+            $vis extern "stdcall" fn $name(target: GLenum, $($component: $type),+) {
+                context().issue($command(target, attribute_components([$($component.$convert()),+].iter().cloned())));
+            }
+
+            $(#[$attr])*
+            $vis unsafe extern "stdcall" fn $vname(target: GLenum, v: *const $type) {
+                let v = slice::from_raw_parts(v, [$(stringify!($component)),+].len());
+                context().issue($command(target, attribute_components(v.iter().map(|c| c.$convert()))));
+            }
+        )+
+    };
+    ($($(#[$attr:meta])* $vis:vis fn $name:ident/$vname:ident($($component:ident),+: $type:ty) => $command:ident($convert:ident);)+) => {
+        $(
+            $(#[$attr])*
+            $vis extern "stdcall" fn $name($($component: $type),+) {
+                context().issue($command(attribute_components([$($component.$convert()),+].iter().cloned())));
+            }
+
+            $(#[$attr])*
+            $vis unsafe extern "stdcall" fn $vname(v: *const $type) {
+                let v = slice::from_raw_parts(v, [$(stringify!($component)),+].len());
+                context().issue($command(attribute_components(v.iter().map(|c| c.$convert()))));
+            }
+        )+
+    };
+}
+
+attribute_entry_points! {
+    #[no_mangle] pub fn glColor3b/glColor3bv(red, green, blue: GLbyte) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3d/glColor3dv(red, green, blue: GLdouble) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3f/glColor3fv(red, green, blue: GLfloat) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3i/glColor3iv(red, green, blue: GLint) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3s/glColor3sv(red, green, blue: GLshort) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3ub/glColor3ubv(red, green, blue: GLubyte) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3ui/glColor3uiv(red, green, blue: GLuint) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor3us/glColor3usv(red, green, blue: GLushort) => color3_command(to_normalized);
+    #[no_mangle] pub fn glColor4b/glColor4bv(red, green, blue, alpha: GLbyte) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4d/glColor4dv(red, green, blue, alpha: GLdouble) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4f/glColor4fv(red, green, blue, alpha: GLfloat) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4i/glColor4iv(red, green, blue, alpha: GLint) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4s/glColor4sv(red, green, blue, alpha: GLshort) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4ub/glColor4ubv(red, green, blue, alpha: GLubyte) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4ui/glColor4uiv(red, green, blue, alpha: GLuint) => color4_command(to_normalized);
+    #[no_mangle] pub fn glColor4us/glColor4usv(red, green, blue, alpha: GLushort) => color4_command(to_normalized);
+}
+
+attribute_entry_points! {
+    #[no_mangle] pub fn glEvalCoord1d/glEvalCoord1dv(u: GLdouble) => eval_coord1_command(to_float);
+    #[no_mangle] pub fn glEvalCoord1f/glEvalCoord1fv(u: GLfloat) => eval_coord1_command(to_float);
+    #[no_mangle] pub fn glEvalCoord2d/glEvalCoord2dv(u, v: GLdouble) => eval_coord2_command(to_float);
+    #[no_mangle] pub fn glEvalCoord2f/glEvalCoord2fv(u, v: GLfloat) => eval_coord2_command(to_float);
+}
+
+attribute_entry_points! {
+    fn glMultiTexCoord1dARB/glMultiTexCoord1dvARB(target, s: GLdouble) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord1fARB/glMultiTexCoord1fvARB(target, s: GLfloat) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord1iARB/glMultiTexCoord1ivARB(target, s: GLint) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord1sARB/glMultiTexCoord1svARB(target, s: GLshort) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord2dARB/glMultiTexCoord2dvARB(target, s, t: GLdouble) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord2fARB/glMultiTexCoord2fvARB(target, s, t: GLfloat) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord2iARB/glMultiTexCoord2ivARB(target, s, t: GLint) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord2sARB/glMultiTexCoord2svARB(target, s, t: GLshort) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord3dARB/glMultiTexCoord3dvARB(target, s, t, r: GLdouble) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord3fARB/glMultiTexCoord3fvARB(target, s, t, r: GLfloat) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord3iARB/glMultiTexCoord3ivARB(target, s, t, r: GLint) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord3sARB/glMultiTexCoord3svARB(target, s, t, r: GLshort) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord4dARB/glMultiTexCoord4dvARB(target, s, t, r, q: GLdouble) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord4fARB/glMultiTexCoord4fvARB(target, s, t, r, q: GLfloat) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord4iARB/glMultiTexCoord4ivARB(target, s, t, r, q: GLint) => multi_tex_coord_command(to_float);
+    fn glMultiTexCoord4sARB/glMultiTexCoord4svARB(target, s, t, r, q: GLshort) => multi_tex_coord_command(to_float);
+}
+
+attribute_entry_points! {
+    #[no_mangle] pub fn glNormal3b/glNormal3bv(nx, ny, nz: GLbyte) => normal_command(to_normalized);
+    #[no_mangle] pub fn glNormal3d/glNormal3dv(nx, ny, nz: GLdouble) => normal_command(to_normalized);
+    #[no_mangle] pub fn glNormal3f/glNormal3fv(nx, ny, nz: GLfloat) => normal_command(to_normalized);
+    #[no_mangle] pub fn glNormal3i/glNormal3iv(nx, ny, nz: GLint) => normal_command(to_normalized);
+    #[no_mangle] pub fn glNormal3s/glNormal3sv(nx, ny, nz: GLshort) => normal_command(to_normalized);
+}
+
+attribute_entry_points! {
+    #[no_mangle] pub fn glRasterPos2d/glRasterPos2dv(x, y: GLdouble) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos2f/glRasterPos2fv(x, y: GLfloat) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos2i/glRasterPos2iv(x, y: GLint) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos2s/glRasterPos2sv(x, y: GLshort) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos3d/glRasterPos3dv(x, y, z: GLdouble) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos3f/glRasterPos3fv(x, y, z: GLfloat) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos3i/glRasterPos3iv(x, y, z: GLint) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos3s/glRasterPos3sv(x, y, z: GLshort) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos4d/glRasterPos4dv(x, y, z, w: GLdouble) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos4f/glRasterPos4fv(x, y, z, w: GLfloat) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos4i/glRasterPos4iv(x, y, z, w: GLint) => raster_pos_command(to_float);
+    #[no_mangle] pub fn glRasterPos4s/glRasterPos4sv(x, y, z, w: GLshort) => raster_pos_command(to_float);
+}
+
+attribute_entry_points! {
+    fn glSecondaryColor3bEXT/glSecondaryColor3bvEXT(red, green, blue: GLbyte) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3dEXT/glSecondaryColor3dvEXT(red, green, blue: GLdouble) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3fEXT/glSecondaryColor3fvEXT(red, green, blue: GLfloat) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3iEXT/glSecondaryColor3ivEXT(red, green, blue: GLint) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3sEXT/glSecondaryColor3svEXT(red, green, blue: GLshort) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3ubEXT/glSecondaryColor3ubvEXT(red, green, blue: GLubyte) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3uiEXT/glSecondaryColor3uivEXT(red, green, blue: GLuint) => secondary_color_command(to_normalized);
+    fn glSecondaryColor3usEXT/glSecondaryColor3usvEXT(red, green, blue: GLushort) => secondary_color_command(to_normalized);
+}
+
+attribute_entry_points! {
+    #[no_mangle] pub fn glTexCoord1d/glTexCoord1dv(s: GLdouble) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord1f/glTexCoord1fv(s: GLfloat) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord1i/glTexCoord1iv(s: GLint) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord1s/glTexCoord1sv(s: GLshort) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord2d/glTexCoord2dv(s, t: GLdouble) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord2f/glTexCoord2fv(s, t: GLfloat) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord2i/glTexCoord2iv(s, t: GLint) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord2s/glTexCoord2sv(s, t: GLshort) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord3d/glTexCoord3dv(s, t, r: GLdouble) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord3f/glTexCoord3fv(s, t, r: GLfloat) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord3i/glTexCoord3iv(s, t, r: GLint) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord3s/glTexCoord3sv(s, t, r: GLshort) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord4d/glTexCoord4dv(s, t, r, q: GLdouble) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord4f/glTexCoord4fv(s, t, r, q: GLfloat) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord4i/glTexCoord4iv(s, t, r, q: GLint) => tex_coord_command(to_float);
+    #[no_mangle] pub fn glTexCoord4s/glTexCoord4sv(s, t, r, q: GLshort) => tex_coord_command(to_float);
+}
+
+attribute_entry_points! {
+    #[no_mangle] pub fn glVertex2d/glVertex2dv(x, y: GLdouble) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex2f/glVertex2fv(x, y: GLfloat) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex2i/glVertex2iv(x, y: GLint) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex2s/glVertex2sv(x, y: GLshort) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex3d/glVertex3dv(x, y, z: GLdouble) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex3f/glVertex3fv(x, y, z: GLfloat) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex3i/glVertex3iv(x, y, z: GLint) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex3s/glVertex3sv(x, y, z: GLshort) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex4d/glVertex4dv(x, y, z, w: GLdouble) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex4f/glVertex4fv(x, y, z, w: GLfloat) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex4i/glVertex4iv(x, y, z, w: GLint) => vertex_command(to_float);
+    #[no_mangle] pub fn glVertex4s/glVertex4sv(x, y, z, w: GLshort) => vertex_command(to_float);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glActiveTextureARB(texture: GLenum) {
+    context().issue(Command::ActiveTextureARB { texture });
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glAlphaFunc(func: GLenum, ref_: GLclampf) {
+    context().issue(Command::AlphaFunc { func, ref_ });
+}
+
 #[no_mangle]
-pub extern "stdcall" fn glActiveTextureARB(texture: GLenum) {
-    context().issue(Command::ActiveTextureARB { texture });
+pub unsafe extern "stdcall" fn glAreTexturesResident(n: GLsizei, textures: *const GLuint, residences: *mut GLboolean) -> GLboolean {
+    context().are_textures_resident(n, textures, residences)
 }
 
 #[no_mangle]
@@ -1345,8 +5520,8 @@ pub extern "stdcall" fn glBindTexture(target: GLenum, texture: GLuint) {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glBitmap(_width: GLsizei, _height: GLsizei, _xorig: GLfloat, _yorig: GLfloat, _xmove: GLfloat, _ymove: GLfloat, _bitmap: *const GLubyte) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glBitmap(width: GLsizei, height: GLsizei, xorig: GLfloat, yorig: GLfloat, xmove: GLfloat, ymove: GLfloat, bitmap: *const GLubyte) {
+    context().bitmap(width, height, xorig, yorig, xmove, ymove, bitmap);
 }
 
 #[no_mangle]
@@ -1359,6 +5534,11 @@ pub extern "stdcall" fn glCallList(list: GLuint) {
     context().issue(Command::CallList { list });
 }
 
+#[no_mangle]
+pub unsafe extern "stdcall" fn glCallLists(n: GLsizei, type_: GLenum, lists: *const GLvoid) {
+    context().call_lists(n, type_, lists);
+}
+
 #[no_mangle]
 pub extern "stdcall" fn glClear(mask: GLbitfield) {
     context().issue(Command::Clear { mask });
@@ -1375,29 +5555,28 @@ pub extern "stdcall" fn glClearDepth(_depth: GLdouble) {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glClientActiveTextureARB(_texture: GLenum) {
-    unimplemented!()
+pub extern "stdcall" fn glClientActiveTextureARB(texture: GLenum) {
+    context().client_active_texture(texture);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glColor3f(red: GLfloat, green: GLfloat, blue: GLfloat) {
-    context().issue(Command::Color3f { red, green, blue });
+pub extern "stdcall" fn glColorMask(red: GLboolean, green: GLboolean, blue: GLboolean, alpha: GLboolean) {
+    context().issue(Command::ColorMask { red, green, blue, alpha });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glColor4f(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat) {
-    context().issue(Command::Color4f { red, green, blue, alpha });
+pub extern "stdcall" fn glColorPointer(size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+    context().color_pointer(size, type_, stride, pointer);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glColor4fv(_v: *const GLfloat) {
-    unimplemented!()
+pub extern "stdcall" fn glCopyTexImage2D(target: GLenum, level: GLint, internalformat: GLenum, x: GLint, y: GLint, width: GLsizei, height: GLsizei, border: GLint) {
+    context().copy_tex_image_2d(target, level, internalformat, x, y, width, height, border);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glCopyTexSubImage2D(_target: GLenum, _level: GLint, _xoffset: GLint, _yoffset: GLint, _x: GLint, _y: GLint, _width: GLsizei, _height: GLsizei) {
-    // TODO!
-    //unimplemented!()
+pub extern "stdcall" fn glCopyTexSubImage2D(target: GLenum, level: GLint, xoffset: GLint, yoffset: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+    context().copy_tex_sub_image_2d(target, level, xoffset, yoffset, x, y, width, height);
 }
 
 #[no_mangle]
@@ -1406,13 +5585,13 @@ pub extern "stdcall" fn glCullFace(mode: GLenum) {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glDeleteLists(_list: GLuint, _range: GLsizei) {
-    unimplemented!()
+pub extern "stdcall" fn glDeleteLists(list: GLuint, range: GLsizei) {
+    context().delete_lists(list, range);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glDeleteTextures(_n: GLsizei, _textures: *const GLuint) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glDeleteTextures(n: GLsizei, textures: *const GLuint) {
+    context().delete_textures(n, textures);
 }
 
 #[no_mangle]
@@ -1436,8 +5615,8 @@ pub extern "stdcall" fn glDisableClientState(array: GLenum) {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glDrawElements(_mode: GLenum, _count: GLsizei, _type_: GLenum, _indices: *const GLvoid) {
-    unimplemented!()
+pub extern "stdcall" fn glDrawArrays(mode: GLenum, first: GLint, count: GLsizei) {
+    context().draw_arrays(mode, first, count);
 }
 
 #[no_mangle]
@@ -1445,6 +5624,36 @@ pub extern "stdcall" fn glDrawBuffer(_mode: GLenum) {
     unimplemented!()
 }
 
+#[no_mangle]
+pub extern "stdcall" fn glDrawElements(mode: GLenum, count: GLsizei, type_: GLenum, indices: *const GLvoid) {
+    context().draw_elements(mode, count, type_, indices);
+}
+
+#[no_mangle]
+pub unsafe extern "stdcall" fn glDrawPixels(width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, pixels: *const GLvoid) {
+    context().draw_pixels(width, height, format, type_, pixels);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glDrawRangeElements(mode: GLenum, start: GLuint, end: GLuint, count: GLsizei, type_: GLenum, indices: *const GLvoid) {
+    context().draw_range_elements(mode, start, end, count, type_, indices);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glEdgeFlag(flag: GLboolean) {
+    context().issue(Command::EdgeFlag { flag });
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glEdgeFlagPointer(stride: GLsizei, pointer: *const GLvoid) {
+    context().edge_flag_pointer(stride, pointer);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glEdgeFlagv(flag: *const GLubyte) {
+    context().issue(Command::EdgeFlag { flag: unsafe { *flag } as _ });
+}
+
 #[no_mangle]
 pub extern "stdcall" fn glEnable(cap: GLenum) {
     context().issue(Command::Enable { cap });
@@ -1466,28 +5675,23 @@ pub extern "stdcall" fn glEndList() {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glEvalCoord1f(_u: GLfloat) {
-    unimplemented!()
-}
-
-#[no_mangle]
-pub extern "stdcall" fn glEvalCoord2f(_u: GLfloat, _v: GLfloat) {
-    unimplemented!()
+pub extern "stdcall" fn glEvalMesh1(mode: GLenum, i1: GLint, i2: GLint) {
+    context().issue(Command::EvalMesh1 { mode, i1, i2 });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glEvalMesh1(_mode: GLenum, _i1: GLint, _i2: GLint) {
-    unimplemented!()
+pub extern "stdcall" fn glEvalMesh2(mode: GLenum, i1: GLint, i2: GLint, j1: GLint, j2: GLint) {
+    context().issue(Command::EvalMesh2 { mode, i1, i2, j1, j2 });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glEvalMesh2(_mode: GLenum, _i1: GLint, _i2: GLint, _j1: GLint, _j2: GLint) {
-    unimplemented!()
+pub extern "stdcall" fn glEvalPoint1(i: GLint) {
+    context().issue(Command::EvalPoint1 { i });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glEvalPoint2(_i: GLint, _j: GLint) {
-    unimplemented!()
+pub extern "stdcall" fn glEvalPoint2(i: GLint, j: GLint) {
+    context().issue(Command::EvalPoint2 { i, j });
 }
 
 #[no_mangle]
@@ -1511,13 +5715,13 @@ pub extern "stdcall" fn glFogi(_pname: GLenum, _param: GLint) {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glFrontFace(_mode: GLenum) {
-    unimplemented!()
+pub extern "stdcall" fn glFrontFace(mode: GLenum) {
+    context().issue(Command::FrontFace { mode });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glFrustum(_left: GLdouble, _right: GLdouble, _bottom: GLdouble, _top: GLdouble, _zNear: GLdouble, _zFar: GLdouble) {
-    unimplemented!()
+pub extern "stdcall" fn glFrustum(left: GLdouble, right: GLdouble, bottom: GLdouble, top: GLdouble, zNear: GLdouble, zFar: GLdouble) {
+    context().issue(Command::Frustum { left, right, bottom, top, zNear, zFar });
 }
 
 #[no_mangle]
@@ -1530,230 +5734,293 @@ pub unsafe extern "stdcall" fn glGenTextures(n: GLsizei, textures: *mut GLuint)
     context().gen_textures(n, textures);
 }
 
+// GLboolean is widened to 32 bits for passing by value, but arrays of it are still bytes
 #[no_mangle]
-pub extern "stdcall" fn glGetError() -> GLenum {
-    // Since we always just panic on error, we'll never have anything to report
-    GL_NO_ERROR
+pub unsafe extern "stdcall" fn glGetBooleanv(pname: GLenum, params: *mut GLubyte) {
+    let context = context();
+    let value = context.state(pname);
+    context.write_state(value, params, StateValue::to_booleans);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glGetFloatv(_pname: GLenum, _params: *mut GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetDoublev(pname: GLenum, params: *mut GLdouble) {
+    let context = context();
+    let value = context.state(pname);
+    context.write_state(value, params, StateValue::to_doubles);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glGetIntegerv(pname: GLenum, params: *mut GLint) {
-    context().get_integerv(pname, params);
+pub extern "stdcall" fn glGetError() -> GLenum {
+    context().get_error()
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glGetString(_name: GLenum) -> *const GLubyte {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetFloatv(pname: GLenum, params: *mut GLfloat) {
+    let context = context();
+    let value = context.state(pname);
+    context.write_state(value, params, StateValue::to_floats);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLightf(light: GLenum, pname: GLenum, param: GLfloat) {
-    context().issue(Command::Lightf { light, pname, param });
+pub unsafe extern "stdcall" fn glGetIntegerv(pname: GLenum, params: *mut GLint) {
+    let context = context();
+    let value = context.state(pname);
+    context.write_state(value, params, StateValue::to_integers);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLightfv(_light: GLenum, _pname: GLenum, _params: *const GLfloat) {
-    // TODO: Parameter unpacking needs to happen here
-    println!("Lightfv");
+pub unsafe extern "stdcall" fn glGetLightfv(light: GLenum, pname: GLenum, params: *mut GLfloat) {
+    let context = context();
+    let value = context.light_state(light, pname);
+    context.write_state(value, params, StateValue::to_floats);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLightModelfv(_pname: GLenum, _params: *const GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetLightiv(light: GLenum, pname: GLenum, params: *mut GLint) {
+    let context = context();
+    let value = context.light_state(light, pname);
+    context.write_state(value, params, StateValue::to_integers);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLightModeli(_pname: GLenum, _param: GLint) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetMaterialfv(face: GLenum, pname: GLenum, params: *mut GLfloat) {
+    let context = context();
+    let value = context.material_state(face, pname);
+    context.write_state(value, params, StateValue::to_floats);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLineWidth(_width: GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetMaterialiv(face: GLenum, pname: GLenum, params: *mut GLint) {
+    let context = context();
+    let value = context.material_state(face, pname);
+    context.write_state(value, params, StateValue::to_integers);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLoadIdentity() {
-    context().issue(Command::LoadIdentity);
+pub extern "stdcall" fn glGetString(name: GLenum) -> *const GLubyte {
+    context().get_string(name)
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glLoadMatrixf(_m: *const GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetTexEnvfv(target: GLenum, pname: GLenum, params: *mut GLfloat) {
+    let context = context();
+    let value = context.texture_env_state(target, pname);
+    context.write_state(value, params, StateValue::to_floats);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMap1f(_target: GLenum, _u1: GLfloat, _u2: GLfloat, _stride: GLint, _order: GLint, _points: *const GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetTexEnviv(target: GLenum, pname: GLenum, params: *mut GLint) {
+    let context = context();
+    let value = context.texture_env_state(target, pname);
+    context.write_state(value, params, StateValue::to_integers);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMap2f(_target: GLenum, _u1: GLfloat, _u2: GLfloat, _ustride: GLint, _uorder: GLint, _v1: GLfloat, _v2: GLfloat, _vstride: GLint, _vorder: GLint, _points: *const GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetTexParameterfv(target: GLenum, pname: GLenum, params: *mut GLfloat) {
+    let context = context();
+    let value = context.texture_parameter_state(target, pname);
+    context.write_state(value, params, StateValue::to_floats);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMapGrid1f(_un: GLint, _u1: GLfloat, _u2: GLfloat) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glGetTexParameteriv(target: GLenum, pname: GLenum, params: *mut GLint) {
+    let context = context();
+    let value = context.texture_parameter_state(target, pname);
+    context.write_state(value, params, StateValue::to_integers);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMapGrid2d(_un: GLint, _u1: GLdouble, _u2: GLdouble, _vn: GLint, _v1: GLdouble, _v2: GLdouble) {
-    unimplemented!()
+pub extern "stdcall" fn glInterleavedArrays(format: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+    context().interleaved_arrays(format, stride, pointer);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMapGrid2f(_un: GLint, _u1: GLfloat, _u2: GLfloat, _vn: GLint, _v1: GLfloat, _v2: GLfloat) {
-    unimplemented!()
+pub extern "stdcall" fn glIsEnabled(cap: GLenum) -> GLboolean {
+    context().is_enabled(cap)
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMaterialf(_face: GLenum, _pname: GLenum, _param: GLfloat) {
-    unimplemented!()
+pub extern "stdcall" fn glIsList(list: GLuint) -> GLboolean {
+    context().is_list(list)
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMaterialfv(_face: GLenum, _pname: GLenum, _params: *const GLfloat) {
-    // TODO: Parameter unpacking needs to happen here
-    println!("Materialfv");
+pub extern "stdcall" fn glIsTexture(texture: GLuint) -> GLboolean {
+    context().is_texture(texture)
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glMatrixMode(mode: GLenum) {
-    context().issue(Command::MatrixMode { mode });
-}
-
-extern "stdcall" fn glMultiTexCoord1dEXT(_target: GLenum, _s: GLdouble) {
-    unimplemented!()
-}
-
-extern "stdcall" fn glMultiTexCoord1dvARB(_target: GLenum, _v: *const GLfloat) {
-    unimplemented!()
-}
-
-extern "stdcall" fn glMultiTexCoord1fARB(_target: GLenum, _s: GLfloat) {
-    unimplemented!()
-}
-
-extern "stdcall" fn glMultiTexCoord1fvARB(_target: GLenum, _v: *const GLfloat) {
-    unimplemented!()
-}
-
-extern "stdcall" fn glMultiTexCoord1iARB(_target: GLenum, _s: GLint) {
-    unimplemented!()
-}
-
-extern "stdcall" fn glMultiTexCoord1ivARB(_target: GLenum, _v: *const GLint) {
-    unimplemented!()
-}
-
-extern "stdcall" fn glMultiTexCoord1sARB(_target: GLenum, _s: GLshort) {
-    unimplemented!()
+pub extern "stdcall" fn glLightf(light: GLenum, pname: GLenum, param: GLfloat) {
+    context().issue(Command::Lightf { light, pname, param });
 }
 
-extern "stdcall" fn glMultiTexCoord1svARB(_target: GLenum, _v: *const GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glLightfv(light: GLenum, pname: GLenum, params: *const GLfloat) {
+    let context = context();
+    let count = match light_param_count(pname) {
+        Some(count) => count,
+        _ => invalid_call!(context, GL_INVALID_ENUM, "glLightfv called with invalid pname: 0x{:08x}", pname)
+    };
+    let mut values = [0.0; 4];
+    values[..count].copy_from_slice(slice::from_raw_parts(params, count));
+    context.issue(Command::Lightfv { light, pname, params: values });
 }
 
-extern "stdcall" fn glMultiTexCoord2dARB(_target: GLenum, _s: GLdouble, _t: GLdouble) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glLightModelf(pname: GLenum, param: GLfloat) {
+    let context = context();
+    if light_model_param_count(pname) != Some(1) {
+        invalid_call!(context, GL_INVALID_ENUM, "glLightModelf called with invalid pname: 0x{:08x}", pname);
+    }
+    context.issue(Command::LightModelfv { pname, params: [param, 0.0, 0.0, 0.0] });
 }
 
-extern "stdcall" fn glMultiTexCoord2dvARB(_target: GLenum, _v: *const GLdouble) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glLightModelfv(pname: GLenum, params: *const GLfloat) {
+    let context = context();
+    let count = match light_model_param_count(pname) {
+        Some(count) => count,
+        _ => invalid_call!(context, GL_INVALID_ENUM, "glLightModelfv called with invalid pname: 0x{:08x}", pname)
+    };
+    let mut values = [0.0; 4];
+    values[..count].copy_from_slice(slice::from_raw_parts(params, count));
+    context.issue(Command::LightModelfv { pname, params: values });
 }
 
-extern "stdcall" fn glMultiTexCoord2fARB(target: GLenum, s: GLfloat, t: GLfloat) {
-    context().issue(Command::MultiTexCoord2fARB { target, s, t });
+#[no_mangle]
+pub extern "stdcall" fn glLightModeli(pname: GLenum, param: GLint) {
+    let context = context();
+    if light_model_param_count(pname) != Some(1) {
+        invalid_call!(context, GL_INVALID_ENUM, "glLightModeli called with invalid pname: 0x{:08x}", pname);
+    }
+    context.issue(Command::LightModelfv { pname, params: [param as _, 0.0, 0.0, 0.0] });
 }
 
-extern "stdcall" fn glMultiTexCoord2fvARB(_target: GLenum, _v: *const GLfloat) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glLightModeliv(pname: GLenum, params: *const GLint) {
+    let context = context();
+    let count = match light_model_param_count(pname) {
+        Some(count) => count,
+        _ => invalid_call!(context, GL_INVALID_ENUM, "glLightModeliv called with invalid pname: 0x{:08x}", pname)
+    };
+    let mut values = [0.0; 4];
+    for (value, &param) in values.iter_mut().zip(slice::from_raw_parts(params, count)) {
+        // Integer colors are normalized like glColor's
+        *value = if pname == GL_LIGHT_MODEL_AMBIENT { param.to_normalized() } else { param.to_float() };
+    }
+    context.issue(Command::LightModelfv { pname, params: values });
 }
 
-extern "stdcall" fn glMultiTexCoord2iARB(_target: GLenum, _s: GLint, _t: GLint) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glLineStipple(factor: GLint, pattern: GLushort) {
+    context().issue(Command::LineStipple { factor, pattern });
 }
 
-extern "stdcall" fn glMultiTexCoord2ivARB(_target: GLenum, _v: *const GLint) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glLineWidth(width: GLfloat) {
+    context().issue(Command::LineWidth { width });
 }
 
-extern "stdcall" fn glMultiTexCoord2sARB(_target: GLenum, _s: GLshort, _t: GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glListBase(base: GLuint) {
+    context().issue(Command::ListBase { base });
 }
 
-extern "stdcall" fn glMultiTexCoord2svARB(_target: GLenum, _v: *const GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glLoadIdentity() {
+    context().issue(Command::LoadIdentity);
 }
 
-extern "stdcall" fn glMultiTexCoord3dARB(_target: GLenum, _s: GLdouble, _t: GLdouble, _r: GLdouble) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glLoadMatrixd(m: *const GLdouble) {
+    let mut m_copy = [0.0; 16];
+    m_copy.copy_from_slice(unsafe { slice::from_raw_parts(m, 16) });
+    context().issue(Command::LoadMatrixd { m: m_copy });
 }
 
-extern "stdcall" fn glMultiTexCoord3dvARB(_target: GLenum, _v: *const GLdouble) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glLoadMatrixf(m: *const GLfloat) {
+    let mut m_copy = [0.0; 16];
+    m_copy.copy_from_slice(unsafe { slice::from_raw_parts(m, 16) });
+    context().issue(Command::LoadMatrixf { m: m_copy });
 }
 
-extern "stdcall" fn glMultiTexCoord3fARB(_target: GLenum, _s: GLfloat, _t: GLfloat, _r: GLfloat) {
-    unimplemented!()
+extern "stdcall" fn glLoadTransposeMatrixdARB(m: *const GLdouble) {
+    let mut m_copy = [0.0; 16];
+    m_copy.copy_from_slice(unsafe { slice::from_raw_parts(m, 16) });
+    context().issue(Command::LoadTransposeMatrixdARB { m: m_copy });
 }
 
-extern "stdcall" fn glMultiTexCoord3fvARB(_target: GLenum, _v: *const GLfloat) {
-    unimplemented!()
+extern "stdcall" fn glLoadTransposeMatrixfARB(m: *const GLfloat) {
+    let mut m_copy = [0.0; 16];
+    m_copy.copy_from_slice(unsafe { slice::from_raw_parts(m, 16) });
+    context().issue(Command::LoadTransposeMatrixfARB { m: m_copy });
 }
 
-extern "stdcall" fn glMultiTexCoord3iARB(_target: GLenum, _s: GLint, _t: GLint, _r: GLint) {
-    unimplemented!()
+extern "stdcall" fn glLockArraysEXT(first: GLint, count: GLsizei) {
+    context().lock_arrays(first, count);
 }
 
-extern "stdcall" fn glMultiTexCoord3ivARB(_target: GLenum, _v: *const GLint) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glMap1d(target: GLenum, u1: GLdouble, u2: GLdouble, stride: GLint, order: GLint, points: *const GLdouble) {
+    context().map1(target, u1 as _, u2 as _, stride, order, points);
 }
 
-extern "stdcall" fn glMultiTexCoord3sARB(_target: GLenum, _s: GLshort, _t: GLshort, _r: GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glMap1f(target: GLenum, u1: GLfloat, u2: GLfloat, stride: GLint, order: GLint, points: *const GLfloat) {
+    context().map1(target, u1, u2, stride, order, points);
 }
 
-extern "stdcall" fn glMultiTexCoord3svARB(_target: GLenum, _v: *const GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glMap2d(target: GLenum, u1: GLdouble, u2: GLdouble, ustride: GLint, uorder: GLint, v1: GLdouble, v2: GLdouble, vstride: GLint, vorder: GLint, points: *const GLdouble) {
+    context().map2(target, u1 as _, u2 as _, ustride, uorder, v1 as _, v2 as _, vstride, vorder, points);
 }
 
-extern "stdcall" fn glMultiTexCoord4dARB(_target: GLenum, _s: GLdouble, _t: GLdouble, _r: GLdouble, _q: GLdouble) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glMap2f(target: GLenum, u1: GLfloat, u2: GLfloat, ustride: GLint, uorder: GLint, v1: GLfloat, v2: GLfloat, vstride: GLint, vorder: GLint, points: *const GLfloat) {
+    context().map2(target, u1, u2, ustride, uorder, v1, v2, vstride, vorder, points);
 }
 
-extern "stdcall" fn glMultiTexCoord4dvARB(_target: GLenum, _v: *const GLdouble) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glMapGrid1d(un: GLint, u1: GLdouble, u2: GLdouble) {
+    context().issue(Command::MapGrid1f { un, u1: u1 as _, u2: u2 as _ });
 }
 
-extern "stdcall" fn glMultiTexCoord4fARB(_target: GLenum, _s: GLfloat, _t: GLfloat, _r: GLfloat, _q: GLfloat) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glMapGrid1f(un: GLint, u1: GLfloat, u2: GLfloat) {
+    context().issue(Command::MapGrid1f { un, u1, u2 });
 }
 
-extern "stdcall" fn glMultiTexCoord4fvARB(_target: GLenum, _v: *const GLfloat) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glMapGrid2d(un: GLint, u1: GLdouble, u2: GLdouble, vn: GLint, v1: GLdouble, v2: GLdouble) {
+    context().issue(Command::MapGrid2f { un, u1: u1 as _, u2: u2 as _, vn, v1: v1 as _, v2: v2 as _ });
 }
 
-extern "stdcall" fn glMultiTexCoord4iARB(_target: GLenum, _s: GLint, _t: GLint, _r: GLint, _q: GLint) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glMapGrid2f(un: GLint, u1: GLfloat, u2: GLfloat, vn: GLint, v1: GLfloat, v2: GLfloat) {
+    context().issue(Command::MapGrid2f { un, u1, u2, vn, v1, v2 });
 }
 
-extern "stdcall" fn glMultiTexCoord4ivARB(_target: GLenum, _v: *const GLint) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glMaterialf(face: GLenum, pname: GLenum, param: GLfloat) {
+    context().issue(Command::Materialf { face, pname, param });
 }
 
-extern "stdcall" fn glMultiTexCoord4sARB(_target: GLenum, _s: GLshort, _t: GLshort, _r: GLshort, _q: GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub unsafe extern "stdcall" fn glMaterialfv(face: GLenum, pname: GLenum, params: *const GLfloat) {
+    let context = context();
+    let count = match material_param_count(pname) {
+        Some(count) => count,
+        _ => invalid_call!(context, GL_INVALID_ENUM, "glMaterialfv called with invalid pname: 0x{:08x}", pname)
+    };
+    let mut values = [0.0; 4];
+    values[..count].copy_from_slice(slice::from_raw_parts(params, count));
+    context.issue(Command::Materialfv { face, pname, params: values });
 }
 
-extern "stdcall" fn glMultiTexCoord4svARB(_target: GLenum, _v: *const GLshort) {
-    unimplemented!()
+#[no_mangle]
+pub extern "stdcall" fn glMatrixMode(mode: GLenum) {
+    context().issue(Command::MatrixMode { mode });
 }
 
 #[no_mangle]
@@ -1770,21 +6037,21 @@ pub extern "stdcall" fn glMultMatrixf(m: *const GLfloat) {
     context().issue(Command::MultMatrixf { m: m_copy });
 }
 
-#[no_mangle]
-pub extern "stdcall" fn glNewList(list: GLuint, mode: GLenum) {
-    context().new_list(list, mode);
+extern "stdcall" fn glMultTransposeMatrixdARB(m: *const GLdouble) {
+    let mut m_copy = [0.0; 16];
+    m_copy.copy_from_slice(unsafe { slice::from_raw_parts(m, 16) });
+    context().issue(Command::MultTransposeMatrixdARB { m: m_copy });
 }
 
-#[no_mangle]
-pub extern "stdcall" fn glNormal3f(nx: GLfloat, ny: GLfloat, nz: GLfloat) {
-    context().issue(Command::Normal3f { nx, ny, nz });
+extern "stdcall" fn glMultTransposeMatrixfARB(m: *const GLfloat) {
+    let mut m_copy = [0.0; 16];
+    m_copy.copy_from_slice(unsafe { slice::from_raw_parts(m, 16) });
+    context().issue(Command::MultTransposeMatrixfARB { m: m_copy });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glNormal3fv(v: *const GLfloat) {
-    let mut v_copy = [0.0; 3];
-    v_copy.copy_from_slice(unsafe { slice::from_raw_parts(v, 3) });
-    context().issue(Command::Normal3fv { v: v_copy });
+pub extern "stdcall" fn glNewList(list: GLuint, mode: GLenum) {
+    context().new_list(list, mode);
 }
 
 #[no_mangle]
@@ -1802,14 +6069,34 @@ pub extern "stdcall" fn glPixelStorei(pname: GLenum, param: GLint) {
     context().pixel_storei(pname, param);
 }
 
+#[no_mangle]
+pub extern "stdcall" fn glPixelZoom(xfactor: GLfloat, yfactor: GLfloat) {
+    context().issue(Command::PixelZoom { xfactor, yfactor });
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glPointSize(size: GLfloat) {
+    context().issue(Command::PointSize { size });
+}
+
 #[no_mangle]
 pub extern "stdcall" fn glPolygonMode(face: GLenum, mode: GLenum) {
     context().issue(Command::PolygonMode { face, mode });
 }
 
+#[no_mangle]
+pub extern "stdcall" fn glPolygonOffset(factor: GLfloat, units: GLfloat) {
+    context().issue(Command::PolygonOffset { factor, units });
+}
+
 #[no_mangle]
 pub extern "stdcall" fn glPopAttrib() {
-    unimplemented!()
+    context().issue(Command::PopAttrib);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glPopClientAttrib() {
+    context().pop_client_attrib();
 }
 
 #[no_mangle]
@@ -1818,8 +6105,18 @@ pub extern "stdcall" fn glPopMatrix() {
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glPushAttrib(_mask: GLbitfield) {
-    unimplemented!()
+pub unsafe extern "stdcall" fn glPrioritizeTextures(n: GLsizei, textures: *const GLuint, priorities: *const GLclampf) {
+    context().prioritize_textures(n, textures, priorities);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glPushAttrib(mask: GLbitfield) {
+    context().issue(Command::PushAttrib { mask });
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glPushClientAttrib(mask: GLbitfield) {
+    context().push_client_attrib(mask);
 }
 
 #[no_mangle]
@@ -1832,45 +6129,61 @@ pub extern "stdcall" fn glReadBuffer(_mode: GLenum) {
     unimplemented!()
 }
 
+#[no_mangle]
+pub unsafe extern "stdcall" fn glReadPixels(x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, pixels: *mut GLvoid) {
+    context().read_pixels(x, y, width, height, format, type_, pixels);
+}
+
+#[no_mangle]
+pub extern "stdcall" fn glRotated(angle: GLdouble, x: GLdouble, y: GLdouble, z: GLdouble) {
+    context().issue(Command::Rotated { angle, x, y, z });
+}
+
 #[no_mangle]
 pub extern "stdcall" fn glRotatef(angle: GLfloat, x: GLfloat, y: GLfloat, z: GLfloat) {
     context().issue(Command::Rotatef { angle, x, y, z });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glScalef(_x: GLfloat, _y: GLfloat, _z: GLfloat) {
-    unimplemented!()
+pub extern "stdcall" fn glScaled(x: GLdouble, y: GLdouble, z: GLdouble) {
+    context().issue(Command::Scaled { x, y, z });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glShadeModel(mode: GLenum) {
-    context().issue(Command::ShadeModel { mode });
+pub extern "stdcall" fn glScalef(x: GLfloat, y: GLfloat, z: GLfloat) {
+    context().issue(Command::Scalef { x, y, z });
+}
+
+extern "stdcall" fn glSecondaryColorPointerEXT(size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+    context().secondary_color_pointer(size, type_, stride, pointer);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glTexCoord2f(s: GLfloat, t: GLfloat) {
-    context().issue(Command::TexCoord2f { s, t });
+pub extern "stdcall" fn glShadeModel(mode: GLenum) {
+    context().issue(Command::ShadeModel { mode });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glTexCoord2i(_s: GLint, _t: GLint) {
-    unimplemented!()
+pub extern "stdcall" fn glTexCoordPointer(size: GLint, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+    context().tex_coord_pointer(size, type_, stride, pointer);
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glTexCoordPointer(_size: GLint, _type_: GLenum, _stride: GLsizei, _pointer: *const GLvoid) {
-    unimplemented!()
+pub extern "stdcall" fn glTexEnvf(target: GLenum, pname: GLenum, param: GLfloat) {
+    context().issue(Command::TexEnvi { target, pname, param: param as _ });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glTexEnvf(_target: GLenum, _pname: GLenum, _param: GLfloat) {
-    // TODO!
-    //unimplemented!()
+pub unsafe extern "stdcall" fn glTexEnvfv(target: GLenum, pname: GLenum, params: *const GLfloat) {
+    let count = if target == GL_TEXTURE_ENV && pname == GL_TEXTURE_ENV_COLOR { 4 } else { 1 };
+    let mut values = [0.0; 4];
+    values[..count].copy_from_slice(slice::from_raw_parts(params, count));
+    context().issue(Command::TexEnvfv { target, pname, params: values });
 }
 
 #[no_mangle]
-pub extern "stdcall" fn glTexEnvi(_target: GLenum, _pname: GLenum, _param: GLint) {
-    unimplemented!()
+pub extern "stdcall" fn glTexEnvi(target: GLenum, pname: GLenum, param: GLint) {
+    context().issue(Command::TexEnvi { target, pname, param });
 }
 
 #[no_mangle]
@@ -1898,6 +6211,11 @@ pub extern "stdcall" fn glTexImage2D(target: GLenum, level: GLint, internalforma
     context().tex_image_2d(target, level, internalformat, width, height, border, format, type_, data);
 }
 
+#[no_mangle]
+pub extern "stdcall" fn glTexSubImage2D(target: GLenum, level: GLint, xoffset: GLint, yoffset: GLint, width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, data: *const GLvoid) {
+    context().tex_sub_image_2d(target, level, xoffset, yoffset, width, height, format, type_, data);
+}
+
 #[no_mangle]
 pub extern "stdcall" fn glTranslated(x: GLdouble, y: GLdouble, z: GLdouble) {
     context().issue(Command::Translated { x, y, z });
@@ -1908,24 +6226,8 @@ pub extern "stdcall" fn glTranslatef(x: GLfloat, y: GLfloat, z: GLfloat) {
     context().issue(Command::Translatef { x, y, z });
 }
 
-#[no_mangle]
-pub extern "stdcall" fn glVertex2f(_x: GLfloat, _y: GLfloat) {
-    unimplemented!()
-}
-
-#[no_mangle]
-pub extern "stdcall" fn glVertex2i(_x: GLint, _y: GLint) {
-    unimplemented!()
-}
-
-#[no_mangle]
-pub extern "stdcall" fn glVertex3f(x: GLfloat, y: GLfloat, z: GLfloat) {
-    context().issue(Command::Vertex3f { x, y, z });
-}
-
-#[no_mangle]
-pub extern "stdcall" fn glVertex3fv(_v: *const GLfloat) {
-    unimplemented!()
+extern "stdcall" fn glUnlockArraysEXT() {
+    context().unlock_arrays();
 }
 
 #[no_mangle]
@@ -1963,7 +6265,7 @@ pub extern "stdcall" fn wglGetCurrentDC() -> HDC {
 #[no_mangle]
 pub unsafe extern "stdcall" fn wglGetProcAddress(name: LPCSTR) -> PROC {
     match CStr::from_ptr(name as *const i8).to_string_lossy().as_ref() {
-        "glMultiTexCoord1dEXT" => glMultiTexCoord1dEXT as _,
+        "glMultiTexCoord1dARB" | "glMultiTexCoord1dEXT" => glMultiTexCoord1dARB as _,
         "glMultiTexCoord1dvARB" => glMultiTexCoord1dvARB as _,
         "glMultiTexCoord1fARB" => glMultiTexCoord1fARB as _,
         "glMultiTexCoord1fvARB" => glMultiTexCoord1fvARB as _,
@@ -1999,9 +6301,40 @@ pub unsafe extern "stdcall" fn wglGetProcAddress(name: LPCSTR) -> PROC {
             glMultiTexCoord4sARB as _
         }
         "glMultiTexCoord4svARB" => glMultiTexCoord4svARB as _,
+        "glSecondaryColor3bEXT" => glSecondaryColor3bEXT as _,
+        "glSecondaryColor3bvEXT" => glSecondaryColor3bvEXT as _,
+        "glSecondaryColor3dEXT" => glSecondaryColor3dEXT as _,
+        "glSecondaryColor3dvEXT" => glSecondaryColor3dvEXT as _,
+        "glSecondaryColor3fEXT" => glSecondaryColor3fEXT as _,
+        "glSecondaryColor3fvEXT" => glSecondaryColor3fvEXT as _,
+        "glSecondaryColor3iEXT" => glSecondaryColor3iEXT as _,
+        "glSecondaryColor3ivEXT" => glSecondaryColor3ivEXT as _,
+        "glSecondaryColor3sEXT" => glSecondaryColor3sEXT as _,
+        "glSecondaryColor3svEXT" => glSecondaryColor3svEXT as _,
+        "glSecondaryColor3ubEXT" => glSecondaryColor3ubEXT as _,
+        "glSecondaryColor3ubvEXT" => glSecondaryColor3ubvEXT as _,
+        "glSecondaryColor3uiEXT" => glSecondaryColor3uiEXT as _,
+        "glSecondaryColor3uivEXT" => glSecondaryColor3uivEXT as _,
+        "glSecondaryColor3usEXT" => glSecondaryColor3usEXT as _,
+        "glSecondaryColor3usvEXT" => glSecondaryColor3usvEXT as _,
+        "glSecondaryColorPointerEXT" => glSecondaryColorPointerEXT as _,
         "glActiveTextureARB" => glActiveTextureARB as _,
         "glClientActiveTextureARB" => glClientActiveTextureARB as _,
-        name => panic!("wglGetProcAddress called with invalid name: {}", name)
+        "glDrawRangeElements" | "glDrawRangeElementsEXT" => glDrawRangeElements as _,
+        "glLoadTransposeMatrixdARB" => glLoadTransposeMatrixdARB as _,
+        "glLoadTransposeMatrixfARB" => glLoadTransposeMatrixfARB as _,
+        "glMultTransposeMatrixdARB" => glMultTransposeMatrixdARB as _,
+        "glMultTransposeMatrixfARB" => glMultTransposeMatrixfARB as _,
+        "glLockArraysEXT" => glLockArraysEXT as _,
+        "glUnlockArraysEXT" => glUnlockArraysEXT as _,
+        name => {
+            // Apps probe for optional extensions this way, so unknown names aren't fatal outside of strict mode
+            if context().strict {
+                panic!("wglGetProcAddress called with invalid name: {}", name);
+            }
+            println!("wglGetProcAddress called with unknown name: {}", name);
+            ptr::null_mut()
+        }
     }
 }
 
@@ -2019,3 +6352,170 @@ extern "stdcall" fn change_display_settings_ex_a(_lpszDeviceName: LPCSTR, _lpDev
     println!("ChangeDisplaySettingsExA called, ignoring");
     0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x_major_columns(a: (f32, f32), b: (f32, f32)) -> Option<(i32, i32)> {
+        diamond_exit_columns(Vec2::new(a.0, a.1), Vec2::new(b.0, b.1), Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0))
+    }
+
+    #[test]
+    fn diamond_exit_excludes_end_fragment() {
+        // Center to center: the start fragment is produced, the end fragment contains b and isn't
+        assert_eq!(x_major_columns((0.5, 0.5), (3.5, 0.5)), Some((0, 2)));
+        assert_eq!(x_major_columns((0.5, 0.5), (2.6, 0.5)), Some((0, 1)));
+    }
+
+    #[test]
+    fn diamond_exit_on_diamond_corners() {
+        // Endpoints on diamond corners are perturbed towards -x, so the corner at the start belongs to the column
+        // before, and the one at the end to the column it ends in
+        assert_eq!(x_major_columns((1.0, 0.5), (4.0, 0.5)), Some((0, 2)));
+        // Between rows, the perturbation towards -y keeps the line from slipping between the diamonds
+        assert_eq!(x_major_columns((0.5, 1.0), (3.5, 1.0)), Some((0, 2)));
+        // Passing through the corner shared by two diamonds in the last column
+        assert_eq!(x_major_columns((0.5, 0.5), (4.5, 2.5)), Some((0, 3)));
+    }
+
+    #[test]
+    fn diamond_exit_sloped() {
+        assert_eq!(x_major_columns((0.5, 0.5), (4.5, 1.7)), Some((0, 3)));
+        assert_eq!(x_major_columns((0.2, 0.1), (3.2, 1.0)), Some((0, 2)));
+    }
+
+    #[test]
+    fn diamond_exit_negative_major_axis() {
+        // Columns count along the major axis, so x = 3.5 down to x = 0.5 covers u = -3.5 to u = -0.5
+        assert_eq!(diamond_exit_columns(Vec2::new(3.5, 0.5), Vec2::new(0.5, 0.5), Vec2::new(-1.0, 0.0), Vec2::new(0.0, 1.0)), Some((-4, -2)));
+    }
+
+    #[test]
+    fn diamond_exit_inside_single_diamond() {
+        assert_eq!(x_major_columns((0.4, 0.5), (0.6, 0.5)), None);
+    }
+
+    fn assert_vec4_eq(a: Vec4, b: Vec4) {
+        for (i, (&x, &y)) in [a.x(), a.y(), a.z(), a.w()].iter().zip([b.x(), b.y(), b.z(), b.w()].iter()).enumerate() {
+            assert!((x - y).abs() < 1e-5, "mismatch at component {}: {} != {}", i, x, y);
+        }
+    }
+
+    // A flat 4x4 patch over [0, 1]^2 in the z = 0 plane, with every control point scaled by w
+    fn flat_patch(w: GLfloat) -> EvalMap {
+        let mut points = Vec::new();
+        for i in 0..4 {
+            for j in 0..4 {
+                points.push(Vec4::new(i as GLfloat / 3.0, j as GLfloat / 3.0, 0.0, 1.0) * w);
+            }
+        }
+        EvalMap { u1: 0.0, u2: 1.0, uorder: 4, v1: 0.0, v2: 1.0, vorder: 4, points }
+    }
+
+    #[test]
+    fn bernstein_cubic() {
+        let (basis, derivatives) = bernstein(4, 0.25);
+        let expected_basis = [27.0 / 64.0, 27.0 / 64.0, 9.0 / 64.0, 1.0 / 64.0];
+        let expected_derivatives = [-1.6875, 0.5625, 0.9375, 0.1875];
+        for i in 0..4 {
+            assert!((basis[i] - expected_basis[i]).abs() < 1e-6, "basis {}: {}", i, basis[i]);
+            assert!((derivatives[i] - expected_derivatives[i]).abs() < 1e-6, "derivative {}: {}", i, derivatives[i]);
+        }
+    }
+
+    #[test]
+    fn bernstein_constant() {
+        assert_eq!(bernstein(1, 0.7), (vec![1.0], vec![0.0]));
+    }
+
+    #[test]
+    fn evaluate_cubic_bezier() {
+        // Control points (0, 0), (1, 2), (3, 2), (4, 0) over u in [0, 2], so derivatives with respect to u are half of
+        // those with respect to the curve parameter
+        let map = EvalMap {
+            u1: 0.0,
+            u2: 2.0,
+            uorder: 4,
+            v1: 0.0,
+            v2: 1.0,
+            vorder: 1,
+            points: vec![Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(1.0, 2.0, 0.0, 1.0), Vec4::new(3.0, 2.0, 0.0, 1.0), Vec4::new(4.0, 0.0, 0.0, 1.0)],
+        };
+
+        let (value, du, dv) = map.evaluate(1.0, 0.0);
+        assert_vec4_eq(value, Vec4::new(2.0, 1.5, 0.0, 1.0));
+        assert_vec4_eq(du, Vec4::new(2.25, 0.0, 0.0, 0.0));
+        assert_vec4_eq(dv, Vec4::zero());
+
+        let (value, du, _) = map.evaluate(0.5, 0.0);
+        assert_vec4_eq(value, Vec4::new(0.90625, 1.125, 0.0, 1.0));
+        assert_vec4_eq(du, Vec4::new(2.0625, 1.5, 0.0, 0.0));
+
+        // The endpoints are interpolated
+        assert_vec4_eq(map.evaluate(0.0, 0.0).0, Vec4::new(0.0, 0.0, 0.0, 1.0));
+        assert_vec4_eq(map.evaluate(2.0, 0.0).0, Vec4::new(4.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn auto_normal_flat_patch() {
+        for &w in [1.0, 2.0].iter() {
+            let (position, du, dv) = flat_patch(w).evaluate(0.3, 0.7);
+            assert_vec4_eq(position, Vec4::new(0.3, 0.7, 0.0, 1.0) * w);
+            let normal = auto_normal(position, du, dv);
+            assert_vec4_eq(Vec4::new(normal.x(), normal.y(), normal.z(), 0.0), Vec4::new(0.0, 0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn auto_normal_degenerate() {
+        let map = EvalMap { points: vec![Vec4::new(1.0, 2.0, 3.0, 1.0); 16], ..flat_patch(1.0) };
+        let (position, du, dv) = map.evaluate(0.5, 0.5);
+        let normal = auto_normal(position, du, dv);
+        assert_eq!((normal.x(), normal.y(), normal.z()), (0.0, 0.0, 0.0));
+    }
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        assert_vec4_eq(Vec4::new(a.x(), a.y(), a.z(), 0.0), Vec4::new(b.x(), b.y(), b.z(), 0.0));
+    }
+
+    // Light 0 (white specular by default) as a directional light, with a white specular material
+    fn specular_lighting(direction: Vec3, shininess: GLfloat) -> LightingState {
+        let mut lighting = LightingState::new();
+        lighting.lights[0].enabled = true;
+        lighting.lights[0].position = Vec4::new(direction.x(), direction.y(), direction.z(), 0.0);
+        lighting.front_material.specular = Vec4::splat(1.0);
+        lighting.front_material.shininess = shininess;
+        lighting
+    }
+
+    #[test]
+    fn specular_head_on() {
+        let lighting = specular_lighting(Vec3::new(0.0, 0.0, 1.0), 10.0);
+        let eye = Vec4::new(0.0, 0.0, -5.0, 1.0);
+        assert_vec3_eq(lighting.specular(eye, Vec3::new(0.0, 0.0, 1.0)), Vec3::splat(1.0));
+        // Surfaces facing away from the light get no highlight
+        assert_vec3_eq(lighting.specular(eye, Vec3::new(0.0, 0.0, -1.0)), Vec3::zero());
+    }
+
+    #[test]
+    fn specular_half_vector() {
+        // The half vector between the light at 45 degrees and the infinite viewer is 22.5 degrees off the normal
+        let lighting = specular_lighting(Vec3::new(0.0, 1.0, 1.0), 2.0);
+        let expected = (22.5f32).to_radians().cos().powf(2.0);
+        assert_vec3_eq(lighting.specular(Vec4::new(0.0, 0.0, -5.0, 1.0), Vec3::new(0.0, 0.0, 1.0)), Vec3::splat(expected));
+    }
+
+    #[test]
+    fn specular_attenuation_and_disabled_lights() {
+        let mut lighting = specular_lighting(Vec3::zero(), 0.0);
+        lighting.lights[0].position = Vec4::new(0.0, 0.0, 2.0, 1.0);
+        lighting.lights[0].constant_attenuation = 0.0;
+        lighting.lights[0].quadratic_attenuation = 1.0;
+        // The vertex is 4 units from the light
+        assert_vec3_eq(lighting.specular(Vec4::new(0.0, 0.0, -4.0, 2.0), Vec3::new(0.0, 0.0, 1.0)), Vec3::splat(1.0 / 16.0));
+
+        lighting.lights[0].enabled = false;
+        assert_vec3_eq(lighting.specular(Vec4::new(0.0, 0.0, -4.0, 2.0), Vec3::new(0.0, 0.0, 1.0)), Vec3::zero());
+    }
+}
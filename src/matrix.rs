@@ -28,6 +28,11 @@ impl Matrix {
         ret
     }
 
+    // Column-major, as returned by the matrix queries
+    pub fn to_floats(&self) -> [f32; NUM_VALUES] {
+        self.values
+    }
+
     pub fn identity() -> Matrix {
         Matrix {
             values: [
@@ -48,6 +53,52 @@ impl Matrix {
         }
     }
 
+    pub fn rotation(angle: f32, x: f32, y: f32, z: f32) -> Matrix {
+        let len = (x * x + y * y + z * z).sqrt();
+        if len == 0.0 {
+            return Matrix::identity();
+        }
+        let x = x / len;
+        let y = y / len;
+        let z = z / len;
+
+        let (s, c) = angle.to_radians().sin_cos();
+        let one_minus_c = 1.0 - c;
+
+        Matrix {
+            values: [
+                x * x * one_minus_c + c, y * x * one_minus_c + z * s, x * z * one_minus_c - y * s, 0.0,
+                x * y * one_minus_c - z * s, y * y * one_minus_c + c, y * z * one_minus_c + x * s, 0.0,
+                x * z * one_minus_c + y * s, y * z * one_minus_c - x * s, z * z * one_minus_c + c, 0.0,
+                0.0, 0.0, 0.0, 1.0]
+        }
+    }
+
+    pub fn scale(x: f32, y: f32, z: f32) -> Matrix {
+        Matrix {
+            values: [
+                x, 0.0, 0.0, 0.0,
+                0.0, y, 0.0, 0.0,
+                0.0, 0.0, z, 0.0,
+                0.0, 0.0, 0.0, 1.0]
+        }
+    }
+
+    pub fn frustum(left: f32, right: f32, bottom: f32, top: f32, z_near: f32, z_far: f32) -> Matrix {
+        let a = (right + left) / (right - left);
+        let b = (top + bottom) / (top - bottom);
+        let c = -(z_far + z_near) / (z_far - z_near);
+        let d = -(2.0 * z_far * z_near) / (z_far - z_near);
+
+        Matrix {
+            values: [
+                2.0 * z_near / (right - left), 0.0, 0.0, 0.0,
+                0.0, 2.0 * z_near / (top - bottom), 0.0, 0.0,
+                a, b, c, -1.0,
+                0.0, 0.0, d, 0.0]
+        }
+    }
+
     pub fn ortho(left: f32, right: f32, bottom: f32, top: f32, z_near: f32, z_far: f32) -> Matrix {
         let tx = -(right + left) / (right - left);
         let ty = -(top + bottom) / (top - bottom);
@@ -61,6 +112,37 @@ impl Matrix {
                 tx, ty, tz, 1.0]
         }
     }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut ret = Matrix {
+            values: [0.0; NUM_VALUES],
+        };
+        for row in 0..NUM_ROWS {
+            for col in 0..NUM_COLS {
+                ret.values[col * NUM_ROWS + row] = self.values[row * NUM_COLS + col];
+            }
+        }
+        ret
+    }
+
+    // The inverse transpose of the upper-left 3x3 part, which transforms normals into eye coordinates. Singular
+    // matrices fall back to the unscaled cofactor matrix, which still gives usable normal directions.
+    pub fn normal_matrix(&self) -> Matrix {
+        let a = |row: usize, col: usize| self.values[col * NUM_ROWS + row];
+        let cofactor = |row: usize, col: usize| {
+            let (row1, row2, col1, col2) = ((row + 1) % 3, (row + 2) % 3, (col + 1) % 3, (col + 2) % 3);
+            a(row1, col1) * a(row2, col2) - a(row1, col2) * a(row2, col1)
+        };
+        let det: f32 = (0..3).map(|col| a(0, col) * cofactor(0, col)).sum();
+        let scale = if det != 0.0 { 1.0 / det } else { 1.0 };
+        let mut ret = Matrix::identity();
+        for row in 0..3 {
+            for col in 0..3 {
+                ret.values[col * NUM_ROWS + row] = cofactor(row, col) * scale;
+            }
+        }
+        ret
+    }
 }
 
 impl Mul<Matrix> for Matrix {
@@ -126,3 +208,103 @@ impl Mul<Vec4> for Matrix {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference matrices are written row-major as they appear in the GL 1.2 spec, and transposed into GL's column-major storage
+    fn from_rows(rows: [f32; NUM_VALUES]) -> Matrix {
+        Matrix::from_floats(&rows).transpose()
+    }
+
+    fn assert_matrix_eq(a: Matrix, b: Matrix) {
+        for i in 0..NUM_VALUES {
+            assert!((a.values[i] - b.values[i]).abs() < 1e-5, "mismatch at index {}: {} != {}", i, a.values[i], b.values[i]);
+        }
+    }
+
+    #[test]
+    fn rotation_about_z() {
+        assert_matrix_eq(Matrix::rotation(90.0, 0.0, 0.0, 1.0), from_rows([
+            0.0, -1.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn rotation_normalizes_axis() {
+        assert_matrix_eq(Matrix::rotation(90.0, 2.0, 0.0, 0.0), from_rows([
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, -1.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn rotation_about_arbitrary_axis() {
+        // Rotating 120 degrees about (1, 1, 1) cycles the basis vectors
+        assert_matrix_eq(Matrix::rotation(120.0, 1.0, 1.0, 1.0), from_rows([
+            0.0, 0.0, 1.0, 0.0,
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn scale() {
+        assert_matrix_eq(Matrix::scale(2.0, 3.0, 4.0), from_rows([
+            2.0, 0.0, 0.0, 0.0,
+            0.0, 3.0, 0.0, 0.0,
+            0.0, 0.0, 4.0, 0.0,
+            0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn frustum() {
+        let (l, r, b, t, n, f) = (-1.0, 3.0, -2.0, 2.0, 1.0, 10.0);
+        assert_matrix_eq(Matrix::frustum(l, r, b, t, n, f), from_rows([
+            2.0 * n / (r - l), 0.0, (r + l) / (r - l), 0.0,
+            0.0, 2.0 * n / (t - b), (t + b) / (t - b), 0.0,
+            0.0, 0.0, -(f + n) / (f - n), -(2.0 * f * n) / (f - n),
+            0.0, 0.0, -1.0, 0.0]));
+    }
+
+    #[test]
+    fn ortho() {
+        let (l, r, b, t, n, f) = (0.0, 320.0, 0.0, 240.0, -1.0, 1.0);
+        assert_matrix_eq(Matrix::ortho(l, r, b, t, n, f), from_rows([
+            2.0 / (r - l), 0.0, 0.0, -(r + l) / (r - l),
+            0.0, 2.0 / (t - b), 0.0, -(t + b) / (t - b),
+            0.0, 0.0, -2.0 / (f - n), -(f + n) / (f - n),
+            0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn translation() {
+        assert_matrix_eq(Matrix::translation(1.0, 2.0, 3.0), from_rows([
+            1.0, 0.0, 0.0, 1.0,
+            0.0, 1.0, 0.0, 2.0,
+            0.0, 0.0, 1.0, 3.0,
+            0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn transpose_round_trip() {
+        let m = Matrix::frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 100.0) * Matrix::rotation(30.0, 0.0, 1.0, 0.0);
+        assert_matrix_eq(m.transpose().transpose(), m);
+    }
+
+    #[test]
+    fn normal_matrix_inverts_scale_and_drops_translation() {
+        let m = Matrix::translation(5.0, 6.0, 7.0) * Matrix::scale(2.0, 4.0, 8.0);
+        assert_matrix_eq(m.normal_matrix(), Matrix::scale(0.5, 0.25, 0.125));
+    }
+
+    #[test]
+    fn normal_matrix_of_rotation() {
+        let m = Matrix::rotation(30.0, 1.0, 2.0, 3.0);
+        assert_matrix_eq(m.normal_matrix(), m);
+    }
+}
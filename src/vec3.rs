@@ -57,6 +57,13 @@ impl Vec3 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x)
+    }
+
     pub fn min(self, other: Vec3) -> Vec3 {
         Vec3 {
             x: self.x.min(other.x),
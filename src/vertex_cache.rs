@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy)]
+pub enum VertexCachePolicy {
+    Fifo,
+    Lru,
+}
+
+// Post-transform vertex cache keyed by vertex index. Entries are kept in eviction order, so the front entry is
+// always the next one to go. Lookups are linear, as the cache is meant to model a small hardware-sized buffer.
+pub struct VertexCache<T> {
+    policy: VertexCachePolicy,
+    capacity: usize,
+    entries: VecDeque<(u32, T)>,
+
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl<T: Copy> VertexCache<T> {
+    pub fn new(policy: VertexCachePolicy, capacity: usize) -> VertexCache<T> {
+        VertexCache {
+            policy,
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn lookup(&mut self, index: u32) -> Option<T> {
+        match self.entries.iter().position(|&(entry_index, _)| entry_index == index) {
+            Some(position) => {
+                self.hits += 1;
+                let entry = self.entries[position];
+                if let VertexCachePolicy::Lru = self.policy {
+                    self.entries.remove(position);
+                    self.entries.push_back(entry);
+                }
+                Some(entry.1)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, index: u32, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((index, value));
+    }
+
+    pub fn invalidate(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fills a cache with indices 0..3, then touches index 0 so LRU and FIFO disagree on the next eviction
+    fn filled_and_touched(policy: VertexCachePolicy) -> VertexCache<u32> {
+        let mut cache = VertexCache::new(policy, 3);
+        for index in 0..3 {
+            cache.insert(index, index * 10);
+        }
+        assert_eq!(cache.lookup(0), Some(0));
+        cache.insert(3, 30);
+        cache
+    }
+
+    #[test]
+    fn fifo_evicts_oldest_insertion() {
+        let mut cache = filled_and_touched(VertexCachePolicy::Fifo);
+        assert_eq!(cache.lookup(0), None);
+        assert_eq!(cache.lookup(1), Some(10));
+        assert_eq!(cache.lookup(2), Some(20));
+        assert_eq!(cache.lookup(3), Some(30));
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut cache = filled_and_touched(VertexCachePolicy::Lru);
+        assert_eq!(cache.lookup(1), None);
+        assert_eq!(cache.lookup(0), Some(0));
+        assert_eq!(cache.lookup(2), Some(20));
+        assert_eq!(cache.lookup(3), Some(30));
+    }
+
+    #[test]
+    fn zero_capacity_never_hits() {
+        let mut cache = VertexCache::new(VertexCachePolicy::Lru, 0);
+        cache.insert(0, 1.0f32);
+        assert_eq!(cache.lookup(0), None);
+        assert_eq!((cache.hits, cache.misses), (0, 1));
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = VertexCache::new(VertexCachePolicy::Fifo, 2);
+        assert_eq!(cache.lookup(5), None);
+        cache.insert(5, 'a');
+        assert_eq!(cache.lookup(5), Some('a'));
+        assert_eq!(cache.lookup(5), Some('a'));
+        assert_eq!(cache.lookup(6), None);
+        assert_eq!((cache.hits, cache.misses), (2, 2));
+
+        cache.reset_stats();
+        assert_eq!((cache.hits, cache.misses), (0, 0));
+        assert_eq!(cache.lookup(5), Some('a'));
+    }
+
+    #[test]
+    fn invalidate_drops_entries_but_keeps_stats() {
+        let mut cache = VertexCache::new(VertexCachePolicy::Fifo, 2);
+        cache.insert(1, 10);
+        assert_eq!(cache.lookup(1), Some(10));
+        cache.invalidate();
+        assert_eq!(cache.lookup(1), None);
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+
+        // The freed slots are usable again
+        cache.insert(2, 20);
+        cache.insert(3, 30);
+        assert_eq!(cache.lookup(2), Some(20));
+        assert_eq!(cache.lookup(3), Some(30));
+    }
+}